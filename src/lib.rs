@@ -0,0 +1,50 @@
+//! FastQC compatible quality control of sequencing reads.
+//!
+//! The QC can run in process on reads already in memory: create the
+//! modules, feed them every read and calculate the reports.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use trust_seq::qc::{self, BasicStatsReport};
+//! use trust_seq::{FastQReader, TrustSeqConfig};
+//!
+//! let config = Arc::new(TrustSeqConfig::new());
+//! let mut modules = qc::create_qcmodules(&config, "reads.fq");
+//! let mut reader = FastQReader::new(&b"@r1\nACGT\n+\nIIII\n@r2\nACGA\n+\nIIII\n"[..]);
+//! while let Some(seq) = reader.next_seq().unwrap() {
+//!     qc::process_sequence(&mut modules, &seq);
+//! }
+//! let reports = qc::calculate_reports(&modules).unwrap();
+//! let basic_stats = qc::get_report::<BasicStatsReport>(&reports).unwrap();
+//! assert_eq!(2, basic_stats.total_sequence);
+//!
+//! let mut text = Vec::new();
+//! qc::write_text_reports(&reports, &mut text).unwrap();
+//! ```
+//!
+//! Checks of your own implement `QCModule` and `QCReport`. Registered with
+//! `TrustSeqConfig::register_module`, as a function or as a closure with
+//! state of its own, they are created by `create_qcmodules` and reported
+//! with the built-in modules, reading their thresholds from `ModuleConfig`
+//! as those do.
+
+#[macro_use]
+extern crate serde_derive;
+
+extern crate serde_json;
+
+extern crate getopts;
+
+extern crate flate2;
+
+mod trust_seq;
+
+pub use crate::trust_seq::{archive, binning, compression, fasta, group, html_report};
+pub use crate::trust_seq::{module_config, parallel, qc, range_coder, sam, utils, validate};
+
+pub use crate::trust_seq::html_report::write_html_report;
+pub use crate::trust_seq::qc::{calculate_reports, create_qcmodules, process_sequence};
+pub use crate::trust_seq::qc::{get_json_reports, write_summary, write_text_reports};
+pub use crate::trust_seq::qc::{CustomModules, QCModule, QCModuleFactory, QCReport, QCResult};
+pub use crate::trust_seq::trust_seq::{Command, TrustSeqConfig, TrustSeqErr};
+pub use crate::trust_seq::utils::{FastQReader, FileFormat, Sequence, SequenceReader};
@@ -1,65 +1,460 @@
-#[macro_use]
-extern crate serde_derive;
-
-extern crate serde_json;
-
-extern crate getopts;
-
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use trust_seq::archive;
+use trust_seq::archive::ArchiveReader;
+use trust_seq::binning::QualityBinner;
+use trust_seq::compression;
+use trust_seq::fasta::FastaReader;
+use trust_seq::html_report;
+use trust_seq::parallel;
 use trust_seq::qc;
-use trust_seq::trust_seq::TrustSeqConfig;
-use trust_seq::utils::FastQReader;
-mod trust_seq;
+use trust_seq::qc::{QCReport, QCResult};
+use trust_seq::sam::{BamReader, SamReader};
+use trust_seq::utils::{get_sample_name, is_stdin};
+use trust_seq::utils::{FastQReader, FileFormat, PairReader, SequenceReader};
+use trust_seq::validate;
+use trust_seq::{Command, TrustSeqConfig, TrustSeqErr};
+
+fn get_file_name(path: &str) -> String {
+    if is_stdin(path) {
+        return "stdin".to_string();
+    }
+    return match Path::new(path).file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => path.to_string(),
+    };
+}
+
+/// Opens a file, or the standard input for `-`, decompressed. Nothing
+/// needs to be seekable, so pipes work too.
+fn open_input(config: &TrustSeqConfig, path: &str) -> Result<Box<Read>, TrustSeqErr> {
+    let source: Box<Read> = if is_stdin(path) {
+        Box::new(io::stdin())
+    } else {
+        Box::new(File::open(path)?)
+    };
+    let read = match config.compression {
+        Some(c) => compression::open_as(source, c),
+        None => compression::open_decompressed(source)?.1,
+    };
+    return Ok(read);
+}
+
+fn get_format(config: &TrustSeqConfig, path: &str) -> FileFormat {
+    return config.format.unwrap_or_else(|| FileFormat::from_path(path));
+}
+
+fn open_reader(config: &TrustSeqConfig, path: &str) -> Result<Box<SequenceReader>, TrustSeqErr> {
+    let read = open_input(config, path)?;
+    let reader: Box<SequenceReader> = match get_format(config, path) {
+        FileFormat::Fastq => {
+            let mut reader = FastQReader::new(read);
+            reader.set_casava_filter(config.filter_casava());
+            Box::new(reader)
+        }
+        FileFormat::Fasta => Box::new(FastaReader::new(read)),
+        FileFormat::Sam => {
+            let mut reader = SamReader::new(read);
+            reader.set_mapped_only(config.mapped_only);
+            Box::new(reader)
+        }
+        FileFormat::Bam => {
+            let mut reader = BamReader::new(read)?;
+            reader.set_mapped_only(config.mapped_only);
+            Box::new(reader)
+        }
+    };
+    return Ok(reader);
+}
+
+/// Checks `path` without running the QC and prints every problem found.
+/// Returns whether the file is well formed.
+fn validate_file(config: &TrustSeqConfig, path: &str) -> Result<bool, TrustSeqErr> {
+    let (record_count, problems): (u64, Vec<String>) = match get_format(config, path) {
+        FileFormat::Fastq => {
+            let (count, errors) = validate::validate_fastq(open_input(config, path)?)?;
+            (count, errors.iter().map(|e| e.to_string()).collect())
+        }
+        // The other readers cannot go on after an error: only the first
+        // one is reported.
+        _ => {
+            let mut reader = open_reader(config, path)?;
+            let mut count = 0;
+            let mut problems = Vec::new();
+            loop {
+                match reader.next_seq() {
+                    Ok(Some(_)) => count += 1,
+                    Ok(None) => break,
+                    Err(e) => {
+                        problems.push(e.to_string());
+                        break;
+                    }
+                }
+            }
+            (count, problems)
+        }
+    };
+    for problem in &problems {
+        println!("{}: {}", path, problem);
+    }
+    if problems.is_empty() {
+        println!("{}: OK, {} records", path, record_count);
+    }
+    return Ok(problems.is_empty());
+}
+
+/// The file `compress` or `decompress` writes when none is given: `.tsq`
+/// added to or taken from the input name, or None for the standard output.
+fn get_default_output(command: Command, path: &str) -> Option<String> {
+    if is_stdin(path) {
+        return None;
+    }
+    return match command {
+        Command::Decompress if path.ends_with(".tsq") => Some(path[..(path.len() - 4)].to_string()),
+        Command::Decompress | Command::View => None,
+        Command::Bin => {
+            let path = path.trim_end_matches(".gz");
+            let stem = match path.rfind('.') {
+                Some(i) if !path[i..].contains('/') => &path[..i],
+                _ => path,
+            };
+            Some(format!("{}.binned.fq", stem))
+        }
+        _ => Some(format!("{}.tsq", path.trim_end_matches(".gz"))),
+    };
+}
+
+/// Writes the records `view` asks for: a region, the reads of some names
+/// or all of them. Without `view`, all of them on more than one thread.
+/// A region past the last record, or a name no read has, is an error.
+fn view_archive<W: Write>(config: &TrustSeqConfig, path: &str, write: &mut W) -> io::Result<u64> {
+    let mut reader = ArchiveReader::open(BufReader::new(File::open(path)?))?;
+    if config.read_names.is_empty() {
+        let record_count = reader.get_record_count();
+        let records = config.region.clone().unwrap_or(0..record_count);
+        if config.region.is_some() && records.start >= record_count {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has only {} records", path, record_count),
+            ));
+        }
+        return reader.view(records, config.threads, write);
+    }
+    let mut count = 0;
+    let mut missing = Vec::new();
+    for name in &config.read_names {
+        let found = reader.find_name(name.as_bytes(), config.threads, write)?;
+        if found.is_empty() {
+            missing.push(name.as_str());
+        }
+        count += found.len() as u64;
+    }
+    if !missing.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} has no read named {}", path, missing.join(", ")),
+        ));
+    }
+    return Ok(count);
+}
+
+/// Runs `compress`, `decompress`, `bin` or `view`. A partly written output
+/// file is removed. When quality scores are binned, the QC impact report
+/// goes to the standard output, or to the standard error if the data does.
+fn run_codec(config: &Arc<TrustSeqConfig>) -> Result<(), TrustSeqErr> {
+    let path = &config.files[0];
+    let is_fastq = || get_format(config, path) == FileFormat::Fastq;
+    match config.command {
+        Command::Compress | Command::Bin if !is_fastq() => {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("only FASTQ files can be compressed or binned"),
+            )));
+        }
+        Command::View if is_stdin(path) => {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("view reads the block index, so needs an archive file"),
+            )));
+        }
+        _ => {}
+    }
+    let out_path = match config.files.get(1) {
+        Some(p) if p == "-" => None,
+        Some(p) => Some(p.clone()),
+        None => get_default_output(config.command, path),
+    };
+    let mut write: BufWriter<Box<Write>> = BufWriter::new(match out_path {
+        Some(ref p) => Box::new(File::create(p)?),
+        None => Box::new(io::stdout()),
+    });
+    let mut binner = None;
+    let rslt = match (config.command, &config.quality_bins) {
+        (Command::View, _) => view_archive(config, path, &mut write),
+        (Command::Decompress, _) if config.threads > 1 && !is_stdin(path) => {
+            view_archive(config, path, &mut write)
+        }
+        (Command::Decompress, _) => {
+            let read: Box<Read> = if is_stdin(path) {
+                Box::new(io::stdin())
+            } else {
+                Box::new(File::open(path)?)
+            };
+            archive::decompress(read, &mut write)
+        }
+        (_, &Some(ref bins)) => {
+            let b = binner.get_or_insert(QualityBinner::new(
+                open_input(config, path)?,
+                bins.clone(),
+                config,
+            ));
+            if config.command == Command::Compress {
+                archive::compress_blocks(b, &mut write, config.block_size)
+            } else {
+                io::copy(b, &mut write)
+            }
+        }
+        _ => archive::compress_blocks(open_input(config, path)?, &mut write, config.block_size),
+    };
+    let rslt = rslt.and_then(|_| write.flush());
+    if rslt.is_err() {
+        if let Some(ref p) = out_path {
+            drop(write);
+            let _ = fs::remove_file(p);
+        }
+    }
+    rslt?;
+    if let Some(b) = binner {
+        let impact = b.get_impact()?;
+        if out_path.is_some() {
+            impact.print_text_report(&mut io::stdout())?;
+        } else {
+            impact.print_text_report(&mut io::stderr())?;
+        }
+    }
+    return Ok(());
+}
+
+/// Writes the report files into `<output_dir>/<out_name>_fastqc`.
+fn write_reports(
+    config: &TrustSeqConfig,
+    out_name: &str,
+    file_name: &str,
+    reports: &Vec<Box<QCReport>>,
+) -> Result<(), TrustSeqErr> {
+    let out_dir = Path::new(&config.output_dir).join(format!("{}_fastqc", out_name));
+    fs::create_dir_all(&out_dir)?;
+
+    let mut data_file = File::create(out_dir.join("fastqc_data.txt"))?;
+    qc::write_text_reports(reports, &mut data_file)?;
+
+    let mut summary_file = File::create(out_dir.join("summary.txt"))?;
+    qc::write_summary(reports, file_name, &mut summary_file)?;
+
+    let mut html_file = File::create(out_dir.join("fastqc_report.html"))?;
+    html_report::write_html_report(reports, file_name, &mut html_file)?;
+
+    let json_report = qc::get_json_reports(reports)?;
+    let mut json_file = File::create(out_dir.join("fastqc_data.json"))?;
+    writeln!(json_file, "{}", serde_json::to_string_pretty(&json_report)?)?;
+    return Ok(());
+}
+
+/// The names of the report directories an input is written to, with the
+/// file each one is for: one per file, and with --paired one per mate and
+/// one for the pairs.
+fn get_out_names<'a>(
+    config: &TrustSeqConfig,
+    path: &'a str,
+    r2_path: Option<&'a str>,
+) -> Vec<(String, &'a str)> {
+    let sample_name = get_sample_name(path);
+    if !config.paired {
+        return vec![(sample_name, path)];
+    }
+    let (r1_out, r2_out) = match r2_path {
+        Some(p) => ((sample_name.clone(), path), (get_sample_name(p), p)),
+        None => (
+            (format!("{}_1", sample_name), path),
+            (format!("{}_2", sample_name), path),
+        ),
+    };
+    return vec![r1_out, r2_out, (format!("{}_paired", sample_name), path)];
+}
+
+/// Fails when two inputs would write their reports to the same directory,
+/// as `a/x.fq` and `b/x.fq` would to `x_fastqc`.
+fn check_out_names(
+    config: &TrustSeqConfig,
+    inputs: &Vec<(&str, Option<&str>)>,
+) -> Result<(), TrustSeqErr> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    for &(path, r2_path) in inputs {
+        for (name, file) in get_out_names(config, path, r2_path) {
+            if let Some(other) = seen.insert(name.clone(), file) {
+                return Err(TrustSeqErr::Io(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} and {} would both write their reports to {}_fastqc",
+                        other, file, name
+                    ),
+                )));
+            }
+        }
+    }
+    return Ok(());
+}
+
+/// Returns the worst status of the modules selected for --exit_status.
+fn process_file(config: &Arc<TrustSeqConfig>, path: &str) -> Result<QCResult, TrustSeqErr> {
+    let mut reader = open_reader(config, path)?;
+    let file_name = get_file_name(path);
+    let modules = if config.threads > 1 {
+        parallel::process_sequences(config, &file_name, &mut reader, config.threads)?
+    } else {
+        let mut modules = qc::create_qcmodules(config, &file_name);
+        while let Some(seq) = reader.next_seq()? {
+            qc::process_sequence(&mut modules, &seq);
+        }
+        modules
+    };
+    let reports = qc::calculate_reports(&modules)?;
+    write_reports(config, &get_sample_name(path), &file_name, &reports)?;
+    return Ok(qc::get_worst_status(&reports, &config.exit_modules));
+}
+
+/// Runs the per file modules on both mates and the pair modules on the
+/// pairs. `r2_path` is None for an interleaved file.
+fn process_pair(
+    config: &Arc<TrustSeqConfig>,
+    r1_path: &str,
+    r2_path: Option<&str>,
+) -> Result<QCResult, TrustSeqErr> {
+    let r1_name = get_file_name(r1_path);
+    let r2_name = r2_path.map_or(r1_name.clone(), |p| get_file_name(p));
+    let r1_file = open_reader(config, r1_path)?;
+    let mut reader = match r2_path {
+        Some(path) => {
+            let r2_file = open_reader(config, path)?;
+            PairReader::new(r1_file, &r1_name, r2_file, &r2_name)
+        }
+        None => PairReader::new_interleaved(r1_file, &r1_name),
+    };
+    reader.set_allow_mismatches(config.mismatched_pairs);
+    let mut r1_modules = qc::create_qcmodules(config, &r1_name);
+    let mut r2_modules = qc::create_qcmodules(config, &r2_name);
+    let mut pair_modules = qc::create_pair_qcmodules(config);
+    while let Some((r1, r2)) = reader.next_pair()? {
+        qc::process_sequence(&mut r1_modules, &r1);
+        qc::process_sequence(&mut r2_modules, &r2);
+        for module in &mut pair_modules {
+            module.process_pair(&r1, &r2);
+        }
+    }
+    let out_names = get_out_names(config, r1_path, r2_path);
+    let pair_name = match r2_path {
+        Some(_) => format!("{} {}", r1_name, r2_name),
+        None => r1_name.clone(),
+    };
+    let mut reports = qc::calculate_reports(&r1_modules)?;
+    write_reports(config, &out_names[0].0, &r1_name, &reports)?;
+    let r2_reports = qc::calculate_reports(&r2_modules)?;
+    write_reports(config, &out_names[1].0, &r2_name, &r2_reports)?;
+    let pair_reports = qc::calculate_pair_reports(&pair_modules)?;
+    write_reports(config, &out_names[2].0, &pair_name, &pair_reports)?;
+    reports.extend(r2_reports);
+    reports.extend(pair_reports);
+    return Ok(qc::get_worst_status(&reports, &config.exit_modules));
+}
 
 fn main() {
-    let mut report = File::create("test_data.txt").unwrap();
     let rslt = TrustSeqConfig::get_fastqc_config(&env::args().collect());
-    let config: TrustSeqConfig;
+    let config: Arc<TrustSeqConfig>;
     match rslt {
         Ok(c) => {
-            config = c;
+            config = Arc::new(c);
         }
         Err(e) => {
             println!("Error:{:?}", e);
-            return;
-        }
-    }
-    let file = File::open(&config.files[0]).unwrap();
-    {
-        let mut fastq_file = FastQReader::new(file);
-        let mut modules = qc::create_qcmodules(&config);
-        loop {
-            let rslt = fastq_file.next_seq();
-            match rslt {
-                Ok(Some(seq)) => {
-                    for module in &mut modules {
-                        module.process_sequence(&seq);
-                    }
-                }
-                Ok(None) => break,
+            process::exit(1);
+        }
+    }
+    if config.command != Command::Qc {
+        // The output may be the standard output, so errors go elsewhere.
+        if let Err(e) = run_codec(&config) {
+            eprintln!(
+                "Failed to {} {}: {:?}",
+                match config.command {
+                    Command::Decompress => "decompress",
+                    Command::Bin => "bin",
+                    Command::View => "view",
+                    _ => "compress",
+                },
+                config.files[0],
+                e
+            );
+            process::exit(1);
+        }
+        return;
+    }
+    if config.validate {
+        let mut valid = true;
+        for path in &config.files {
+            match validate_file(&config, path) {
+                Ok(v) => valid = valid && v,
                 Err(e) => {
-                    println!("Error={}", e);
-                    break;
+                    println!("Failed to validate {}: {:?}", path, e);
+                    valid = false;
                 }
             }
         }
-        match qc::write_text_reports(&modules, &mut report) {
-            Err(e) => {
-                println!("Error={:?}", e);
-            }
-            _ => {}
+        process::exit(if valid { 0 } else { 1 });
+    }
+    let mut worst = QCResult::Pass;
+    let mut failed = false;
+    let inputs: Vec<(&str, Option<&str>)> = if config.interleaved {
+        config.files.iter().map(|f| (&f[..], None)).collect()
+    } else if config.paired {
+        config
+            .files
+            .chunks(2)
+            .map(|f| (&f[0][..], Some(&f[1][..])))
+            .collect()
+    } else {
+        config.files.iter().map(|f| (&f[..], None)).collect()
+    };
+    if let Err(e) = check_out_names(&config, &inputs) {
+        println!("Error:{:?}", e);
+        process::exit(1);
+    }
+    for (path, r2_path) in inputs {
+        let rslt = if config.paired {
+            process_pair(&config, path, r2_path)
+        } else {
+            process_file(&config, path)
         };
-
-        let json_report = qc::get_json_reports(&modules);
-        match json_report {
-            Ok(map) => {
-                let json = serde_json::to_string_pretty(&map).unwrap();
-                println!("{}", json);
-            }
+        match rslt {
+            Ok(status) => worst = worst.max(status),
             Err(e) => {
-                println!("Error={:?}", e);
+                println!("Failed to process {}: {:?}", path, e);
+                failed = true;
             }
         }
     }
+    if failed {
+        process::exit(1);
+    }
+    if config.exit_status {
+        process::exit(worst.get_exit_code());
+    }
 }
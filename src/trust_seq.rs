@@ -1,13 +1,22 @@
 pub mod adapter;
 pub mod adapter_list;
+pub mod archive;
+pub mod binning;
+pub mod compression;
 pub mod contaminant;
 pub mod contaminant_list;
+pub mod fasta;
 pub mod gc_model;
 pub mod group;
+pub mod html_report;
 pub mod limits;
 pub mod math;
 pub mod module_config;
+pub mod parallel;
 pub mod qc;
 pub mod range_coder;
+pub mod sam;
+pub mod svg;
 pub mod trust_seq;
 pub mod utils;
+pub mod validate;
@@ -1,7 +1,9 @@
+use super::utils;
 use std::io::BufRead;
+use std::io::Result;
 use std::vec::Vec;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Adapter {
     pub name: String,
     pub sequence: String,
@@ -15,33 +17,16 @@ impl Adapter {
             positions: Vec::new(),
         };
     }
+    /// Counts a hit starting at `idx`. `positions` holds the hits per start
+    /// position; the cumulative coverage is built when reporting.
     pub fn increment_count(&mut self, idx: usize) {
-        for pos in &mut self.positions[idx..] {
-            *pos += 1;
+        if self.positions.len() <= idx {
+            self.positions.resize(idx + 1, 0);
         }
+        self.positions[idx] += 1;
     }
-    pub fn load_adapters<R: BufRead>(reader: R) -> Vec<Adapter> {
-        let mut cons = Vec::new();
-        for rslt in reader.lines() {
-            let line = match rslt {
-                Ok(l) => l,
-                _ => String::new(),
-            };
-            if line.starts_with('#') || line.len() == 0 {
-                continue;
-            }
-            let mut key = String::new();
-            let mut value = String::new();
-            match line.find('\t') {
-                Some(i) => key.push_str(&line[0..i]),
-                None => continue,
-            }
-            match line.rfind('\t') {
-                Some(i) => value.push_str(&line[(i + 1)..]),
-                None => continue,
-            }
-            cons.push(Adapter::new(&key, &value));
-        }
-        return cons;
+    pub fn load_adapters<R: BufRead>(reader: R) -> Result<Vec<Adapter>> {
+        let entries = utils::read_sequence_list(reader)?;
+        return Ok(entries.iter().map(|e| Adapter::new(&e.0, &e.1)).collect());
     }
 }
@@ -0,0 +1,1202 @@
+use super::range_coder::context_model::{IntModel, MixedNucleotideModel};
+use super::range_coder::name_tokenizer::{compare_names, NameModel};
+use super::range_coder::range_corder::{RangeDecoder, RangeEncoder, SimpleModel};
+use super::utils::{check_fastq_record, trim_line_end, FastQError, LineReader};
+use flate2::Crc;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::mem;
+use std::ops::Range;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// The first bytes of a trust_seq FASTQ archive.
+pub const MAGIC: &'static [u8] = b"TSQZ";
+const VERSION: u8 = 6;
+// An archive is the magic and version, then blocks, each the record
+// count, FASTQ byte count and CRC-32 of its records and the three coded
+// streams. A record count of 0 ends the blocks, and the block index and
+// a footer of the index offset, record count, byte count and magic
+// follow.
+const HEADER_SIZE: u64 = 5;
+const FOOTER_SIZE: u64 = 28;
+/// The records of a block, unless given.
+pub const BLOCK_SIZE: u64 = 100_000;
+// First read names longer than this are taken for a corrupt index.
+const MAX_NAME_LENGTH: u64 = 1 << 20;
+// The bits of a name filter a read ID sets.
+const FILTER_PROBES: u64 = 4;
+// The orders of the base models mixed: a short one for bases never seen,
+// and one long enough to find the rest of a read elsewhere in the file.
+// The order 12 model takes 96M bytes.
+const BASE_ORDERS: [usize; 2] = [2, 12];
+// Qualities are modelled by the group of 8 positions they are in, the
+// last group holding the rest of the read.
+const POSITION_GROUPS: usize = 8;
+// The end of each line of a record.
+const LF: u16 = 0;
+const CRLF: u16 = 1;
+const NO_LINE_END: u16 = 2;
+// What follows the '+' of the separator line.
+const PLUS_ONLY: u16 = 0;
+const PLUS_NAME: u16 = 1;
+const PLUS_OTHER: u16 = 2;
+
+fn corrupt() -> Error {
+    return Error::new(ErrorKind::InvalidData, "the archive is corrupt");
+}
+fn get_line_end(line: &[u8]) -> u16 {
+    if !line.ends_with(b"\n") {
+        return NO_LINE_END;
+    } else if line.ends_with(b"\r\n") {
+        return CRLF;
+    } else {
+        return LF;
+    }
+}
+fn line_end_bytes(line_end: u16) -> &'static [u8] {
+    return match line_end {
+        LF => b"\n",
+        CRLF => b"\r\n",
+        _ => b"",
+    };
+}
+fn base_code(base: u8) -> Option<u16> {
+    return match base {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    };
+}
+
+/// Codes a line of text byte by byte, each byte by the one at the same
+/// position of the previous line. Symbol 256 ends the line.
+struct TextModel {
+    models: Vec<SimpleModel>,
+    previous: Vec<u8>,
+}
+impl TextModel {
+    fn new() -> TextModel {
+        return TextModel {
+            models: vec![SimpleModel::new(257); 256],
+            previous: Vec::new(),
+        };
+    }
+    fn encode(&mut self, rc: &mut RangeEncoder, text: &[u8]) {
+        for idx in 0..(text.len() + 1) {
+            let sym = text.get(idx).map_or(256, |c| *c as u16);
+            let context = self.previous.get(idx).map_or(0, |c| *c as usize);
+            self.models[context].encode(rc, sym);
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(text);
+    }
+    /// Decodes a line into `text`, failing if it is longer than `limit`.
+    fn decode(&mut self, rd: &mut RangeDecoder, text: &mut Vec<u8>, limit: u64) -> Result<()> {
+        text.clear();
+        loop {
+            let context = self.previous.get(text.len()).map_or(0, |c| *c as usize);
+            let sym = self.models[context].decode(rd);
+            if sym == 256 {
+                break;
+            }
+            if text.len() as u64 >= limit {
+                return Err(corrupt());
+            }
+            text.push(sym as u8);
+        }
+        self.previous.clear();
+        self.previous.extend_from_slice(text);
+        return Ok(());
+    }
+}
+
+/// Codes the quality characters of a read by the two before them and by
+/// their position.
+struct QualityModel {
+    models: Vec<SimpleModel>,
+}
+impl QualityModel {
+    fn new() -> QualityModel {
+        return QualityModel {
+            models: vec![SimpleModel::new(256); 256 * 2 * POSITION_GROUPS],
+        };
+    }
+    fn get_context(position: usize, q1: u8, q2: u8) -> usize {
+        let group = (position / 8).min(POSITION_GROUPS - 1);
+        return ((q1 as usize * 2) + (q1 == q2) as usize) * POSITION_GROUPS + group;
+    }
+    fn encode(&mut self, rc: &mut RangeEncoder, quality: &[u8]) {
+        let (mut q1, mut q2) = (0, 0);
+        for (position, q) in quality.iter().enumerate() {
+            self.models[QualityModel::get_context(position, q1, q2)].encode(rc, *q as u16);
+            q2 = q1;
+            q1 = *q;
+        }
+    }
+    fn decode(&mut self, rd: &mut RangeDecoder, quality: &mut Vec<u8>, len: usize) {
+        quality.clear();
+        let (mut q1, mut q2) = (0, 0);
+        for position in 0..len {
+            let context = QualityModel::get_context(position, q1, q2);
+            let q = self.models[context].decode(rd) as u8;
+            quality.push(q);
+            q2 = q1;
+            q1 = q;
+        }
+    }
+}
+
+/// The models of every stream. Both sides start from the same models and
+/// update them with the same symbols.
+struct Models {
+    line_ends: Vec<SimpleModel>,
+    names: NameModel,
+    plus_kinds: SimpleModel,
+    plus_lines: TextModel,
+    lengths: IntModel,
+    exception_counts: IntModel,
+    exception_gaps: IntModel,
+    exception_bases: SimpleModel,
+    bases: MixedNucleotideModel,
+    qualities: QualityModel,
+    // The blank lines before each record, and after the last one.
+    blank_lines: IntModel,
+    blank_line_ends: SimpleModel,
+}
+impl Models {
+    fn new() -> Models {
+        return Models {
+            line_ends: vec![SimpleModel::new(3); 4],
+            names: NameModel::new(),
+            plus_kinds: SimpleModel::new(3),
+            plus_lines: TextModel::new(),
+            lengths: IntModel::new(),
+            exception_counts: IntModel::new(),
+            exception_gaps: IntModel::new(),
+            exception_bases: SimpleModel::new(256),
+            bases: MixedNucleotideModel::new(&BASE_ORDERS),
+            qualities: QualityModel::new(),
+            blank_lines: IntModel::new(),
+            blank_line_ends: SimpleModel::new(2),
+        };
+    }
+}
+
+fn write_u64<W: Write>(write: &mut W, value: u64) -> Result<()> {
+    return write.write_all(&value.to_le_bytes());
+}
+fn read_u64<R: Read>(read: &mut R) -> Result<u64> {
+    let mut buf = [0u8; 8];
+    read.read_exact(&mut buf)?;
+    return Ok(u64::from_le_bytes(buf));
+}
+/// The read ID: the name up to the first whitespace.
+fn get_read_id(name: &[u8]) -> &[u8] {
+    let end = name
+        .iter()
+        .position(|c| *c == b' ' || *c == b'\t')
+        .unwrap_or(name.len());
+    return &name[..end];
+}
+/// A hash of a read ID that stays the same from build to build, FNV-1a
+/// with the 64 bit MurmurHash3 finalizer to spread the bits.
+fn hash_read_id(id: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for c in id {
+        hash = (hash ^ *c as u64).wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 33)).wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash = (hash ^ (hash >> 33)).wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    return hash ^ (hash >> 33);
+}
+
+/// A Bloom filter of the read IDs of a block, about 8 bits a read, so a
+/// name is looked up only in the blocks that may hold it whatever the
+/// order of the reads. About 1 block in 40 without the name is decoded.
+#[derive(Debug, Clone, PartialEq)]
+struct NameFilter {
+    bits: Vec<u8>,
+}
+impl NameFilter {
+    /// The bytes of the filter of a block of `record_count` reads, or
+    /// `None` for a count no block has.
+    fn get_size(record_count: u64) -> Option<u64> {
+        return record_count.max(8).checked_next_power_of_two();
+    }
+    fn new(hashes: &[u64]) -> NameFilter {
+        let size = NameFilter::get_size(hashes.len() as u64).unwrap();
+        let mut filter = NameFilter {
+            bits: vec![0; size as usize],
+        };
+        for hash in hashes {
+            for bit in filter.get_bits(*hash) {
+                filter.bits[(bit / 8) as usize] |= 1 << (bit % 8);
+            }
+        }
+        return filter;
+    }
+    fn get_bits(&self, hash: u64) -> Vec<u64> {
+        let mask = self.bits.len() as u64 * 8 - 1;
+        let step = (hash >> 32) | 1;
+        return (0..FILTER_PROBES)
+            .map(|i| hash.wrapping_add(i.wrapping_mul(step)) & mask)
+            .collect();
+    }
+    /// Whether the block may hold the read ID `id`.
+    fn may_contain(&self, id: &[u8]) -> bool {
+        return self
+            .get_bits(hash_read_id(id))
+            .iter()
+            .all(|bit| self.bits[(bit / 8) as usize] & (1 << (bit % 8)) != 0);
+    }
+}
+
+fn write_header<W: Write>(write: &mut W) -> Result<()> {
+    write.write_all(MAGIC)?;
+    return write.write_all(&[VERSION]);
+}
+fn read_header<R: Read>(read: &mut R) -> Result<()> {
+    let mut magic = [0u8; 5];
+    read.read_exact(&mut magic)?;
+    if &magic[..4] != MAGIC {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not a trust_seq archive",
+        ));
+    }
+    if magic[4] != VERSION {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported archive version {}", magic[4]),
+        ));
+    }
+    return Ok(());
+}
+/// Reads the index offset, record count and byte count of the footer.
+fn read_footer<R: Read>(read: &mut R) -> Result<(u64, u64, u64)> {
+    let footer = (read_u64(read)?, read_u64(read)?, read_u64(read)?);
+    let mut magic = [0u8; 4];
+    read.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(corrupt());
+    }
+    return Ok(footer);
+}
+
+/// Where a block of an archive is, and the first read in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEntry {
+    /// The position of the block in the archive.
+    pub offset: u64,
+    /// The number of the first record of the block, counted from 0.
+    pub first_record: u64,
+    pub record_count: u64,
+    /// The name of the first read, without the '@'.
+    pub first_name: Vec<u8>,
+    name_filter: NameFilter,
+}
+fn write_index<W: Write>(write: &mut W, index: &[BlockEntry], sorted_names: bool) -> Result<()> {
+    write_u64(write, index.len() as u64)?;
+    write.write_all(&[sorted_names as u8])?;
+    for entry in index {
+        write_u64(write, entry.offset)?;
+        write_u64(write, entry.first_record)?;
+        write_u64(write, entry.record_count)?;
+        write_u64(write, entry.first_name.len() as u64)?;
+        write.write_all(&entry.first_name)?;
+        write.write_all(&entry.name_filter.bits)?;
+    }
+    return Ok(());
+}
+/// Reads the block index, and whether the read IDs are sorted.
+fn read_index<R: Read>(read: &mut R) -> Result<(Vec<BlockEntry>, bool)> {
+    let block_count = read_u64(read)?;
+    let mut sorted_names = [0u8; 1];
+    read.read_exact(&mut sorted_names)?;
+    let mut index = Vec::new();
+    for _ in 0..block_count {
+        let offset = read_u64(read)?;
+        let first_record = read_u64(read)?;
+        let record_count = read_u64(read)?;
+        let len = read_u64(read)?;
+        if len > MAX_NAME_LENGTH {
+            return Err(corrupt());
+        }
+        let mut first_name = vec![0; len as usize];
+        read.read_exact(&mut first_name)?;
+        let size = NameFilter::get_size(record_count).ok_or_else(corrupt)?;
+        let mut bits = Vec::new();
+        read.take(size).read_to_end(&mut bits)?;
+        if (bits.len() as u64) < size {
+            return Err(corrupt());
+        }
+        index.push(BlockEntry {
+            offset: offset,
+            first_record: first_record,
+            record_count: record_count,
+            first_name: first_name,
+            name_filter: NameFilter { bits: bits },
+        });
+    }
+    return Ok((index, sorted_names[0] != 0));
+}
+/// Checks that the blocks follow one another before `index_offset` and
+/// hold `record_count` records between them.
+fn check_index(index: &[BlockEntry], record_count: u64, index_offset: u64) -> Result<()> {
+    let mut offset = HEADER_SIZE;
+    let mut first_record = 0;
+    for entry in index {
+        if entry.offset < offset || entry.first_record != first_record || entry.record_count == 0 {
+            return Err(corrupt());
+        }
+        offset = entry.offset + 1;
+        first_record += entry.record_count;
+    }
+    if offset > index_offset || first_record != record_count {
+        return Err(corrupt());
+    }
+    return Ok(());
+}
+
+/// Codes the records of a block into its three streams, with models of
+/// its own.
+struct BlockWriter {
+    models: Models,
+    names_rc: RangeEncoder,
+    bases_rc: RangeEncoder,
+    qualities_rc: RangeEncoder,
+    crc: Crc,
+    record_count: u64,
+    byte_count: u64,
+    exceptions: Vec<(usize, u8)>,
+    id_hashes: Vec<u64>,
+}
+impl BlockWriter {
+    fn new() -> BlockWriter {
+        return BlockWriter {
+            models: Models::new(),
+            names_rc: RangeEncoder::new(1 << 16),
+            bases_rc: RangeEncoder::new(1 << 16),
+            qualities_rc: RangeEncoder::new(1 << 16),
+            crc: Crc::new(),
+            record_count: 0,
+            byte_count: 0,
+            exceptions: Vec::new(),
+            id_hashes: Vec::new(),
+        };
+    }
+    /// Adds blank lines of the lengths given by
+    /// `LineReader::skip_blank_lines`.
+    fn add_blank_lines(&mut self, blank_lines: &[usize]) {
+        let models = &mut self.models;
+        models
+            .blank_lines
+            .encode(&mut self.names_rc, blank_lines.len() as u32);
+        for len in blank_lines {
+            let line: &[u8] = if *len == 1 { b"\n" } else { b"\r\n" };
+            self.crc.update(line);
+            self.byte_count += line.len() as u64;
+            models
+                .blank_line_ends
+                .encode(&mut self.names_rc, get_line_end(line));
+        }
+    }
+    /// Adds the lines of a well formed record and the blank lines before
+    /// it.
+    fn add(&mut self, blank_lines: &[usize], lines: &[&[u8]]) {
+        self.add_blank_lines(blank_lines);
+        let models = &mut self.models;
+        self.record_count += 1;
+        for (idx, line) in lines.iter().enumerate() {
+            self.crc.update(line);
+            self.byte_count += line.len() as u64;
+            models.line_ends[idx].encode(&mut self.names_rc, get_line_end(line));
+        }
+        let name = &trim_line_end(lines[0])[1..];
+        self.id_hashes.push(hash_read_id(get_read_id(name)));
+        models.names.encode(&mut self.names_rc, name);
+        let plus = &trim_line_end(lines[2])[1..];
+        if plus.is_empty() {
+            models.plus_kinds.encode(&mut self.names_rc, PLUS_ONLY);
+        } else if plus == name {
+            models.plus_kinds.encode(&mut self.names_rc, PLUS_NAME);
+        } else {
+            models.plus_kinds.encode(&mut self.names_rc, PLUS_OTHER);
+            models.plus_lines.encode(&mut self.names_rc, plus);
+        }
+
+        // Bases other than A, C, G and T, N mostly, are coded apart with
+        // the gap since the one before.
+        let sequence = trim_line_end(lines[1]);
+        let exceptions = &mut self.exceptions;
+        exceptions.clear();
+        exceptions.extend(
+            sequence
+                .iter()
+                .enumerate()
+                .filter(|b| base_code(*b.1).is_none())
+                .map(|b| (b.0, *b.1)),
+        );
+        models
+            .lengths
+            .encode(&mut self.bases_rc, sequence.len() as u32);
+        models
+            .exception_counts
+            .encode(&mut self.bases_rc, exceptions.len() as u32);
+        let mut next = 0;
+        for &(position, base) in exceptions.iter() {
+            models
+                .exception_gaps
+                .encode(&mut self.bases_rc, (position - next) as u32);
+            models
+                .exception_bases
+                .encode(&mut self.bases_rc, base as u16);
+            next = position + 1;
+        }
+        for base in sequence {
+            if let Some(code) = base_code(*base) {
+                models.bases.encode(&mut self.bases_rc, code);
+            }
+        }
+        models
+            .qualities
+            .encode(&mut self.qualities_rc, trim_line_end(lines[3]));
+    }
+    /// Writes out the block, ending with `blank_lines` after its last
+    /// record, and returns its size and the filter of its read IDs.
+    fn finish<W: Write>(
+        mut self,
+        blank_lines: &[usize],
+        write: &mut W,
+    ) -> Result<(u64, NameFilter)> {
+        self.add_blank_lines(blank_lines);
+        write_u64(write, self.record_count)?;
+        write_u64(write, self.byte_count)?;
+        write.write_all(&self.crc.sum().to_le_bytes())?;
+        let mut size = 20;
+        for rc in &mut [self.names_rc, self.bases_rc, self.qualities_rc] {
+            rc.finish_encode();
+            write_u64(write, rc.get_buf().len() as u64)?;
+            write.write_all(rc.get_buf())?;
+            size += 8 + rc.get_buf().len() as u64;
+        }
+        return Ok((size, NameFilter::new(&self.id_hashes)));
+    }
+}
+
+/// The records taken out of a block.
+#[derive(Debug, Clone)]
+enum Selection {
+    /// The records with these numbers, counted from 0.
+    Records(Range<u64>),
+    /// The records with this read ID or whole name.
+    Name(Vec<u8>),
+}
+impl Selection {
+    fn contains(&self, number: u64, name: &[u8]) -> bool {
+        return match *self {
+            Selection::Records(ref records) => records.contains(&number),
+            Selection::Name(ref n) => &n[..] == name || &n[..] == get_read_id(name),
+        };
+    }
+}
+
+/// Decodes blank lines added by `BlockWriter::add_blank_lines` onto
+/// `text`, no more than `limit` bytes of them.
+fn decode_blank_lines(
+    models: &mut Models,
+    rd: &mut RangeDecoder,
+    text: &mut Vec<u8>,
+    limit: u64,
+) -> Result<()> {
+    let count = models.blank_lines.decode(rd) as u64;
+    if count > limit {
+        return Err(corrupt());
+    }
+    for _ in 0..count {
+        text.extend_from_slice(line_end_bytes(models.blank_line_ends.decode(rd)));
+    }
+    return Ok(());
+}
+
+/// A block as read from an archive, still coded.
+struct Block {
+    record_count: u64,
+    byte_count: u64,
+    crc_sum: [u8; 4],
+    streams: Vec<Vec<u8>>,
+}
+impl Block {
+    /// Reads the next block, or `None` at the end of the blocks.
+    fn read<R: Read>(read: &mut R) -> Result<Option<Block>> {
+        let record_count = read_u64(read)?;
+        if record_count == 0 {
+            return Ok(None);
+        }
+        let byte_count = read_u64(read)?;
+        let mut crc_sum = [0u8; 4];
+        read.read_exact(&mut crc_sum)?;
+        let mut streams: Vec<Vec<u8>> = Vec::new();
+        for _ in 0..3 {
+            let len = read_u64(read)?;
+            let mut stream = Vec::new();
+            read.take(len).read_to_end(&mut stream)?;
+            if (stream.len() as u64) < len {
+                return Err(corrupt());
+            }
+            streams.push(stream);
+        }
+        return Ok(Some(Block {
+            record_count: record_count,
+            byte_count: byte_count,
+            crc_sum: crc_sum,
+            streams: streams,
+        }));
+    }
+    fn get_size(&self) -> u64 {
+        return 20 + self.streams.iter().map(|s| 8 + s.len() as u64).sum::<u64>();
+    }
+    /// Decodes every record of the block, the first one being record
+    /// `first_record` of the file, and returns the FASTQ text of those in
+    /// `selection`. The numbers of the records are returned for a
+    /// `Selection::Name` only, as those of a range are known.
+    fn decode(&self, first_record: u64, selection: &Selection) -> Result<(Vec<u64>, Vec<u8>)> {
+        let mut names_rd = RangeDecoder::new(&self.streams[0]);
+        let mut bases_rd = RangeDecoder::new(&self.streams[1]);
+        let mut qualities_rd = RangeDecoder::new(&self.streams[2]);
+        let mut models = Models::new();
+        let mut crc = Crc::new();
+        // Decoding stops at the length of the FASTQ text, however corrupt
+        // the block is.
+        let mut remaining = self.byte_count;
+        let mut line_ends = [LF; 4];
+        let mut name = Vec::new();
+        let mut plus = Vec::new();
+        let mut sequence = Vec::new();
+        let mut is_exception = Vec::new();
+        let mut quality = Vec::new();
+        let mut record = Vec::new();
+        let mut numbers = Vec::new();
+        let mut fastq = Vec::new();
+        let mut is_selected = false;
+        for number in first_record..(first_record + self.record_count) {
+            record.clear();
+            decode_blank_lines(&mut models, &mut names_rd, &mut record, remaining)?;
+            for (idx, line_end) in line_ends.iter_mut().enumerate() {
+                *line_end = models.line_ends[idx].decode(&mut names_rd);
+            }
+            models.names.decode(&mut names_rd, &mut name, remaining)?;
+            match models.plus_kinds.decode(&mut names_rd) {
+                PLUS_ONLY => plus.clear(),
+                PLUS_NAME => {
+                    plus.clear();
+                    plus.extend_from_slice(&name);
+                }
+                _ => models
+                    .plus_lines
+                    .decode(&mut names_rd, &mut plus, remaining)?,
+            }
+
+            let len = models.lengths.decode(&mut bases_rd) as usize;
+            let exception_count = models.exception_counts.decode(&mut bases_rd) as usize;
+            if len as u64 > remaining || exception_count > len {
+                return Err(corrupt());
+            }
+            sequence.clear();
+            sequence.resize(len, 0);
+            is_exception.clear();
+            is_exception.resize(len, false);
+            let mut next = 0;
+            for _ in 0..exception_count {
+                let position = next + models.exception_gaps.decode(&mut bases_rd) as usize;
+                if position >= len {
+                    return Err(corrupt());
+                }
+                sequence[position] = models.exception_bases.decode(&mut bases_rd) as u8;
+                is_exception[position] = true;
+                next = position + 1;
+            }
+            for (base, exception) in sequence.iter_mut().zip(is_exception.iter()) {
+                if !exception {
+                    *base = b"ACGT"[models.bases.decode(&mut bases_rd) as usize];
+                }
+            }
+            models
+                .qualities
+                .decode(&mut qualities_rd, &mut quality, len);
+
+            record.push(b'@');
+            record.extend_from_slice(&name);
+            record.extend_from_slice(line_end_bytes(line_ends[0]));
+            record.extend_from_slice(&sequence);
+            record.extend_from_slice(line_end_bytes(line_ends[1]));
+            record.push(b'+');
+            record.extend_from_slice(&plus);
+            record.extend_from_slice(line_end_bytes(line_ends[2]));
+            record.extend_from_slice(&quality);
+            record.extend_from_slice(line_end_bytes(line_ends[3]));
+            if record.len() as u64 > remaining {
+                return Err(corrupt());
+            }
+            remaining -= record.len() as u64;
+            crc.update(&record);
+            is_selected = selection.contains(number, &name);
+            if is_selected {
+                if let Selection::Name(_) = *selection {
+                    numbers.push(number);
+                }
+                fastq.extend_from_slice(&record);
+            }
+        }
+        // The blank lines at the end of the file go with the last record.
+        record.clear();
+        decode_blank_lines(&mut models, &mut names_rd, &mut record, remaining)?;
+        if record.len() as u64 > remaining {
+            return Err(corrupt());
+        }
+        remaining -= record.len() as u64;
+        crc.update(&record);
+        if is_selected {
+            fastq.extend_from_slice(&record);
+        }
+        if remaining != 0 || crc.sum().to_le_bytes() != self.crc_sum {
+            return Err(corrupt());
+        }
+        return Ok((numbers, fastq));
+    }
+}
+
+type Job = (usize, u64, Block);
+type JobResult = (usize, Result<(Vec<u64>, Vec<u8>)>);
+
+fn run_decoder(
+    selection: Arc<Selection>,
+    jobs: Arc<Mutex<Receiver<Job>>>,
+    results: Sender<JobResult>,
+) {
+    loop {
+        let job = jobs.lock().unwrap().recv();
+        let (idx, first_record, block) = match job {
+            Ok(j) => j,
+            Err(_) => return,
+        };
+        if results
+            .send((idx, block.decode(first_record, &selection)))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Writes decoded blocks in archive order, whatever order they arrive in.
+struct BlockOutput {
+    numbers: Vec<u64>,
+    next_idx: usize,
+    pending: BTreeMap<usize, Result<(Vec<u64>, Vec<u8>)>>,
+}
+impl BlockOutput {
+    fn add<W: Write>(
+        &mut self,
+        idx: usize,
+        decoded: Result<(Vec<u64>, Vec<u8>)>,
+        write: &mut W,
+    ) -> Result<()> {
+        self.pending.insert(idx, decoded);
+        while let Some(decoded) = self.pending.remove(&self.next_idx) {
+            let (numbers, fastq) = decoded?;
+            write.write_all(&fastq)?;
+            self.numbers.extend(numbers);
+            self.next_idx += 1;
+        }
+        return Ok(());
+    }
+}
+
+/// Decodes `blocks`, each with the number of its first record, on
+/// `threads` threads and writes the records of `selection` in order.
+/// Returns the numbers `Block::decode` gives.
+fn decode_blocks<W: Write, I: Iterator<Item = Result<(u64, Block)>>>(
+    blocks: I,
+    selection: Selection,
+    threads: usize,
+    write: &mut W,
+) -> Result<Vec<u64>> {
+    let mut output = BlockOutput {
+        numbers: Vec::new(),
+        next_idx: 0,
+        pending: BTreeMap::new(),
+    };
+    if threads <= 1 {
+        for (idx, block) in blocks.enumerate() {
+            let (first_record, block) = block?;
+            output.add(idx, block.decode(first_record, &selection), write)?;
+        }
+        return Ok(output.numbers);
+    }
+    let selection = Arc::new(selection);
+    let (job_tx, job_rx) = sync_channel::<Job>(threads);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+    let (result_tx, result_rx) = channel::<JobResult>();
+    let mut workers = Vec::new();
+    for _ in 0..threads {
+        let selection = selection.clone();
+        let jobs = job_rx.clone();
+        let results = result_tx.clone();
+        workers.push(thread::spawn(move || run_decoder(selection, jobs, results)));
+    }
+    drop(result_tx);
+
+    let mut rslt = Ok(());
+    for (idx, block) in blocks.enumerate() {
+        rslt = block.and_then(|(first_record, block)| {
+            return job_tx
+                .send((idx, first_record, block))
+                .map_err(|_| Error::new(ErrorKind::Other, "a decoder thread stopped"));
+        });
+        while rslt.is_ok() {
+            match result_rx.try_recv() {
+                Ok((idx, decoded)) => rslt = output.add(idx, decoded, write),
+                Err(_) => break,
+            }
+        }
+        if rslt.is_err() {
+            break;
+        }
+    }
+    drop(job_tx);
+    for (idx, decoded) in result_rx.iter() {
+        if rslt.is_ok() {
+            rslt = output.add(idx, decoded, write);
+        }
+    }
+    for worker in workers {
+        if worker.join().is_err() {
+            return Err(Error::new(ErrorKind::Other, "a decoder thread panicked"));
+        }
+    }
+    rslt?;
+    return Ok(output.numbers);
+}
+
+/// Compresses a FASTQ file into a trust_seq archive, returning the number
+/// of records. Read names, bases and qualities go to separate streams,
+/// each coded with its own context models. Anything but a well formed
+/// FASTQ file fails with a `FastQError`, as only those round trip.
+///
+/// The records are coded in blocks of `BLOCK_SIZE` records, the models
+/// starting afresh in each, and an index of the blocks ends the archive.
+/// `ArchiveReader` decodes only the blocks it needs through the index.
+/// Each block holds the CRC-32 and the length of its FASTQ text, so
+/// decoding gives back the same bytes or fails.
+pub fn compress<R: Read, W: Write>(read: R, write: &mut W) -> Result<u64> {
+    return compress_blocks(read, write, BLOCK_SIZE);
+}
+/// `compress` with blocks of `block_size` records, at least 1. Smaller
+/// blocks compress a little worse, but less is decoded to get at a read.
+pub fn compress_blocks<R: Read, W: Write>(read: R, write: &mut W, block_size: u64) -> Result<u64> {
+    let block_size = block_size.max(1);
+    let mut reader = LineReader::new(read, 4096);
+    write_header(write)?;
+    let mut archive_offset = HEADER_SIZE;
+    let mut index: Vec<BlockEntry> = Vec::new();
+    let mut block = BlockWriter::new();
+    let mut record_count = 0u64;
+    let mut offset = 0u64;
+    // Whether the read IDs are in order, so a name is looked up in the
+    // blocks its ID falls between.
+    let mut sorted_names = true;
+    let mut previous_id = Vec::new();
+    // Blank lines go with the record after them, or at the end of the
+    // file with the last block.
+    let mut blank_lines;
+    loop {
+        blank_lines = reader.skip_blank_lines()?;
+        let lines = reader.read_lines(4)?;
+        if lines.is_empty() {
+            break;
+        }
+        offset += blank_lines.iter().sum::<usize>() as u64;
+        record_count += 1;
+        if let Some(kind) = check_fastq_record(&lines) {
+            return Err(FastQError {
+                kind: kind,
+                record: record_count,
+                offset: offset,
+            }
+            .into());
+        }
+        let name = &trim_line_end(lines[0])[1..];
+        if sorted_names {
+            let id = get_read_id(name);
+            sorted_names = compare_names(&previous_id, id) != Ordering::Greater;
+            previous_id.clear();
+            previous_id.extend_from_slice(id);
+        }
+        if block.record_count == block_size {
+            let (size, filter) = mem::replace(&mut block, BlockWriter::new()).finish(&[], write)?;
+            archive_offset += size;
+            index.last_mut().unwrap().name_filter = filter;
+        }
+        if block.record_count == 0 {
+            index.push(BlockEntry {
+                offset: archive_offset,
+                first_record: record_count - 1,
+                record_count: 0,
+                first_name: name.to_vec(),
+                name_filter: NameFilter { bits: Vec::new() },
+            });
+        }
+        block.add(&blank_lines, &lines);
+        index.last_mut().unwrap().record_count += 1;
+        offset += lines.iter().map(|l| l.len() as u64).sum::<u64>();
+    }
+    if block.record_count > 0 {
+        offset += blank_lines.iter().sum::<usize>() as u64;
+        let (size, filter) = block.finish(&blank_lines, write)?;
+        archive_offset += size;
+        index.last_mut().unwrap().name_filter = filter;
+    }
+    write_u64(write, 0)?;
+    let index_offset = archive_offset + 8;
+    write_index(write, &index, sorted_names)?;
+    write_u64(write, index_offset)?;
+    write_u64(write, record_count)?;
+    write_u64(write, offset)?;
+    write.write_all(MAGIC)?;
+    return Ok(record_count);
+}
+
+/// Writes out the FASTQ file a trust_seq archive was made from, returning
+/// the number of records. The archive is read once from start to end, so
+/// it can come through a pipe. Fails with `InvalidData` on anything but
+/// an intact archive.
+pub fn decompress<R: Read, W: Write>(mut read: R, write: &mut W) -> Result<u64> {
+    read_header(&mut read)?;
+    let mut blocks = Vec::new();
+    let mut offset = HEADER_SIZE;
+    let mut record_count = 0;
+    let mut byte_count = 0;
+    while let Some(block) = Block::read(&mut read)? {
+        let (_, fastq) = block.decode(record_count, &Selection::Records(0..u64::MAX))?;
+        write.write_all(&fastq)?;
+        blocks.push((offset, record_count, block.record_count));
+        offset += block.get_size();
+        record_count += block.record_count;
+        byte_count += block.byte_count;
+    }
+    let (index, _) = read_index(&mut read)?;
+    let footer = read_footer(&mut read)?;
+    let same_blocks = index.len() == blocks.len()
+        && index
+            .iter()
+            .zip(blocks.iter())
+            .all(|(e, b)| (e.offset, e.first_record, e.record_count) == *b);
+    if !same_blocks || footer != (offset + 8, record_count, byte_count) {
+        return Err(corrupt());
+    }
+    return Ok(record_count);
+}
+
+/// Reads records out of an archive without decoding all of it: through
+/// the block index, only the blocks holding the records asked for are
+/// decoded, on as many threads as given.
+pub struct ArchiveReader<R: Read + Seek> {
+    read: R,
+    index: Vec<BlockEntry>,
+    sorted_names: bool,
+    record_count: u64,
+}
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Reads the block index of an archive, failing with `InvalidData` on
+    /// anything but a trust_seq archive.
+    pub fn open(mut read: R) -> Result<ArchiveReader<R>> {
+        read.seek(SeekFrom::Start(0))?;
+        read_header(&mut read)?;
+        let end = read.seek(SeekFrom::End(0))?;
+        if end < HEADER_SIZE + 8 + FOOTER_SIZE {
+            return Err(corrupt());
+        }
+        read.seek(SeekFrom::Start(end - FOOTER_SIZE))?;
+        let (index_offset, record_count, _) = read_footer(&mut read)?;
+        if index_offset < HEADER_SIZE + 8 || index_offset > end - FOOTER_SIZE {
+            return Err(corrupt());
+        }
+        read.seek(SeekFrom::Start(index_offset))?;
+        let (index, sorted_names) =
+            read_index(&mut (&mut read).take(end - FOOTER_SIZE - index_offset))?;
+        check_index(&index, record_count, index_offset)?;
+        return Ok(ArchiveReader {
+            read: read,
+            index: index,
+            sorted_names: sorted_names,
+            record_count: record_count,
+        });
+    }
+    pub fn get_record_count(&self) -> u64 {
+        return self.record_count;
+    }
+    pub fn get_index(&self) -> &[BlockEntry] {
+        return &self.index;
+    }
+    fn decode_selected<W: Write>(
+        &mut self,
+        blocks: &[usize],
+        selection: Selection,
+        threads: usize,
+        write: &mut W,
+    ) -> Result<Vec<u64>> {
+        let read = &mut self.read;
+        let index = &self.index;
+        let blocks = blocks.iter().map(|idx| {
+            let entry = &index[*idx];
+            read.seek(SeekFrom::Start(entry.offset))?;
+            return match Block::read(read)? {
+                Some(block) if block.record_count == entry.record_count => {
+                    Ok((entry.first_record, block))
+                }
+                _ => Err(corrupt()),
+            };
+        });
+        return decode_blocks(blocks, selection, threads, write);
+    }
+    /// Writes the records numbered `records.start` up to `records.end`,
+    /// counted from 0, returning how many there were.
+    pub fn view<W: Write>(
+        &mut self,
+        records: Range<u64>,
+        threads: usize,
+        write: &mut W,
+    ) -> Result<u64> {
+        let end = records.end.min(self.record_count);
+        let blocks: Vec<usize> = (0..self.index.len())
+            .filter(|idx| {
+                let entry = &self.index[*idx];
+                entry.first_record < end && records.start < entry.first_record + entry.record_count
+            })
+            .collect();
+        let count = end.saturating_sub(records.start);
+        self.decode_selected(&blocks, Selection::Records(records), threads, write)?;
+        return Ok(count);
+    }
+    /// Writes the records with the read ID, or the whole name, `name`,
+    /// returning their numbers. Only the blocks whose name filter may hold
+    /// the read ID are decoded, and when the archive has the read IDs in
+    /// order, only those of the blocks the ID falls between.
+    pub fn find_name<W: Write>(
+        &mut self,
+        name: &[u8],
+        threads: usize,
+        write: &mut W,
+    ) -> Result<Vec<u64>> {
+        let id = get_read_id(name);
+        let blocks: Vec<usize> = if self.sorted_names {
+            let before = |entry: &BlockEntry| compare_names(get_read_id(&entry.first_name), id);
+            let first = self
+                .index
+                .iter()
+                .rposition(|e| before(e) == Ordering::Less)
+                .unwrap_or(0);
+            (first..self.index.len())
+                .take_while(|idx| *idx == first || before(&self.index[*idx]) != Ordering::Greater)
+                .collect()
+        } else {
+            (0..self.index.len()).collect()
+        };
+        let blocks: Vec<usize> = blocks
+            .into_iter()
+            .filter(|idx| self.index[*idx].name_filter.may_contain(id))
+            .collect();
+        return self.decode_selected(&blocks, Selection::Name(name.to_vec()), threads, write);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, compress_blocks, decompress, hash_read_id, ArchiveReader, NameFilter};
+    use std::io::Cursor;
+    use std::io::ErrorKind;
+
+    fn round_trip(fastq: &[u8]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        compress(fastq, &mut archive).unwrap();
+        let mut out = Vec::new();
+        decompress(&archive[..], &mut out).unwrap();
+        assert_eq!(fastq, &out[..]);
+        return archive;
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(b"");
+        round_trip(b"@r1\nACGT\n+\nIIII\n");
+        // Line ends, separator lines and bases that need more than 2 bits.
+        round_trip(
+            b"@SIM:1:FCX:1:1101:1000:2000 1:N:0:ATCACG\r\nACGTNNacgtRY\r\n+\r\nIIII##IIII55\r\n\
+@SIM:1:FCX:1:1101:1000:2001 1:N:0:ATCACG\nACGT\n+SIM:1:FCX:1:1101:1000:2001 1:N:0:ATCACG\nI!I~\n\
+@r3\n\n+other\n\n@r4\nN\n+\n#",
+        );
+
+        let mut fastq = Vec::new();
+        for i in 0..2000 {
+            fastq.extend_from_slice(
+                format!("@SIM:1:FCX:1:1101:{}:{}\n", 1000 + i, 2000 + i * 7).as_bytes(),
+            );
+            fastq.extend_from_slice(b"ACGTTGCAAGGCTTAACGGTTTGCAAGGCTTAACGGTACG\n+\n");
+            fastq.extend_from_slice(b"IIIIHHHHGGGGFFFFEEEEIIIIIIIIIIHHHHHHHHHH\n");
+        }
+        let archive = round_trip(&fastq);
+        assert!(archive.len() * 10 < fastq.len());
+
+        // Blank lines before, between and after the records, also across
+        // blocks.
+        let fastq = b"\n@r1\nACGT\n+\nIIII\n\n\r\n@r2\nACGT\n+\nIIII\n\n@r3\nA\n+\nI\n\n\n";
+        round_trip(fastq);
+        let mut archive = Vec::new();
+        compress_blocks(&fastq[..], &mut archive, 1).unwrap();
+        let mut out = Vec::new();
+        decompress(&archive[..], &mut out).unwrap();
+        assert_eq!(&fastq[..], &out[..]);
+        let mut reader = ArchiveReader::open(Cursor::new(&archive)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(3, reader.view(0..3, 2, &mut out).unwrap());
+        assert_eq!(&fastq[..], &out[..]);
+        let mut out = Vec::new();
+        reader.find_name(b"r2", 1, &mut out).unwrap();
+        assert_eq!(&b"\n\r\n@r2\nACGT\n+\nIIII\n"[..], &out[..]);
+    }
+    #[test]
+    fn test_errors() {
+        let mut archive = Vec::new();
+        let err = compress(
+            &b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nIII\n"[..],
+            &mut archive,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            "record 2 at byte 16: the sequence has 4 bases but the quality has 3 characters",
+            err.to_string()
+        );
+
+        // Two blocks, cut or damaged anywhere.
+        let fastq = b"@r1\nACGTNACGT\n+\nIIIIIIIII\n@r2\nTTTT\n+\nIIII\n@r3\nGG\n+\nII\n";
+        let mut archive = Vec::new();
+        compress_blocks(&fastq[..], &mut archive, 2).unwrap();
+        let mut out = Vec::new();
+        let err = decompress(&fastq[..], &mut out).err().unwrap();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        for len in 0..archive.len() {
+            assert!(decompress(&archive[..len], &mut Vec::new()).is_err());
+            assert!(ArchiveReader::open(Cursor::new(&archive[..len])).is_err());
+        }
+        // Damage is found, or is in bytes the decoding does not depend on.
+        for idx in 5..archive.len() {
+            let mut damaged = archive.clone();
+            damaged[idx] ^= 0x55;
+            let mut out = Vec::new();
+            if decompress(&damaged[..], &mut out).is_ok() {
+                assert_eq!(&fastq[..], &out[..]);
+            }
+            let mut out = Vec::new();
+            let viewed = ArchiveReader::open(Cursor::new(&damaged))
+                .and_then(|mut reader| reader.view(0..3, 2, &mut out));
+            if viewed.is_ok() {
+                assert_eq!(&fastq[..], &out[..]);
+            }
+        }
+    }
+    #[test]
+    fn test_name_filter() {
+        let ids: Vec<String> = (0..1000)
+            .map(|i| format!("SIM:1:FCX:1:1101:{}:2000", i))
+            .collect();
+        let hashes: Vec<u64> = ids.iter().map(|id| hash_read_id(id.as_bytes())).collect();
+        let filter = NameFilter::new(&hashes);
+        assert_eq!(1024, filter.bits.len());
+        assert!(ids.iter().all(|id| filter.may_contain(id.as_bytes())));
+        let false_positives = (1000..11000)
+            .filter(|i| filter.may_contain(format!("SIM:1:FCX:1:1101:{}:2000", i).as_bytes()))
+            .count();
+        assert!(false_positives < 400, "{} false positives", false_positives);
+    }
+    #[test]
+    fn test_archive_reader() {
+        let record = |i: u64| {
+            format!(
+                "@r{}/1 x\nACGT\n+\n{}\n",
+                i,
+                if i % 2 == 0 { "IIII" } else { "5555" }
+            )
+        };
+        let fastq: String = (1..=10).map(record).collect();
+        let mut archive = Vec::new();
+        assert_eq!(
+            10,
+            compress_blocks(fastq.as_bytes(), &mut archive, 4).unwrap()
+        );
+        let mut reader = ArchiveReader::open(Cursor::new(&archive)).unwrap();
+        assert_eq!(10, reader.get_record_count());
+        let index = reader.get_index();
+        assert_eq!(3, index.len());
+        assert_eq!(
+            vec![0, 4, 8],
+            index.iter().map(|e| e.first_record).collect::<Vec<u64>>()
+        );
+        assert_eq!(b"r5/1 x".to_vec(), index[1].first_name);
+
+        for threads in 1..4 {
+            let mut out = Vec::new();
+            assert_eq!(5, reader.view(2..7, threads, &mut out).unwrap());
+            assert_eq!((3..=7).map(record).collect::<String>().into_bytes(), out);
+            let mut out = Vec::new();
+            assert_eq!(10, reader.view(0..100, threads, &mut out).unwrap());
+            assert_eq!(fastq.as_bytes(), &out[..]);
+        }
+        let mut out = Vec::new();
+        assert_eq!(0, reader.view(10..20, 2, &mut out).unwrap());
+        assert!(out.is_empty());
+
+        // By read ID or by the whole name, in sorted and unsorted archives.
+        let mut out = Vec::new();
+        assert_eq!(vec![6], reader.find_name(b"r7/1", 2, &mut out).unwrap());
+        assert_eq!(record(7).into_bytes(), out);
+        let mut out = Vec::new();
+        assert_eq!(vec![9], reader.find_name(b"r10/1 x", 1, &mut out).unwrap());
+        assert_eq!(record(10).into_bytes(), out);
+        assert!(reader
+            .find_name(b"r0", 1, &mut Vec::new())
+            .unwrap()
+            .is_empty());
+
+        let unsorted = format!("{}{}{}", record(5), record(1), record(5));
+        let mut archive = Vec::new();
+        compress_blocks(unsorted.as_bytes(), &mut archive, 1).unwrap();
+        let mut reader = ArchiveReader::open(Cursor::new(&archive)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(vec![0, 2], reader.find_name(b"r5/1", 2, &mut out).unwrap());
+        assert_eq!(format!("{}{}", record(5), record(5)).into_bytes(), out);
+
+        // The block of r1 is left alone by the name filter, even damaged.
+        let offset = reader.get_index()[1].offset as usize;
+        let mut damaged = archive.clone();
+        for byte in &mut damaged[(offset + 20)..(offset + 40)] {
+            *byte ^= 0x55;
+        }
+        let mut reader = ArchiveReader::open(Cursor::new(&damaged)).unwrap();
+        let mut out = Vec::new();
+        assert_eq!(vec![0, 2], reader.find_name(b"r5/1", 2, &mut out).unwrap());
+        assert!(reader.find_name(b"r1/1", 2, &mut Vec::new()).is_err());
+    }
+}
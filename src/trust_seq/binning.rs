@@ -0,0 +1,440 @@
+use super::qc;
+use super::qc::{PerBaseQualityReport, PerSequenceQualityReport, QCModule, QCResult};
+use super::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use super::utils::{check_fastq_record, format_double, trim_line_end};
+use super::utils::{FastQError, LineReader, Sequence};
+use std::cmp;
+use std::io;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Qualities are binned as Phred scores in the Sanger / Illumina 1.8+
+/// encoding. Characters below the offset are left as they are.
+pub const PHRED_OFFSET: u8 = 33;
+// The 8 level binning of Illumina: the lowest score of each bin and the
+// score it is binned to. Scores 0 and 1, of no calls, are the 8th level.
+const ILLUMINA_BINS: [(u8, u8); 7] = [
+    (2, 6),
+    (10, 15),
+    (20, 22),
+    (25, 27),
+    (30, 33),
+    (35, 37),
+    (40, 40),
+];
+
+/// A map of every quality character to the one of its bin.
+#[derive(Clone)]
+pub struct QualityBins {
+    map: [u8; 256],
+}
+impl std::fmt::Debug for QualityBins {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        return f.debug_list().entries(self.map.iter()).finish();
+    }
+}
+impl QualityBins {
+    /// Bins that keep every score.
+    fn identity() -> QualityBins {
+        let mut map = [0u8; 256];
+        for (idx, c) in map.iter_mut().enumerate() {
+            *c = idx as u8;
+        }
+        return QualityBins { map: map };
+    }
+    /// Sets the scores `low` to `high` to `value`.
+    fn set(&mut self, low: u8, high: u8, value: u8) {
+        let value = cmp::min(value as usize + PHRED_OFFSET as usize, 126) as u8;
+        let high = cmp::min(high as usize + PHRED_OFFSET as usize, 255);
+        for c in (low as usize + PHRED_OFFSET as usize)..(high + 1) {
+            self.map[c] = value;
+        }
+    }
+    /// The 8 level binning of Illumina: 2-9 to 6, 10-19 to 15, 20-24 to
+    /// 22, 25-29 to 27, 30-34 to 33, 35-39 to 37, and 40 and up to 40.
+    pub fn illumina() -> QualityBins {
+        let mut bins = QualityBins::identity();
+        for (idx, &(low, value)) in ILLUMINA_BINS.iter().enumerate() {
+            let high = ILLUMINA_BINS.get(idx + 1).map_or(255, |b| b.0 - 1);
+            bins.set(low, high, value);
+        }
+        return bins;
+    }
+    /// Parses `illumina`, or bins of Phred scores as in
+    /// `0-9:6,10-29:20,30-:37`, where `30-` has no upper bound. Scores in
+    /// no bin are kept.
+    pub fn from_spec(spec: &str) -> io::Result<QualityBins> {
+        if spec == "illumina" {
+            return Ok(QualityBins::illumina());
+        }
+        let invalid = || {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is not a valid quality binning! Use illumina or bins such as 0-9:6,10-29:20,30-:37.",
+                    spec
+                ),
+            )
+        };
+        let mut bins = QualityBins::identity();
+        for bin in spec.split(',') {
+            let mut parts = bin.trim().splitn(2, ':');
+            let range = parts.next().ok_or_else(invalid)?;
+            let value = parts.next().ok_or_else(invalid)?;
+            let value = value.parse::<u8>().map_err(|_| invalid())?;
+            let (low, high) = match range.find('-') {
+                Some(pos) => (&range[..pos], &range[(pos + 1)..]),
+                None => (range, range),
+            };
+            let low = low.parse::<u8>().map_err(|_| invalid())?;
+            let high = if high.is_empty() {
+                255
+            } else {
+                high.parse::<u8>().map_err(|_| invalid())?
+            };
+            if high < low {
+                return Err(invalid());
+            }
+            bins.set(low, high, value);
+        }
+        return Ok(bins);
+    }
+    pub fn apply(&self, quality: &mut [u8]) {
+        for q in quality {
+            *q = self.map[*q as usize];
+        }
+    }
+}
+
+/// How one value of a QC module moved with binning.
+#[derive(Serialize)]
+pub struct ModuleImpact {
+    pub module: &'static str,
+    pub before: QCResult,
+    pub after: QCResult,
+    pub measure: &'static str,
+    /// The largest change of the measure, over the position groups of
+    /// the per base module, rounded to 2 decimals.
+    pub largest_shift: f64,
+}
+/// Rounds a change of a measure to 2 decimals, so the difference of two
+/// means does not show float noise such as 0.7800000000000011.
+fn round_shift(shift: f64) -> f64 {
+    return (shift * 100.0).round() / 100.0;
+}
+/// How binning changed the quality modules.
+#[derive(Serialize)]
+pub struct BinningImpact {
+    pub read_count: u64,
+    pub quality_count: u64,
+    pub changed_quality_count: u64,
+    pub modules: Vec<ModuleImpact>,
+}
+impl BinningImpact {
+    /// Whether a quality module passes, warns or fails differently.
+    pub fn status_changed(&self) -> bool {
+        return self.modules.iter().any(|m| m.before != m.after);
+    }
+    pub fn print_text_report(&self, w: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(w, "#Reads\t{}", self.read_count)?;
+        writeln!(
+            w,
+            "#Qualities changed\t{} of {}",
+            self.changed_quality_count, self.quality_count
+        )?;
+        writeln!(w, "#Module\tBefore\tAfter\tMeasure\tLargest Shift")?;
+        for m in &self.modules {
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}",
+                m.module,
+                m.before.get_label(),
+                m.after.get_label(),
+                m.measure,
+                format_double(m.largest_shift)
+            )?;
+        }
+        writeln!(
+            w,
+            "#Status changed\t{}",
+            if self.status_changed() { "yes" } else { "no" }
+        )?;
+        return Ok(());
+    }
+}
+
+/// Bins the qualities of a FASTQ file as it is read, leaving every other
+/// byte as it is. The per base and per sequence quality modules see the
+/// qualities before and after binning, for `get_impact`.
+pub struct QualityBinner<R: Read> {
+    reader: LineReader<R>,
+    bins: QualityBins,
+    // The binned record being read, from pos on.
+    record: Vec<u8>,
+    pos: usize,
+    quality: Vec<u8>,
+    original_modules: Vec<Box<QCModule>>,
+    binned_modules: Vec<Box<QCModule>>,
+    record_count: u64,
+    offset: u64,
+    quality_count: u64,
+    changed_quality_count: u64,
+}
+impl<R: Read> QualityBinner<R> {
+    pub fn new(read: R, bins: QualityBins, config: &Arc<TrustSeqConfig>) -> QualityBinner<R> {
+        return QualityBinner {
+            reader: LineReader::new(read, 4096),
+            bins: bins,
+            record: Vec::new(),
+            pos: 0,
+            quality: Vec::new(),
+            original_modules: qc::create_quality_qcmodules(config),
+            binned_modules: qc::create_quality_qcmodules(config),
+            record_count: 0,
+            offset: 0,
+            quality_count: 0,
+            changed_quality_count: 0,
+        };
+    }
+    /// Reads and bins the next record, returning false at the end. Blank
+    /// lines between records are left out.
+    fn next_record(&mut self) -> io::Result<bool> {
+        self.offset += self.reader.skip_blank_lines()?.iter().sum::<usize>() as u64;
+        let lines = self.reader.read_lines(4)?;
+        if lines.is_empty() {
+            return Ok(false);
+        }
+        self.record_count += 1;
+        if let Some(kind) = check_fastq_record(&lines) {
+            return Err(FastQError {
+                kind: kind,
+                record: self.record_count,
+                offset: self.offset,
+            }
+            .into());
+        }
+        let quality = trim_line_end(lines[3]);
+        self.quality.clear();
+        self.quality.extend_from_slice(quality);
+        self.bins.apply(&mut self.quality);
+        self.quality_count += quality.len() as u64;
+        self.changed_quality_count += quality
+            .iter()
+            .zip(self.quality.iter())
+            .filter(|q| q.0 != q.1)
+            .count() as u64;
+
+        let mut seq = Sequence {
+            id: trim_line_end(lines[0]),
+            sequence: trim_line_end(lines[1]),
+            quality: quality,
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        };
+        qc::process_sequence(&mut self.original_modules, &seq);
+        seq.quality = &self.quality;
+        qc::process_sequence(&mut self.binned_modules, &seq);
+
+        self.record.clear();
+        self.pos = 0;
+        for line in &lines[..3] {
+            self.record.extend_from_slice(line);
+            self.offset += line.len() as u64;
+        }
+        self.record.extend_from_slice(&self.quality);
+        self.record.extend_from_slice(&lines[3][quality.len()..]);
+        self.offset += lines[3].len() as u64;
+        return Ok(true);
+    }
+    /// Compares the quality modules before and after binning, over the
+    /// records read so far.
+    pub fn get_impact(&self) -> Result<BinningImpact, TrustSeqErr> {
+        let original = qc::calculate_reports(&self.original_modules)?;
+        let binned = qc::calculate_reports(&self.binned_modules)?;
+        let mut modules = Vec::new();
+        let per_base = (
+            qc::get_report::<PerBaseQualityReport>(&original),
+            qc::get_report::<PerBaseQualityReport>(&binned),
+        );
+        if let (Some(before), Some(after)) = per_base {
+            let pairs: Vec<_> = before
+                .quality_data
+                .iter()
+                .zip(after.quality_data.iter())
+                .collect();
+            let largest = |f: &Fn(&qc::Quality) -> f64| {
+                pairs
+                    .iter()
+                    .map(|p| (f(p.1) - f(p.0)).abs())
+                    .fold(0.0, f64::max)
+            };
+            let measures: [(&'static str, f64); 3] = [
+                ("Median", largest(&|q| q.median)),
+                ("Lower Quartile", largest(&|q| q.lower_quartile)),
+                ("Mean", largest(&|q| q.mean)),
+            ];
+            for &(measure, shift) in measures.iter() {
+                modules.push(ModuleImpact {
+                    module: "Per base sequence quality",
+                    before: before.status,
+                    after: after.status,
+                    measure: measure,
+                    largest_shift: round_shift(shift),
+                });
+            }
+        }
+        let per_sequence = (
+            qc::get_report::<PerSequenceQualityReport>(&original),
+            qc::get_report::<PerSequenceQualityReport>(&binned),
+        );
+        if let (Some(before), Some(after)) = per_sequence {
+            // The most common mean quality, which the status is taken from.
+            let mode = |r: &PerSequenceQualityReport| {
+                let mut best = (0, 0);
+                for &(quality, count) in &r.qualities {
+                    if count > best.1 {
+                        best = (quality, count);
+                    }
+                }
+                best.0 as f64
+            };
+            modules.push(ModuleImpact {
+                module: "Per sequence quality scores",
+                before: before.status,
+                after: after.status,
+                measure: "Mode",
+                largest_shift: round_shift((mode(after) - mode(before)).abs()),
+            });
+        }
+        return Ok(BinningImpact {
+            read_count: self.record_count,
+            quality_count: self.quality_count,
+            changed_quality_count: self.changed_quality_count,
+            modules: modules,
+        });
+    }
+}
+impl<R: Read> Read for QualityBinner<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.record.len() && !self.next_record()? {
+            return Ok(0);
+        }
+        let len = cmp::min(buf.len(), self.record.len() - self.pos);
+        buf[..len].copy_from_slice(&self.record[self.pos..(self.pos + len)]);
+        self.pos += len;
+        return Ok(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QualityBinner, QualityBins};
+    use crate::trust_seq::qc::QCResult;
+    use crate::trust_seq::trust_seq::TrustSeqConfig;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_quality_bins() {
+        let mut quality = b"!\"#*+,5>?@ABHIJ~".to_vec();
+        QualityBins::illumina().apply(&mut quality);
+        assert_eq!(&b"!\"''007<BBBBFIII"[..], &quality[..]);
+        let bins = QualityBins::from_spec("0-9:6, 20:25,30-:37").unwrap();
+        let mut quality = b" !*+5?I".to_vec();
+        bins.apply(&mut quality);
+        assert_eq!(&b" ''+:FF"[..], &quality[..]);
+        assert!(QualityBins::from_spec("10-5:7").is_err());
+        assert!(QualityBins::from_spec("illumina8").is_err());
+        assert!(QualityBins::from_spec("1-2").is_err());
+    }
+    #[test]
+    fn test_quality_binner() {
+        let fastq = b"@r1\r\nACGTACGTAC\r\n+r1\r\nIIIII#####\r\n@r2\nACGT\n+\n@A5+";
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut binner = QualityBinner::new(&fastq[..], QualityBins::illumina(), &config);
+        let mut binned = Vec::new();
+        binner.read_to_end(&mut binned).unwrap();
+        assert_eq!(
+            &b"@r1\r\nACGTACGTAC\r\n+r1\r\nIIIII'''''\r\n@r2\nACGT\n+\nBB70"[..],
+            &binned[..]
+        );
+        let impact = binner.get_impact().unwrap();
+        assert_eq!(
+            (2, 14, 9),
+            (
+                impact.read_count,
+                impact.quality_count,
+                impact.changed_quality_count
+            )
+        );
+        // Blank lines between records are left out.
+        let fastq = b"@r1\nACGT\n+\nIIII\n\n@r2\nACGT\n+\nIIII\n\n";
+        let mut binner = QualityBinner::new(&fastq[..], QualityBins::illumina(), &config);
+        let mut binned = Vec::new();
+        binner.read_to_end(&mut binned).unwrap();
+        assert_eq!(
+            &b"@r1\nACGT\n+\nIIII\n@r2\nACGT\n+\nIIII\n"[..],
+            &binned[..]
+        );
+        // Percentiles need more than 100 reads at a position.
+        let impact_of = |qualities: &[&str]| {
+            let mut fastq = String::new();
+            for i in 0..150 {
+                let quality = qualities[i % qualities.len()];
+                fastq.push_str(&format!("@r{}\nACGTACGTAC\n+\n{}\n", i, quality));
+            }
+            let mut binner = QualityBinner::new(fastq.as_bytes(), QualityBins::illumina(), &config);
+            binner.read_to_end(&mut Vec::new()).unwrap();
+            binner.get_impact().unwrap()
+        };
+        let impact = impact_of(&["IIIII#####"]);
+        assert_eq!(4, impact.modules.len());
+        let shifts: Vec<(&str, f64)> = impact
+            .modules
+            .iter()
+            .map(|m| (m.measure, m.largest_shift))
+            .collect();
+        assert_eq!(
+            vec![
+                ("Median", 4.0),
+                ("Lower Quartile", 4.0),
+                ("Mean", 4.0),
+                ("Mode", 2.0)
+            ],
+            shifts
+        );
+        assert_eq!(QCResult::Fail, impact.modules[0].before);
+        assert!(!impact.status_changed());
+        let mut text = Vec::new();
+        impact.print_text_report(&mut text).unwrap();
+        assert!(String::from_utf8(text)
+            .unwrap()
+            .ends_with("#Status changed\tno\n"));
+
+        // A mean quality of 20 fails, 22 only warns.
+        let impact = impact_of(&["5555555555"]);
+        assert_eq!(
+            (QCResult::Fail, QCResult::Warn),
+            (impact.modules[3].before, impact.modules[3].after)
+        );
+        assert!(impact.status_changed());
+
+        // The mean of 40, 30 and 20 goes from 30 to 31.666666666666668.
+        let impact = impact_of(&["IIIIIIIIII", "??????????", "5555555555"]);
+        assert_eq!(1.67, impact.modules[2].largest_shift);
+        let mut text = Vec::new();
+        impact.print_text_report(&mut text).unwrap();
+        assert!(String::from_utf8(text).unwrap().contains("\tMean\t1.67\n"));
+
+        let mut binner = QualityBinner::new(
+            &b"@r1\nACGT\n+\nIII\n"[..],
+            QualityBins::illumina(),
+            &config,
+        );
+        assert!(binner.read_to_end(&mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use flate2::read::MultiGzDecoder;
+use std::io::{BufReader, Cursor, Read, Result};
+
+const GZIP_MAGIC: [u8; 3] = [0x1f, 0x8b, 0x08];
+const GZIP_FLAG_EXTRA: u8 = 0x04;
+// gzip header (10 bytes) + XLEN (2 bytes) + first subfield id and length (4 bytes)
+const MAGIC_LENGTH: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bgzf,
+}
+
+impl Compression {
+    pub fn detect(magic: &[u8]) -> Compression {
+        if magic.len() < GZIP_MAGIC.len() || magic[0..3] != GZIP_MAGIC {
+            return Compression::None;
+        }
+        // BGZF is a series of gzip members whose first extra subfield is 'BC'.
+        if magic.len() >= MAGIC_LENGTH
+            && magic[3] & GZIP_FLAG_EXTRA != 0
+            && magic[12] == 'B' as u8
+            && magic[13] == 'C' as u8
+        {
+            return Compression::Bgzf;
+        }
+        return Compression::Gzip;
+    }
+}
+
+fn read_magic<R: Read>(read: &mut R) -> Result<Vec<u8>> {
+    let mut magic = vec![0u8; MAGIC_LENGTH];
+    let mut len = 0;
+    while len < MAGIC_LENGTH {
+        let n = read.read(&mut magic[len..])?;
+        if n == 0 {
+            break;
+        }
+        len += n;
+    }
+    magic.truncate(len);
+    return Ok(magic);
+}
+
+/// Wraps `read` with the decompressor for `compression`, as declared by
+/// the user rather than detected.
+pub fn open_as<R: Read + 'static>(read: R, compression: Compression) -> Box<Read> {
+    return match compression {
+        Compression::None => Box::new(read),
+        // Multi-member gzip and BGZF are both concatenated gzip members.
+        Compression::Gzip | Compression::Bgzf => {
+            Box::new(MultiGzDecoder::new(BufReader::new(read)))
+        }
+    };
+}
+
+/// Wraps `read` with a decompressor chosen from its leading bytes.
+/// The sniffed bytes are replayed, so the source never has to be seekable.
+pub fn open_decompressed<R: Read + 'static>(mut read: R) -> Result<(Compression, Box<Read>)> {
+    let magic = read_magic(&mut read)?;
+    let compression = Compression::detect(&magic);
+    let stream = Cursor::new(magic).chain(read);
+    return Ok((compression, open_as(stream, compression)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::GzBuilder;
+    use std::io::Write;
+
+    const FASTQ: &'static [u8] = b"@read1\nACGT\n+\nIIII\n@read2\nTTGA\n+\nIIII\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        return encoder.finish().unwrap();
+    }
+    fn bgzf_block(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzBuilder::new()
+            .extra(vec!['B' as u8, 'C' as u8, 2, 0, 0, 0])
+            .write(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        return encoder.finish().unwrap();
+    }
+    fn read_all(data: Vec<u8>) -> (Compression, Vec<u8>) {
+        let (compression, mut reader) = open_decompressed(Cursor::new(data)).unwrap();
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf).unwrap();
+        return (compression, buf);
+    }
+
+    #[test]
+    fn test_open_as() {
+        let mut buf = Vec::new();
+        open_as(Cursor::new(gzip(FASTQ)), Compression::Gzip)
+            .read_to_end(&mut buf)
+            .unwrap();
+        assert_eq!(FASTQ.to_vec(), buf);
+    }
+    #[test]
+    fn test_plain() {
+        assert_eq!(
+            (Compression::None, FASTQ.to_vec()),
+            read_all(FASTQ.to_vec())
+        );
+        assert_eq!(
+            (Compression::None, b"@r".to_vec()),
+            read_all(b"@r".to_vec())
+        );
+    }
+    #[test]
+    fn test_gzip() {
+        assert_eq!((Compression::Gzip, FASTQ.to_vec()), read_all(gzip(FASTQ)));
+    }
+    #[test]
+    fn test_multi_member_gzip() {
+        let mut data = gzip(&FASTQ[..20]);
+        data.extend(gzip(&FASTQ[20..]));
+        assert_eq!((Compression::Gzip, FASTQ.to_vec()), read_all(data));
+    }
+    #[test]
+    fn test_bgzf() {
+        let mut data = bgzf_block(&FASTQ[..20]);
+        data.extend(bgzf_block(&FASTQ[20..]));
+        data.extend(bgzf_block(b""));
+        assert_eq!((Compression::Bgzf, FASTQ.to_vec()), read_all(data));
+    }
+}
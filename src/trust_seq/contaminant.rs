@@ -1,6 +1,7 @@
 use super::utils;
 use std::cmp;
 use std::io::BufRead;
+use std::io::Result;
 use std::vec::Vec;
 
 #[derive(Debug, Clone, Copy)]
@@ -10,27 +11,27 @@ pub struct ContaminantHit<'a> {
     pub length: usize,
     pub percent_id: u32,
 }
-fn get_length<'a>(h: Option<ContaminantHit<'a>>) -> usize {
-    if let Some(x) = h {
-        x.length
-    } else {
-        0
-    }
-}
+/// Like FastQC, the first contaminant in the list wins among equally long
+/// hits unless a later one has a higher identity.
 pub fn find_contaminant<'a>(
     contaminants: &'a Vec<Contaminant>,
     query: &[u8],
 ) -> Option<ContaminantHit<'a>> {
-    match contaminants
-        .iter()
-        .map(|c| c.find_match(query))
-        .max_by_key(|h| get_length(*h))
-    {
-        Some(v) => v,
-        None => None,
+    let mut best_hit: Option<ContaminantHit> = None;
+    for hit in contaminants.iter().filter_map(|c| c.find_match(query)) {
+        let is_better = match best_hit {
+            Some(b) => {
+                b.length < hit.length || (b.length == hit.length && b.percent_id < hit.percent_id)
+            }
+            None => true,
+        };
+        if is_better {
+            best_hit = Some(hit);
+        }
     }
+    return best_hit;
 }
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Contaminant {
     pub name: String,
     forward: String,
@@ -109,12 +110,31 @@ mod tests {
         );
     }
     #[test]
+    fn test_load_contaminants() {
+        let cons =
+            Contaminant::load_contaminants(&b"# comment\nPrimer 1\t\tacgtn\n\n"[..]).unwrap();
+        assert_eq!(1, cons.len());
+        assert_eq!("Primer 1", cons[0].name);
+        assert_eq!("ACGTN", cons[0].forward);
+        let err = Contaminant::load_contaminants(&b"# comment\nPrimer 1 ACGT\n"[..]).unwrap_err();
+        assert_eq!(
+            "line 2: expected a name and a sequence separated by a tab",
+            err.to_string()
+        );
+        let err = Contaminant::load_contaminants(&b"Primer 1\tACGU\n"[..]).unwrap_err();
+        assert_eq!(
+            "line 1: 'U' in the sequence of 'Primer 1' is not a base",
+            err.to_string()
+        );
+    }
+    #[test]
     fn test_find_match() {
         let c = Contaminant::new("Test", "AGCTTCGA");
         let hit = c.find_match("AGCTTCGA".as_bytes());
         assert_eq!(0, hit.unwrap().direction);
         let hit2 = c.find_match("TCGAAGCT".as_bytes());
         assert_eq!(1, hit2.unwrap().direction);
+        assert!(c.find_match("AGCT".as_bytes()).is_none());
     }
     #[test]
     fn test_find_match3() {
@@ -136,6 +156,23 @@ mod tests {
         let hit2 = c.find_match("AAACAAGCAGAAGACGGCATACGAGCTCTTCCGATCAAA".as_bytes());
         assert_eq!(33, hit2.unwrap().length);
     }
+    #[test]
+    fn test_find_contaminant() {
+        let cons = vec![
+            Contaminant::new("Mismatch", "AGATCGGAAGAGCACTCGTCTGAACTCCAGTCAC"),
+            Contaminant::new("First", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCAC"),
+            Contaminant::new("Second", "AGATCGGAAGAGCACACGTCTGAACTCCAGTCAC"),
+        ];
+        let query = b"ATCGGAAGAGCACACGTCTGAACTCCAGTCA";
+        let hit = cons[0].find_match(query).unwrap();
+        assert_eq!((31, 96), (hit.length, hit.percent_id));
+        let hit = find_contaminant(&cons, query).unwrap();
+        assert_eq!(
+            ("First", 31, 100),
+            (&hit.contaminant.name[..], hit.length, hit.percent_id)
+        );
+        assert!(find_contaminant(&cons, b"CCCCCCCCCAAATCGGAAAAACACACCCCTA").is_none());
+    }
 }
 impl Contaminant {
     pub fn new(name: &str, sequence: &str) -> Contaminant {
@@ -164,7 +201,7 @@ impl Contaminant {
             }
         }
         let s: i32 = 20 - self.forward.len() as i32;
-        let e: i32 = (query.len() - 20) as i32;
+        let e: i32 = query.len() as i32 - 20;
         let mut best_len = 20;
         let mut best_hit: Option<ContaminantHit> = None;
         for (idx, seq) in [&self.forward, &self.reverse].iter().enumerate() {
@@ -196,28 +233,11 @@ impl Contaminant {
         }
         return best_hit;
     }
-    pub fn load_contaminants<R: BufRead>(reader: R) -> Vec<Contaminant> {
-        let mut cons = Vec::new();
-        for rslt in reader.lines() {
-            let line = match rslt {
-                Ok(l) => l,
-                _ => String::new(),
-            };
-            if line.starts_with('#') || line.len() == 0 {
-                continue;
-            }
-            let mut key = String::new();
-            let mut value = String::new();
-            match line.find('\t') {
-                Some(i) => key.push_str(&line[0..i]),
-                None => continue,
-            }
-            match line.rfind('\t') {
-                Some(i) => value.push_str(&line[(i + 1)..]),
-                None => continue,
-            }
-            cons.push(Contaminant::new(&key, &value));
-        }
-        return cons;
+    pub fn load_contaminants<R: BufRead>(reader: R) -> Result<Vec<Contaminant>> {
+        let entries = utils::read_sequence_list(reader)?;
+        return Ok(entries
+            .iter()
+            .map(|e| Contaminant::new(&e.0, &e.1))
+            .collect());
     }
 }
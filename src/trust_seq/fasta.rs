@@ -0,0 +1,100 @@
+use super::utils::{trim_line_end, LineReader, Sequence, SequenceReader};
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::mem;
+
+/// Reads FASTA records, whose sequence may span several lines, as
+/// `Sequence`s with an empty quality.
+pub struct FastaReader<T: Read> {
+    reader: LineReader<T>,
+    id: Vec<u8>,
+    sequence: Vec<u8>,
+    // The header of the next record, read at the end of the last one.
+    next_id: Vec<u8>,
+    line_count: u64,
+}
+impl<T: Read> FastaReader<T> {
+    pub fn new(read: T) -> FastaReader<T> {
+        return FastaReader {
+            reader: LineReader::new(read, 4096),
+            id: Vec::new(),
+            sequence: Vec::new(),
+            next_id: Vec::new(),
+            line_count: 0,
+        };
+    }
+    pub fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        while self.next_id.is_empty() {
+            let line = match self.reader.read_line()? {
+                Some(l) => trim_line_end(l),
+                None => return Ok(None),
+            };
+            self.line_count += 1;
+            if line.is_empty() {
+                continue;
+            }
+            if line[0] != '>' as u8 {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "line {} is not a FASTA header starting with '>'",
+                        self.line_count
+                    ),
+                ));
+            }
+            self.next_id.extend_from_slice(line);
+        }
+        mem::swap(&mut self.id, &mut self.next_id);
+        self.next_id.clear();
+        self.sequence.clear();
+        while let Some(line) = self.reader.read_line()? {
+            let line = trim_line_end(line);
+            self.line_count += 1;
+            if line.first() == Some(&('>' as u8)) {
+                self.next_id.extend_from_slice(line);
+                break;
+            }
+            // Assemblies mark repeats in lower case.
+            self.sequence
+                .extend(line.iter().map(|c| c.to_ascii_uppercase()));
+        }
+        return Ok(Some(Sequence {
+            id: &self.id,
+            sequence: &self.sequence,
+            quality: &[],
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        }));
+    }
+}
+impl<T: Read> SequenceReader for FastaReader<T> {
+    fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        return FastaReader::next_seq(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FastaReader;
+
+    #[test]
+    fn test_fasta_reader() {
+        let fasta = b">contig1 len=10\r\nACGTa\r\ncgtNN\r\n\n>contig2\n>contig3\nTTTT";
+        let mut reader = FastaReader::new(&fasta[..]);
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(&b">contig1 len=10"[..], seq.id);
+        assert_eq!(&b"ACGTACGTNN"[..], seq.sequence);
+        assert!(seq.quality.is_empty());
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!((&b">contig2"[..], &b""[..]), (seq.id, seq.sequence));
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!((&b">contig3"[..], &b"TTTT"[..]), (seq.id, seq.sequence));
+        assert!(reader.next_seq().unwrap().is_none());
+
+        let mut reader = FastaReader::new(&b"\nACGT\n"[..]);
+        assert!(reader.next_seq().is_err());
+    }
+}
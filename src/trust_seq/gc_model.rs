@@ -6,9 +6,9 @@ pub struct GCModel {
     models: Vec<Vec<GCModelValue>>,
 }
 impl GCModel {
-    pub fn add_value(&self, gc_count: usize, gc_distribution: &mut [f64]) {
+    pub fn add_value(&self, gc_count: usize, count: u64, gc_distribution: &mut [f64]) {
         for val in &self.models[gc_count] {
-            gc_distribution[val.percentage] += val.increment;
+            gc_distribution[val.percentage] += val.increment * count as f64;
         }
     }
     pub fn new(read_length: usize) -> GCModel {
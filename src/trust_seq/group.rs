@@ -1,4 +1,5 @@
 use std::cmp;
+use std::fmt;
 
 #[derive(Serialize)]
 pub struct BaseGroup {
@@ -10,6 +11,16 @@ pub enum GroupType {
     None,
     Linear,
     Exponential,
+    /// Groups that widen tenfold every decade, for reads of 100kb and more.
+    Logarithmic,
+}
+impl fmt::Display for BaseGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lower_count == self.upper_count {
+            return write!(f, "{}", self.lower_count);
+        }
+        return write!(f, "{}-{}", self.lower_count, self.upper_count);
+    }
 }
 impl BaseGroup {
     fn make_ungrouped_groups(max_len: usize) -> Vec<BaseGroup> {
@@ -82,11 +93,45 @@ impl BaseGroup {
         }
         return v;
     }
+    fn make_logarithmic_groups(max_len: usize) -> Vec<BaseGroup> {
+        let mut v = BaseGroup::make_ungrouped_groups(cmp::min(9, max_len));
+        let mut start_pos = 10;
+        // 18 groups per decade: 10-14, 15-19, ..., 100-149, ...
+        let mut interval = 5;
+        while start_pos <= max_len {
+            let end_pos = cmp::min(start_pos + interval - 1, max_len);
+            v.push(BaseGroup {
+                lower_count: start_pos,
+                upper_count: end_pos,
+            });
+            start_pos += interval;
+            if start_pos == interval * 20 {
+                interval *= 10;
+            }
+        }
+        return v;
+    }
+    /// The index of the logarithmic group holding the base at `position`,
+    /// counted from 0, whatever the length of the reads. Modules keep long
+    /// reads by these groups rather than by position.
+    pub fn get_logarithmic_index(position: usize) -> usize {
+        if position < 9 {
+            return position;
+        }
+        let mut start_pos = 10;
+        let mut index = 9;
+        while position + 1 >= start_pos * 10 {
+            start_pos *= 10;
+            index += 18;
+        }
+        return index + (position + 1 - start_pos) / (start_pos / 2);
+    }
     pub fn make_base_groups(group_type: &GroupType, max_len: usize) -> Vec<BaseGroup> {
         match *group_type {
             GroupType::None => BaseGroup::make_ungrouped_groups(max_len),
             GroupType::Linear => BaseGroup::make_linear_groups(max_len),
             GroupType::Exponential => BaseGroup::make_exponential_groups(max_len),
+            GroupType::Logarithmic => BaseGroup::make_logarithmic_groups(max_len),
         }
     }
 }
@@ -113,4 +158,22 @@ mod tests {
         check_base_groups(GroupType::Linear, 140, 36);
         check_base_groups(GroupType::Linear, 500, 59);
     }
+    #[test]
+    fn test_logarithmic_group() {
+        check_base_groups(GroupType::Logarithmic, 5, 5);
+        check_base_groups(GroupType::Logarithmic, 99, 27);
+        check_base_groups(GroupType::Logarithmic, 100, 28);
+        check_base_groups(GroupType::Logarithmic, 150_000, 9 + 18 * 4 + 2);
+        let groups = BaseGroup::make_base_groups(&GroupType::Logarithmic, 1000);
+        assert_eq!("950-999", groups[groups.len() - 2].to_string());
+        assert_eq!("1000", groups[groups.len() - 1].to_string());
+    }
+    #[test]
+    fn test_logarithmic_index() {
+        let groups = BaseGroup::make_base_groups(&GroupType::Logarithmic, 150_000);
+        for (idx, group) in groups.iter().enumerate() {
+            assert_eq!(idx, BaseGroup::get_logarithmic_index(group.lower_count - 1));
+            assert_eq!(idx, BaseGroup::get_logarithmic_index(group.upper_count - 1));
+        }
+    }
 }
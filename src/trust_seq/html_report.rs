@@ -0,0 +1,136 @@
+use super::qc::{QCReport, QCResult, FASTQC_VERSION};
+use super::svg::escape_xml;
+use super::trust_seq::TrustSeqErr;
+use std::io::Write;
+
+const STYLE: &'static str = r#"
+body { font-family: Arial, sans-serif; color: #222222; margin: 0; }
+.header { background: #00346e; color: #ffffff; padding: 12px 20px; position: fixed; top: 0; left: 0; right: 0; height: 40px; }
+.header .title { font-size: 24px; font-weight: bold; }
+.header .file { float: right; font-size: 14px; margin-top: 6px; }
+.summary { position: fixed; top: 64px; left: 0; width: 260px; bottom: 30px; overflow: auto; padding: 10px 20px; background: #f4f4f4; border-right: 1px solid #cccccc; }
+.summary ul { list-style: none; padding: 0; }
+.summary li { margin: 6px 0; }
+.summary a { color: #000000; text-decoration: none; }
+.main { margin: 64px 0 30px 300px; padding: 10px 20px; }
+.module { margin-bottom: 40px; }
+.module h2 { font-size: 20px; border-bottom: 1px solid #cccccc; padding-bottom: 4px; }
+.status { display: inline-block; width: 44px; text-align: center; border-radius: 3px; color: #ffffff; font-size: 11px; font-weight: bold; padding: 2px 0; margin-right: 8px; vertical-align: middle; }
+.status.pass { background: #3c9a3c; }
+.status.warn { background: #e0a000; }
+.status.fail { background: #c82020; }
+table { border-collapse: collapse; margin: 10px 0; }
+th, td { border: 1px solid #cccccc; padding: 3px 8px; text-align: left; font-size: 13px; }
+th { background: #00346e; color: #ffffff; }
+tr:nth-child(even) td { background: #f0f0f0; }
+.footer { position: fixed; bottom: 0; left: 0; right: 0; height: 20px; padding: 4px 20px; background: #00346e; color: #ffffff; font-size: 12px; }
+"#;
+
+fn write_status(w: &mut Write, status: QCResult) -> Result<(), TrustSeqErr> {
+    write!(
+        w,
+        "<span class=\"status {}\">{}</span>",
+        status.get_label(),
+        status.get_summary_label()
+    )?;
+    return Ok(());
+}
+
+/// Renders the output of `QCReport::print_text_report` as an HTML table.
+/// Lines starting with '#' become header rows.
+pub fn write_text_table(text: &[u8], w: &mut Write) -> Result<(), TrustSeqErr> {
+    let text = String::from_utf8_lossy(text);
+    if text.trim().is_empty() {
+        writeln!(w, "<p>No entries to report.</p>")?;
+        return Ok(());
+    }
+    writeln!(w, "<table>")?;
+    for line in text.lines() {
+        if line.is_empty() {
+            continue;
+        }
+        let (tag, cells) = if line.starts_with('#') {
+            ("th", &line[1..])
+        } else {
+            ("td", line)
+        };
+        write!(w, "<tr>")?;
+        for cell in cells.split('\t') {
+            write!(w, "<{}>{}</{}>", tag, escape_xml(cell), tag)?;
+        }
+        writeln!(w, "</tr>")?;
+    }
+    writeln!(w, "</table>")?;
+    return Ok(());
+}
+
+/// Writes a single self contained HTML page (inline CSS and SVG, no
+/// scripts) with a status summary followed by every report.
+pub fn write_html_report(
+    reports: &Vec<Box<QCReport>>,
+    file_name: &str,
+    w: &mut Write,
+) -> Result<(), TrustSeqErr> {
+    let title = escape_xml(file_name);
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html>")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>{} trust_seq Report</title>", title)?;
+    writeln!(w, "<style>{}</style>", STYLE)?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(
+        w,
+        "<div class=\"header\"><span class=\"title\">trust_seq Report</span><span class=\"file\">{}</span></div>",
+        title
+    )?;
+    writeln!(w, "<div class=\"summary\">")?;
+    writeln!(w, "<h2>Summary</h2>")?;
+    writeln!(w, "<ul>")?;
+    for (idx, report) in reports.iter().enumerate() {
+        write!(w, "<li>")?;
+        write_status(w, report.get_status())?;
+        writeln!(
+            w,
+            "<a href=\"#M{}\">{}</a></li>",
+            idx,
+            escape_xml(report.get_name())
+        )?;
+    }
+    writeln!(w, "</ul>")?;
+    writeln!(w, "</div>")?;
+    writeln!(w, "<div class=\"main\">")?;
+    for (idx, report) in reports.iter().enumerate() {
+        writeln!(w, "<div class=\"module\">")?;
+        write!(w, "<h2 id=\"M{}\">", idx)?;
+        write_status(w, report.get_status())?;
+        writeln!(w, "{}</h2>", escape_xml(report.get_name()))?;
+        report.print_html_report(w)?;
+        writeln!(w, "</div>")?;
+    }
+    writeln!(w, "</div>")?;
+    writeln!(
+        w,
+        "<div class=\"footer\">Produced by trust_seq (FastQC {} compatible)</div>",
+        FASTQC_VERSION
+    )?;
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")?;
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::write_text_table;
+
+    #[test]
+    fn test_write_text_table() {
+        let mut buf: Vec<u8> = Vec::new();
+        write_text_table(b"#Measure\tValue\nFilename\ta<b>.fq\n", &mut buf).unwrap();
+        assert_eq!(
+            "<table>\n<tr><th>Measure</th><th>Value</th></tr>\n<tr><td>Filename</td><td>a&lt;b&gt;.fq</td></tr>\n</table>\n",
+            String::from_utf8(buf).unwrap()
+        );
+    }
+}
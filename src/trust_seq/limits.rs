@@ -26,6 +26,16 @@ duplication error	50
 kmer	warn	2
 kmer	error	5
 
+# A Kmer is reported when its corrected binomial pvalue is below
+# pvalue at a position where it is enriched more than obs_exp fold.
+# The correction is legacy (p * 4^k as in FastQC), bonferroni
+# (p * number of tests) or fdr (Benjamini-Hochberg).  At most
+# top_n Kmers are reported.
+kmer	correction	legacy
+kmer	pvalue	0.01
+kmer	obs_exp	5
+kmer	top_n	20
+
 # For the N module the filter is on the percentage of Ns
 # at any position in the library
 n_content	warn	5
@@ -61,6 +71,11 @@ gc_sequence	error	30
 quality_sequence	warn	27
 quality_sequence	error	20
 
+# The per read mean quality module (--nano) tests the median of the
+# read mean qualities, each averaged over error probabilities
+mean_quality	warn	10
+mean_quality	error	7
+
 # The per tile module tests the maximum phred score loss between 
 # and individual tile and the average for that base across all tiles
 tile	warn	5
@@ -80,4 +95,19 @@ sequence_length	error	1
 
 adapter	warn	5
 adapter	error	10
+
+# The mapping module (SAM and BAM files) tests the percentage of
+# reads which are unmapped
+
+mapping	warn	20
+mapping	error	50
+
+# The paired end modules. Read name pairing is on the percentage of
+# pairs whose read names differ, adapter read-through on the percentage
+# of pairs whose overlap shows adapter at any position
+
+pair_names	warn	0
+pair_names	error	1
+read_through	warn	5
+read_through	error	10
 "#;
@@ -149,6 +149,19 @@ where
     }
     return c;
 }
+/// Benjamini-Hochberg adjusted p-values for `sorted_p_values`, given in
+/// ascending order, out of `test_count` tests in total. Tests left out of
+/// `sorted_p_values` are taken to have larger p-values.
+pub fn adjust_benjamini_hochberg(sorted_p_values: &[f64], test_count: usize) -> Vec<f64> {
+    let mut adjusted = vec![1.0; sorted_p_values.len()];
+    let mut lowest = 1.0f64;
+    for idx in (0..sorted_p_values.len()).rev() {
+        let q = sorted_p_values[idx] * test_count as f64 / (idx + 1) as f64;
+        lowest = lowest.min(q);
+        adjusted[idx] = lowest;
+    }
+    return adjusted;
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -171,4 +184,16 @@ mod tests {
             calc_binomial_distribution_cummulative(20, 0.4, 9)
         );
     }
+    #[test]
+    fn test_adjust_benjamini_hochberg() {
+        assert_eq!(
+            vec![0.025, 0.025, 0.04, 0.5],
+            adjust_benjamini_hochberg(&[0.01, 0.0125, 0.03, 0.5], 4)
+        );
+        // Two more tests with p-values near 1.
+        assert_eq!(
+            vec![0.06, 0.06],
+            adjust_benjamini_hochberg(&[0.01, 0.02], 6)
+        );
+    }
 }
@@ -7,11 +7,13 @@ use std::io::{BufRead, BufReader};
 #[derive(Debug, Clone)]
 pub struct ModuleConfig {
     params: HashMap<String, f64>,
+    options: HashMap<String, String>,
 }
 impl ModuleConfig {
     pub fn new() -> ModuleConfig {
         let mut config = ModuleConfig {
             params: HashMap::new(),
+            options: HashMap::new(),
         };
         config
             .load(BufReader::new(limits::LIMIT_DEFAULT.as_bytes()))
@@ -21,7 +23,19 @@ impl ModuleConfig {
     pub fn get(&self, key: &str) -> f64 {
         return *self.params.get(key).unwrap();
     }
+    /// Returns a setting whose value is not a number.
+    pub fn get_option(&self, key: &str) -> &str {
+        return &self.options.get(key).unwrap()[..];
+    }
     pub fn load<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        return self.load_values(reader, true);
+    }
+    /// Loads settings like `load`, but keeps the values already set, so
+    /// the defaults of a custom module don't replace a user's limits file.
+    pub fn load_defaults<R: BufRead>(&mut self, reader: R) -> Result<(), Error> {
+        return self.load_values(reader, false);
+    }
+    fn load_values<R: BufRead>(&mut self, reader: R, replace: bool) -> Result<(), Error> {
         for rlst in reader.lines() {
             let line = rlst?;
             if line.starts_with('#') || line.len() == 0 {
@@ -35,9 +49,17 @@ impl ModuleConfig {
                 );
                 break;
             }
-            let rslt = vals[2].parse::<f64>();
-            if let Ok(val) = rslt {
-                self.params.insert(format!("{}:{}", vals[0], vals[1]), val);
+            let key = format!("{}:{}", vals[0], vals[1]);
+            if !replace && (self.params.contains_key(&key) || self.options.contains_key(&key)) {
+                continue;
+            }
+            match vals[2].parse::<f64>() {
+                Ok(val) => {
+                    self.params.insert(key, val);
+                }
+                Err(_) => {
+                    self.options.insert(key, vals[2].to_string());
+                }
             }
         }
         return Ok(());
@@ -52,5 +74,17 @@ mod tests {
         let module_config = ModuleConfig::new();
         assert_eq!(module_config.get("duplication:warn"), 70.0);
         assert_eq!(module_config.get("duplication:error"), 50.0);
+        assert_eq!(module_config.get_option("kmer:correction"), "legacy");
+    }
+    #[test]
+    fn test_load_defaults() {
+        let mut module_config = ModuleConfig::new();
+        module_config.load("poly_g warn 5\n".as_bytes()).unwrap();
+        module_config
+            .load_defaults("poly_g warn 10\npoly_g error 20\nduplication warn 1\n".as_bytes())
+            .unwrap();
+        assert_eq!(module_config.get("poly_g:warn"), 5.0);
+        assert_eq!(module_config.get("poly_g:error"), 20.0);
+        assert_eq!(module_config.get("duplication:warn"), 70.0);
     }
 }
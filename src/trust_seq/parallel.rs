@@ -0,0 +1,273 @@
+use super::qc;
+use super::qc::QCModule;
+use super::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use super::utils::{SequenceReader, SequenceRecord};
+use std::collections::BTreeMap;
+use std::io;
+use std::io::ErrorKind;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Number of reads handed to a worker at a time. Modules sample every 10th
+/// or 50th read, so chunks must hold a multiple of 50 reads to sample the
+/// same reads as a single pass.
+pub const CHUNK_SIZE: usize = 10000;
+
+type Chunk = (usize, Vec<SequenceRecord>);
+
+enum WorkerResult {
+    /// The state `QCModule::take_chunk` returned after a chunk, by module.
+    Chunk(usize, Vec<Option<Box<QCModule>>>),
+    /// The modules of a worker once there are no chunks left.
+    Done(Vec<Box<QCModule>>),
+}
+
+fn run_worker(
+    config: Arc<TrustSeqConfig>,
+    file_name: String,
+    chunks: Arc<Mutex<Receiver<Chunk>>>,
+    results: Sender<WorkerResult>,
+) {
+    // One module set for all the chunks of a worker, so tables such as the
+    // k-mer counts are allocated and merged once per thread.
+    let mut modules = qc::create_qcmodules(&config, &file_name);
+    loop {
+        let chunk = chunks.lock().unwrap().recv();
+        let (idx, records) = match chunk {
+            Ok(c) => c,
+            Err(_) => break,
+        };
+        for record in &records {
+            qc::process_sequence(&mut modules, &record.as_sequence());
+        }
+        let parts = modules.iter_mut().map(|m| m.take_chunk()).collect();
+        if results.send(WorkerResult::Chunk(idx, parts)).is_err() {
+            return;
+        }
+    }
+    results.send(WorkerResult::Done(modules)).ok();
+}
+
+/// Merges the state of order dependent modules chunk by chunk in file
+/// order, whatever order the chunks arrive in, and the other modules once
+/// per worker.
+struct ChunkMerger {
+    modules: Vec<Box<QCModule>>,
+    next_idx: usize,
+    pending: BTreeMap<usize, Vec<Option<Box<QCModule>>>>,
+}
+impl ChunkMerger {
+    fn add(&mut self, result: WorkerResult) {
+        match result {
+            WorkerResult::Chunk(idx, parts) => {
+                self.pending.insert(idx, parts);
+                while let Some(parts) = self.pending.remove(&self.next_idx) {
+                    for (module, part) in self.modules.iter_mut().zip(parts.iter()) {
+                        if let Some(ref part) = *part {
+                            module.merge(part.as_ref());
+                        }
+                    }
+                    self.next_idx += 1;
+                }
+            }
+            // The order dependent modules of a worker are empty by now.
+            WorkerResult::Done(modules) => qc::merge_qcmodules(&mut self.modules, &modules),
+        }
+    }
+}
+
+fn read_chunk(reader: &mut SequenceReader, chunk_size: usize) -> io::Result<Vec<SequenceRecord>> {
+    let mut records = Vec::with_capacity(chunk_size);
+    while records.len() < chunk_size {
+        match reader.next_seq()? {
+            Some(seq) => records.push(SequenceRecord::from_sequence(&seq)),
+            None => break,
+        }
+    }
+    return Ok(records);
+}
+
+/// Runs the QC modules over every read of `reader` on `threads` worker
+/// threads. The returned modules hold the same state as a single pass.
+pub fn process_sequences(
+    config: &Arc<TrustSeqConfig>,
+    file_name: &str,
+    reader: &mut SequenceReader,
+    threads: usize,
+) -> Result<Vec<Box<QCModule>>, TrustSeqErr> {
+    return process_chunks(config, file_name, reader, threads, CHUNK_SIZE);
+}
+
+fn process_chunks(
+    config: &Arc<TrustSeqConfig>,
+    file_name: &str,
+    reader: &mut SequenceReader,
+    threads: usize,
+    chunk_size: usize,
+) -> Result<Vec<Box<QCModule>>, TrustSeqErr> {
+    let (chunk_tx, chunk_rx) = sync_channel::<Chunk>(threads * 2);
+    let chunk_rx = Arc::new(Mutex::new(chunk_rx));
+    let (result_tx, result_rx) = channel::<WorkerResult>();
+    let mut workers = Vec::new();
+    for _ in 0..threads {
+        let config = config.clone();
+        let file_name = file_name.to_string();
+        let chunks = chunk_rx.clone();
+        let results = result_tx.clone();
+        workers.push(thread::spawn(move || {
+            run_worker(config, file_name, chunks, results)
+        }));
+    }
+    drop(result_tx);
+
+    let mut merger = ChunkMerger {
+        modules: qc::create_qcmodules(config, file_name),
+        next_idx: 0,
+        pending: BTreeMap::new(),
+    };
+    let mut chunk_count = 0;
+    let read_result = loop {
+        let records = match read_chunk(reader, chunk_size) {
+            Ok(r) => r,
+            Err(e) => break Err(e),
+        };
+        if records.is_empty() || chunk_tx.send((chunk_count, records)).is_err() {
+            break Ok(());
+        }
+        chunk_count += 1;
+        while let Ok(result) = result_rx.try_recv() {
+            merger.add(result);
+        }
+    };
+    drop(chunk_tx);
+    for result in result_rx.iter() {
+        merger.add(result);
+    }
+    for worker in workers {
+        if worker.join().is_err() {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::Other,
+                "a worker thread panicked",
+            )));
+        }
+    }
+    read_result?;
+    return Ok(merger.modules);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::process_chunks;
+    use crate::trust_seq::qc;
+    use crate::trust_seq::trust_seq::TrustSeqConfig;
+    use crate::trust_seq::utils::FastQReader;
+    use std::sync::Arc;
+
+    fn make_fastq(count: usize) -> Vec<u8> {
+        let mut seed: u64 = 12345;
+        let mut next = move || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            return (seed >> 33) as usize;
+        };
+        let mut fastq = String::new();
+        for i in 0..count {
+            let len = 30 + next() % 40;
+            let mut seq = String::new();
+            let mut qual = String::new();
+            for _ in 0..len {
+                seq.push(['A', 'C', 'G', 'T', 'N'][next() % 5]);
+                qual.push((35 + next() % 40) as u8 as char);
+            }
+            // Some duplicated reads and an adapter, so every module has data.
+            if i % 7 == 0 {
+                seq = "AGATCGGAAGAGCACGTCTGAACTCCAGTCACTTAGG"[..len.min(37)].to_string();
+                qual.truncate(seq.len());
+            }
+            fastq.push_str(&format!(
+                "@SIM:1:FCX:1:{}:{}:{} 1:N:0:ATCACG\n{}\n+\n{}\n",
+                1101 + next() % 3,
+                i,
+                next() % 1000,
+                seq,
+                qual
+            ));
+        }
+        return fastq.into_bytes();
+    }
+    fn text_report(modules: &Vec<Box<qc::QCModule>>) -> String {
+        let reports = qc::calculate_reports(modules).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        qc::write_text_reports(&reports, &mut buf).unwrap();
+        return String::from_utf8(buf).unwrap();
+    }
+
+    #[test]
+    fn test_same_as_single_thread() {
+        let config = Arc::new(TrustSeqConfig::new());
+        let fastq = make_fastq(1234);
+        let mut modules = qc::create_qcmodules(&config, "sim.fastq");
+        let mut reader = FastQReader::new(&fastq[..]);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            for module in &mut modules {
+                module.process_sequence(&seq);
+            }
+        }
+        let mut reader = FastQReader::new(&fastq[..]);
+        let merged = process_chunks(&config, "sim.fastq", &mut reader, 3, 100).unwrap();
+        assert_eq!(text_report(&modules), text_report(&merged));
+    }
+    #[test]
+    fn test_same_with_filtered_reads() {
+        let mut config = TrustSeqConfig::new();
+        config.casava = true;
+        let config = Arc::new(config);
+        // Every third read flagged as filtered by CASAVA.
+        let fastq = String::from_utf8(make_fastq(3000)).unwrap();
+        let mut flagged = String::new();
+        for (idx, line) in fastq.lines().enumerate() {
+            if idx % 12 == 0 {
+                flagged.push_str(&line.replace(" 1:N:", " 1:Y:"));
+            } else {
+                flagged.push_str(line);
+            }
+            flagged.push('\n');
+        }
+        let fastq = flagged.into_bytes();
+        let mut modules = qc::create_qcmodules(&config, "sim.fastq");
+        let mut reader = FastQReader::new(&fastq[..]);
+        reader.set_casava_filter(true);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            qc::process_sequence(&mut modules, &seq);
+        }
+        let mut reader = FastQReader::new(&fastq[..]);
+        reader.set_casava_filter(true);
+        let merged = process_chunks(&config, "sim.fastq", &mut reader, 4, 100).unwrap();
+        let report = text_report(&modules);
+        assert!(report.contains("Sequences flagged as poor quality\t1000"));
+        assert_eq!(report, text_report(&merged));
+    }
+    #[test]
+    fn test_same_past_observation_cutoff() {
+        // With a cutoff of 1000 the distinct sequences are frozen partway
+        // through the 12th chunk.
+        let fastq = make_fastq(3000);
+        let mut reports: Vec<String> = Vec::new();
+        for cutoff in &[qc::OBSERVATION_CUTOFF, 1000] {
+            let mut config = TrustSeqConfig::new();
+            config.observation_cutoff = *cutoff;
+            let config = Arc::new(config);
+            let mut modules = qc::create_qcmodules(&config, "sim.fastq");
+            let mut reader = FastQReader::new(&fastq[..]);
+            while let Some(seq) = reader.next_seq().unwrap() {
+                qc::process_sequence(&mut modules, &seq);
+            }
+            let mut reader = FastQReader::new(&fastq[..]);
+            let merged = process_chunks(&config, "sim.fastq", &mut reader, 4, 100).unwrap();
+            let report = text_report(&modules);
+            assert_eq!(report, text_report(&merged));
+            reports.push(report);
+        }
+        assert_ne!(reports[0], reports[1]);
+    }
+}
@@ -1,95 +1,334 @@
 mod adapter_content;
 mod basic_stats;
 mod kmer_content;
+mod mapping_stats;
 mod n_content;
 mod over_represented_seqs;
+mod pair_names;
+mod pair_overlap;
 mod per_base_quality_scores;
 mod per_base_sequence_content;
 mod per_sequence_gc_content;
 mod per_sequence_quality_scores;
 mod per_tile_quality_scores;
 mod quality_counts;
+mod read_length_stats;
+mod read_mean_quality;
 mod sequence_length_distribution;
+mod yield_over_time;
 use super::utils::Sequence;
 
+use std::any::Any;
+use std::fmt;
 use std::io;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::io::Write;
+use std::sync::Arc;
 
 use self::adapter_content::AdapterContent;
 use self::basic_stats::BasicStats;
 use self::kmer_content::KmerContent;
+pub use self::kmer_content::{Correction, MAX_KMER_SIZE, MIN_KMER_SIZE};
+use self::mapping_stats::MappingStats;
 use self::n_content::NContent;
 use self::over_represented_seqs::OverRepresentedSeqs;
+use self::pair_names::PairNames;
+use self::pair_overlap::PairOverlap;
 use self::per_base_quality_scores::PerBaseQualityScores;
 use self::per_base_sequence_content::PerBaseSequenceContent;
 use self::per_sequence_gc_content::PerSequenceGCContents;
 use self::per_sequence_quality_scores::PerSequenceQualityScores;
 use self::per_tile_quality_scores::PerTileQualityScores;
+use self::read_length_stats::ReadLengthStats;
+use self::read_mean_quality::ReadMeanQuality;
 use self::sequence_length_distribution::SequenceLengthDistribution;
+use self::yield_over_time::YieldOverTime;
+pub use self::adapter_content::AdapterContentReport;
+pub use self::basic_stats::BasicStatsReport;
+pub use self::kmer_content::{KmerContentReport, KmerReport};
+pub use self::mapping_stats::MappingStatsReport;
+pub use self::n_content::NContentReport;
+pub use self::over_represented_seqs::{DuplicationLevel, DuplicationLevelReport};
+pub use self::over_represented_seqs::{OverRepresentedReport, OverRepresentedSeq};
+pub use self::over_represented_seqs::OBSERVATION_CUTOFF;
+pub use self::pair_names::{get_pair_name, PairMismatch, PairNamesReport};
+pub use self::pair_overlap::{InsertSizeReport, ReadThroughReport};
+pub use self::per_base_quality_scores::{PerBaseQualityReport, Quality};
+pub use self::per_base_sequence_content::PerBaseSequenceReport;
+pub use self::per_sequence_gc_content::PerSequenceGCReport;
+pub use self::per_sequence_quality_scores::PerSequenceQualityReport;
+pub use self::per_tile_quality_scores::PerTileQualityReport;
+pub use self::read_length_stats::{LengthBin, ReadLengthStatsReport};
+pub use self::read_mean_quality::ReadMeanQualityReport;
+pub use self::sequence_length_distribution::{GroupValue, SequenceLengthReport};
+pub use self::yield_over_time::{YieldBin, YieldOverTimeReport};
+use super::html_report;
 use super::trust_seq::{TrustSeqConfig, TrustSeqErr};
 use serde_json::map::Map;
 use serde_json::Value;
 
-pub fn create_qcmodules<'a>(config: &'a TrustSeqConfig) -> Vec<Box<QCModule + 'a>> {
-    let mut modules: Vec<Box<QCModule + 'a>> = Vec::new();
-    modules.push(Box::new(BasicStats::new()));
-    modules.push(Box::new(PerBaseQualityScores::new(config)));
-    modules.push(Box::new(PerTileQualityScores::new(config)));
-    modules.push(Box::new(PerSequenceQualityScores::new(config)));
-    modules.push(Box::new(PerBaseSequenceContent::new(config)));
-    modules.push(Box::new(PerSequenceGCContents::new(config)));
-    modules.push(Box::new(NContent::new(config)));
-    modules.push(Box::new(SequenceLengthDistribution::new(config)));
-    modules.push(Box::new(OverRepresentedSeqs::new(config)));
-    modules.push(Box::new(AdapterContent::new(config)));
-    modules.push(Box::new(KmerContent::new(config)));
+/// The FastQC release whose fastqc_data.txt layout the text reports follow.
+pub const FASTQC_VERSION: &'static str = "0.11.9";
+
+/// The names of the built-in modules, whether or not a run reports them.
+pub const MODULE_NAMES: [&'static str; 19] = [
+    "Basic Statistics",
+    "Per base sequence quality",
+    "Per tile sequence quality",
+    "Per sequence quality scores",
+    "Per read mean quality",
+    "Per base sequence content",
+    "Per sequence GC content",
+    "Per base N content",
+    "Sequence Length Distribution",
+    "Read length statistics",
+    "Yield over time",
+    "Sequence Duplication Levels",
+    "Overrepresented sequences",
+    "Adapter Content",
+    "Kmer Content",
+    "Mapping statistics",
+    "Read name pairing",
+    "Insert size distribution",
+    "Adapter read-through",
+];
+
+/// Creates a module of a library user, see `TrustSeqConfig::register_module`.
+/// A closure can carry state of its own into the modules it creates.
+pub type QCModuleFactory = Arc<dyn Fn(&Arc<TrustSeqConfig>) -> Box<QCModule> + Send + Sync>;
+/// The factories of the modules registered by a library user.
+#[derive(Clone, Default)]
+pub struct CustomModules {
+    pub factories: Vec<QCModuleFactory>,
+}
+impl fmt::Debug for CustomModules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "CustomModules({} modules)", self.factories.len());
+    }
+}
+
+pub fn create_qcmodules(config: &Arc<TrustSeqConfig>, file_name: &str) -> Vec<Box<QCModule>> {
+    let mut modules: Vec<Box<QCModule>> = Vec::new();
+    modules.push(Box::new(BasicStats::new(file_name)));
+    modules.push(Box::new(PerBaseQualityScores::new(config.clone())));
+    // Long reads have no tiles, and are too error prone to be duplicated.
+    if !config.nano {
+        modules.push(Box::new(PerTileQualityScores::new(config.clone())));
+    }
+    modules.push(Box::new(PerSequenceQualityScores::new(config.clone())));
+    if config.nano {
+        modules.push(Box::new(ReadMeanQuality::new(config.clone())));
+    }
+    modules.push(Box::new(PerBaseSequenceContent::new(config.clone())));
+    modules.push(Box::new(PerSequenceGCContents::new(config.clone())));
+    modules.push(Box::new(NContent::new(config.clone())));
+    modules.push(Box::new(SequenceLengthDistribution::new(config.clone())));
+    if config.nano {
+        modules.push(Box::new(ReadLengthStats::new()));
+        modules.push(Box::new(YieldOverTime::new()));
+    } else {
+        modules.push(Box::new(OverRepresentedSeqs::new(config.clone())));
+    }
+    modules.push(Box::new(AdapterContent::new(config.clone())));
+    modules.push(Box::new(KmerContent::new(config.clone())));
+    modules.push(Box::new(MappingStats::new(config.clone())));
+    for factory in &config.custom_modules.factories {
+        modules.push(factory(config));
+    }
+    return modules;
+}
+/// Creates the modules of the quality scores alone, to see how a change
+/// of the qualities such as binning shows in the QC.
+pub fn create_quality_qcmodules(config: &Arc<TrustSeqConfig>) -> Vec<Box<QCModule>> {
+    let mut modules: Vec<Box<QCModule>> = Vec::new();
+    modules.push(Box::new(PerBaseQualityScores::new(config.clone())));
+    modules.push(Box::new(PerSequenceQualityScores::new(config.clone())));
+    return modules;
+}
+/// Creates the modules that look at both reads of a pair.
+pub fn create_pair_qcmodules(config: &Arc<TrustSeqConfig>) -> Vec<Box<PairQCModule>> {
+    let mut modules: Vec<Box<PairQCModule>> = Vec::new();
+    modules.push(Box::new(PairNames::new(config.clone())));
+    modules.push(Box::new(PairOverlap::new(config.clone())));
     return modules;
 }
-#[derive(Serialize, Debug, Copy, Clone)]
+/// Runs `seq` through every module that does not ignore it.
+pub fn process_sequence(modules: &mut Vec<Box<QCModule>>, seq: &Sequence) {
+    for module in modules {
+        if (seq.filtered || seq.excluded) && module.ignore_filtered_sequences() {
+            continue;
+        }
+        module.process_sequence(seq);
+    }
+}
+/// Merges module sets created by `create_qcmodules`. `others` must have
+/// processed the sequences that directly follow the ones seen by `modules`.
+pub fn merge_qcmodules(modules: &mut Vec<Box<QCModule>>, others: &Vec<Box<QCModule>>) {
+    for (module, other) in modules.iter_mut().zip(others.iter()) {
+        module.merge(other.as_ref());
+    }
+}
+/// Ordered from best to worst, so the worst of several results is their max.
+#[derive(Serialize, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum QCResult {
     Pass,
     Warn,
     Fail,
 }
-pub trait QCModule {
+pub trait QCModule: Send {
     fn ignore_in_report(&self) -> bool {
         return false;
     }
+    /// Whether reads flagged as filtered, or excluded, are kept from
+    /// `process_sequence`.
+    fn ignore_filtered_sequences(&self) -> bool {
+        return true;
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> ();
+    /// Adds the state of `other`, a module of the same type that processed
+    /// other sequences of the same input. The merged state is the same as
+    /// if one module had processed all of them, in any order, unless the
+    /// module returns its state from `take_chunk`.
+    fn merge(&mut self, other: &QCModule) -> ();
+    /// For modules whose state depends on the order of the reads: returns
+    /// the state so far and starts over. These parts are merged in file
+    /// order, so `other` in `merge` saw the reads following those of `self`.
+    fn take_chunk(&mut self) -> Option<Box<QCModule>> {
+        return None;
+    }
+    fn as_any(&self) -> &Any;
     fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr>;
 }
 
+pub trait PairQCModule {
+    fn process_pair(&mut self, r1: &Sequence, r2: &Sequence) -> ();
+    fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr>;
+}
+/// The result of a module. Each report type is public, so the values can
+/// be read from `as_any` or `get_report` as well as from the JSON.
 pub trait QCReport {
+    fn as_any(&self) -> &Any;
     fn get_status(&self) -> QCResult;
     fn get_name(&self) -> &'static str;
     fn print_text_report(&self, w: &mut Write) -> Result<(), TrustSeqErr>;
+    /// Writes the body of the module section in the HTML report. Reports
+    /// without a chart fall back to their text report as a table.
+    fn print_html_report(&self, w: &mut Write) -> Result<(), TrustSeqErr> {
+        let mut text: Vec<u8> = Vec::new();
+        self.print_text_report(&mut text)?;
+        return html_report::write_text_table(&text, w);
+    }
     fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr>;
 }
-pub fn write_text_reports<'a>(
-    modules: &Vec<Box<QCModule + 'a>>,
-    w: &mut Write,
-) -> Result<(), TrustSeqErr> {
+impl QCResult {
+    /// The lower case form FastQC uses in fastqc_data.txt.
+    pub fn get_label(&self) -> &'static str {
+        return match *self {
+            QCResult::Pass => "pass",
+            QCResult::Warn => "warn",
+            QCResult::Fail => "fail",
+        };
+    }
+    /// The upper case form FastQC uses in summary.txt.
+    pub fn get_summary_label(&self) -> &'static str {
+        return match *self {
+            QCResult::Pass => "PASS",
+            QCResult::Warn => "WARN",
+            QCResult::Fail => "FAIL",
+        };
+    }
+    /// The process exit code used with --exit_status (1 is kept for errors).
+    pub fn get_exit_code(&self) -> i32 {
+        return match *self {
+            QCResult::Pass => 0,
+            QCResult::Warn => 2,
+            QCResult::Fail => 3,
+        };
+    }
+}
+pub fn calculate_reports(modules: &Vec<Box<QCModule>>) -> Result<Vec<Box<QCReport>>, TrustSeqErr> {
     let mut reports: Vec<Box<QCReport>> = Vec::new();
     for module in modules {
+        if module.ignore_in_report() {
+            continue;
+        }
         module.calculate(&mut reports)?;
     }
+    return Ok(reports);
+}
+pub fn calculate_pair_reports(
+    modules: &Vec<Box<PairQCModule>>,
+) -> Result<Vec<Box<QCReport>>, TrustSeqErr> {
+    let mut reports: Vec<Box<QCReport>> = Vec::new();
+    for module in modules {
+        module.calculate(&mut reports)?;
+    }
+    return Ok(reports);
+}
+/// Writes the reports in the fastqc_data.txt format of FastQC, so parsers
+/// such as MultiQC's fastqc module can read them unchanged.
+pub fn write_text_reports(reports: &Vec<Box<QCReport>>, w: &mut Write) -> Result<(), TrustSeqErr> {
+    writeln!(w, "##FastQC\t{}", FASTQC_VERSION)?;
     for report in reports {
-        writeln!(w, ">>{}\t{:?}", report.get_name(), report.get_status())?;
+        writeln!(w, ">>{}\t{}", report.get_name(), report.get_status().get_label())?;
         report.print_text_report(w)?;
         writeln!(w, ">>END_MODULE")?;
     }
     return Ok(());
 }
-pub fn get_json_reports<'a>(
-    modules: &Vec<Box<QCModule + 'a>>,
-) -> Result<Map<String, Value>, TrustSeqErr> {
-    let mut reports: Vec<Box<QCReport>> = Vec::new();
-    let mut map: Map<String, Value> = Map::new();
-    for module in modules {
-        module.calculate(&mut reports)?;
+pub fn write_summary(
+    reports: &Vec<Box<QCReport>>,
+    file_name: &str,
+    w: &mut Write,
+) -> Result<(), TrustSeqErr> {
+    for report in reports {
+        writeln!(
+            w,
+            "{}\t{}\t{}",
+            report.get_status().get_summary_label(),
+            report.get_name(),
+            file_name
+        )?;
+    }
+    return Ok(());
+}
+/// Returns the worst status among the reports named in `modules`
+/// (case insensitive), or among all reports when no subset is given.
+/// Modules that did not run, such as the nano modules on short reads, are
+/// skipped.
+pub fn get_worst_status(reports: &Vec<Box<QCReport>>, modules: &Option<Vec<String>>) -> QCResult {
+    let mut worst = QCResult::Pass;
+    match *modules {
+        None => {
+            for report in reports {
+                worst = worst.max(report.get_status());
+            }
+        }
+        Some(ref names) => {
+            for name in names {
+                for report in reports {
+                    if report.get_name().eq_ignore_ascii_case(name) {
+                        worst = worst.max(report.get_status());
+                    }
+                }
+            }
+        }
+    }
+    return worst;
+}
+/// Returns the first report of type `T`, for example
+/// `get_report::<BasicStatsReport>(&reports)`.
+pub fn get_report<T: QCReport + 'static>(reports: &Vec<Box<QCReport>>) -> Option<&T> {
+    for report in reports {
+        if let Some(r) = report.as_any().downcast_ref::<T>() {
+            return Some(r);
+        }
     }
+    return None;
+}
+pub fn get_json_reports(reports: &Vec<Box<QCReport>>) -> Result<Map<String, Value>, TrustSeqErr> {
+    let mut map: Map<String, Value> = Map::new();
     for report in reports {
         report.add_json(&mut map)?;
     }
@@ -133,3 +372,249 @@ impl PhreadEncoding {
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::trust_seq::sam::SamReader;
+    use crate::trust_seq::utils::FastQReader;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const FASTQ: &'static [u8] = b"@SIM:1:FCX:1:1101:1000:2000 1:N:0:ATCACG
+ACGTTGCAAGGCTTAACGGT
++
+IIIIHHHHGGGGFFFFEEEE
+@SIM:1:FCX:1:1102:1000:2001 1:N:0:ATCACG
+TTGCAAGGCTTAACGGTACG
++
+IIIIIIIIIIHHHHHHHHHH
+";
+
+    #[test]
+    fn test_write_text_reports() {
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut modules = create_qcmodules(&config, "sim.fastq");
+        let mut reader = FastQReader::new(FASTQ);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            for module in &mut modules {
+                module.process_sequence(&seq);
+            }
+        }
+        let reports = calculate_reports(&modules).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        write_text_reports(&reports, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!("##FastQC\t0.11.9", lines[0]);
+        assert_eq!(">>Basic Statistics\tpass", lines[1]);
+        assert_eq!("#Measure\tValue", lines[2]);
+        assert_eq!("Filename\tsim.fastq", lines[3]);
+        assert!(lines.contains(&"Sequence length\t20"));
+        let names: Vec<&str> = lines
+            .iter()
+            .filter(|l| l.starts_with(">>") && **l != ">>END_MODULE")
+            .map(|l| &l[2..l.find('\t').unwrap()])
+            .collect();
+        assert_eq!(
+            vec![
+                "Basic Statistics",
+                "Per base sequence quality",
+                "Per sequence quality scores",
+                "Per base sequence content",
+                "Per sequence GC content",
+                "Per base N content",
+                "Sequence Length Distribution",
+                "Sequence Duplication Levels",
+                "Overrepresented sequences",
+                "Adapter Content",
+                "Kmer Content",
+            ],
+            names
+        );
+    }
+    #[test]
+    fn test_empty_file() {
+        let config = Arc::new(TrustSeqConfig::new());
+        let modules = create_qcmodules(&config, "empty.fastq");
+        let reports = calculate_reports(&modules).unwrap();
+        let mut buf: Vec<u8> = Vec::new();
+        write_text_reports(&reports, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.lines().any(|l| l == "Total Sequences\t0"));
+        assert!(text.lines().any(|l| l == "%GC\t0"));
+    }
+    #[test]
+    fn test_module_names() {
+        let mut reports: Vec<Box<QCReport>> = Vec::new();
+        for nano in &[false, true] {
+            let mut config = TrustSeqConfig::new();
+            config.nano = *nano;
+            let config = Arc::new(config);
+            let mut modules = create_qcmodules(&config, "sim.fastq");
+            let mut reader = FastQReader::new(FASTQ);
+            while let Some(seq) = reader.next_seq().unwrap() {
+                process_sequence(&mut modules, &seq);
+            }
+            for module in &modules {
+                module.calculate(&mut reports).unwrap();
+            }
+            reports.extend(calculate_pair_reports(&create_pair_qcmodules(&config)).unwrap());
+        }
+        for report in &reports {
+            assert!(MODULE_NAMES.contains(&report.get_name()), "{}", report.get_name());
+        }
+    }
+    #[test]
+    fn test_get_worst_status() {
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut modules = create_qcmodules(&config, "sim.fastq");
+        let mut reader = FastQReader::new(FASTQ);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            for module in &mut modules {
+                module.process_sequence(&seq);
+            }
+        }
+        let reports = calculate_reports(&modules).unwrap();
+        let worst = reports.iter().map(|r| r.get_status()).max().unwrap();
+        assert_eq!(worst, get_worst_status(&reports, &None));
+        let subset = Some(vec!["basic statistics".to_string()]);
+        assert_eq!(QCResult::Pass, get_worst_status(&reports, &subset));
+        let absent = Some(vec![
+            "Basic Statistics".to_string(),
+            "Per read mean quality".to_string(),
+        ]);
+        assert_eq!(QCResult::Pass, get_worst_status(&reports, &absent));
+    }
+    #[test]
+    fn test_mapped_only() {
+        let sam = b"r1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tABCD\n\
+r2\t4\t*\t0\t0\t*\t*\t0\t0\tGGTA\tABCD\n\
+r3\t16\tchr1\t200\t30\t4M\t*\t0\t0\tAACG\tABCD\n";
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut modules = create_qcmodules(&config, "sim.sam");
+        let mut reader = SamReader::new(&sam[..]);
+        reader.set_mapped_only(true);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            process_sequence(&mut modules, &seq);
+        }
+        let reports = calculate_reports(&modules).unwrap();
+        // Unmapped reads are not poor quality: only the mapping
+        // statistics count them.
+        let basic_stats = get_report::<BasicStatsReport>(&reports).unwrap();
+        assert_eq!((2, 0), (basic_stats.total_sequence, basic_stats.filtered_sequence));
+        let mapping = get_report::<MappingStatsReport>(&reports).unwrap();
+        assert_eq!((3, 1), (mapping.total_count, mapping.unmapped_count));
+    }
+    // A lab specific check, as a library user would write it.
+    struct PolyGTail {
+        config: Arc<TrustSeqConfig>,
+        count: u64,
+        poly_g_count: u64,
+    }
+    #[derive(Serialize)]
+    struct PolyGTailReport {
+        status: QCResult,
+        percentage: f64,
+    }
+    fn create_poly_g_tail(config: &Arc<TrustSeqConfig>) -> Box<QCModule> {
+        return Box::new(PolyGTail {
+            config: config.clone(),
+            count: 0,
+            poly_g_count: 0,
+        });
+    }
+    impl QCReport for PolyGTailReport {
+        fn as_any(&self) -> &Any {
+            return self;
+        }
+        fn get_status(&self) -> QCResult {
+            return self.status;
+        }
+        fn get_name(&self) -> &'static str {
+            return "Poly-G tails";
+        }
+        fn print_text_report(&self, w: &mut Write) -> Result<(), TrustSeqErr> {
+            writeln!(w, "%Poly-G\t{}", self.percentage)?;
+            return Ok(());
+        }
+        fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+            map.insert(self.get_name().to_string(), serde_json::to_value(self)?);
+            return Ok(());
+        }
+    }
+    impl QCModule for PolyGTail {
+        fn process_sequence(&mut self, seq: &Sequence) -> () {
+            self.count += 1;
+            if seq.sequence.ends_with(b"GGGGG") {
+                self.poly_g_count += 1;
+            }
+        }
+        fn merge(&mut self, other: &QCModule) -> () {
+            let other = other.as_any().downcast_ref::<PolyGTail>().unwrap();
+            self.count += other.count;
+            self.poly_g_count += other.poly_g_count;
+        }
+        fn as_any(&self) -> &Any {
+            return self;
+        }
+        fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+            let percentage = self.poly_g_count as f64 * 100.0 / self.count as f64;
+            let status = if percentage > self.config.module_config.get("poly_g:error") {
+                QCResult::Fail
+            } else if percentage > self.config.module_config.get("poly_g:warn") {
+                QCResult::Warn
+            } else {
+                QCResult::Pass
+            };
+            results.push(Box::new(PolyGTailReport {
+                status: status,
+                percentage: percentage,
+            }));
+            return Ok(());
+        }
+    }
+    #[test]
+    fn test_register_module() {
+        let mut config = TrustSeqConfig::new();
+        config
+            .module_config
+            .load("poly_g warn 40\n".as_bytes())
+            .unwrap();
+        // The factory keeps count of the modules it creates.
+        let created = Arc::new(AtomicUsize::new(0));
+        let count = created.clone();
+        let factory = move |config: &Arc<TrustSeqConfig>| {
+            count.fetch_add(1, Ordering::SeqCst);
+            return create_poly_g_tail(config);
+        };
+        config
+            .register_module(factory, "poly_g warn 10\npoly_g error 60\n")
+            .unwrap();
+        let config = Arc::new(config);
+        create_qcmodules(&config, "other.fastq");
+        let mut modules = create_qcmodules(&config, "sim.fastq");
+        assert_eq!(2, created.load(Ordering::SeqCst));
+        let fastq = b"@r1\nACGTGGGGG\n+\nIIIIIIIII\n@r2\nACGTACGTA\n+\nIIIIIIIII\n";
+        let mut reader = FastQReader::new(&fastq[..]);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            process_sequence(&mut modules, &seq);
+        }
+        let reports = calculate_reports(&modules).unwrap();
+        let report = get_report::<PolyGTailReport>(&reports).unwrap();
+        assert_eq!(50.0, report.percentage);
+        // The limits file wins over the default warn threshold of 10.
+        assert_eq!(QCResult::Warn, report.status);
+
+        let mut buf: Vec<u8> = Vec::new();
+        write_text_reports(&reports, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.ends_with(">>Poly-G tails\twarn\n%Poly-G\t50\n>>END_MODULE\n"));
+        let json = get_json_reports(&reports).unwrap();
+        assert_eq!(
+            Some(&Value::from(50.0)),
+            json["Poly-G tails"].get("percentage")
+        );
+        let mut buf: Vec<u8> = Vec::new();
+        html_report::write_html_report(&reports, "sim.fastq", &mut buf).unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains("Poly-G tails"));
+    }
+}
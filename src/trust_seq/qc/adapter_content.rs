@@ -1,6 +1,7 @@
 use super::super::adapter::Adapter;
-use super::super::adapter_list::ADAPTER_LIST;
 use super::super::group::BaseGroup;
+use super::super::svg::LineGraph;
+use super::super::utils::format_double;
 use super::super::utils::Sequence;
 use super::{QCModule, QCReport, QCResult};
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
@@ -8,26 +9,30 @@ use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
 use std::cmp;
-use std::io::BufReader;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 use std::str;
 
 #[derive(Debug)]
-pub struct AdapterContent<'a> {
-    config: &'a TrustSeqConfig,
+pub struct AdapterContent {
+    config: Arc<TrustSeqConfig>,
     longest_sequence: usize,
     longest_adapter: usize,
     total_count: u64,
     adapters: Vec<Adapter>,
 }
 #[derive(Serialize)]
-struct AdapterContentReport {
-    status: QCResult,
-    groups: Vec<BaseGroup>,
-    adapter_names: Vec<String>,
-    enrichments: Vec<Vec<f64>>,
+pub struct AdapterContentReport {
+    pub status: QCResult,
+    pub groups: Vec<BaseGroup>,
+    pub adapter_names: Vec<String>,
+    pub enrichments: Vec<Vec<f64>>,
 }
 impl QCReport for AdapterContentReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         "Adapter Content"
     }
@@ -38,6 +43,19 @@ impl QCReport for AdapterContentReport {
         map.insert(self.get_name().to_string(), value::to_value(&self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let graph = LineGraph {
+            title: "% Adapter".to_string(),
+            x_title: "Position in read (bp)",
+            x_labels: self.groups.iter().map(|g| g.to_string()).collect(),
+            series_names: self.adapter_names.clone(),
+            series: self.enrichments.clone(),
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
         write!(writer, "#Position")?;
         for name in &self.adapter_names {
@@ -45,22 +63,22 @@ impl QCReport for AdapterContentReport {
         }
         write!(writer, "\n")?;
         for (group_idx, group) in self.groups.iter().enumerate() {
-            if group.lower_count == group.upper_count {
-                write!(writer, "{}", group.lower_count)?;
-            } else {
-                write!(writer, "{}-{}", group.lower_count, group.upper_count)?;
-            }
+            write!(writer, "{}", group)?;
             for adapter_idx in 0..self.adapter_names.len() {
-                write!(writer, "\t{}", self.enrichments[adapter_idx][group_idx])?;
+                write!(
+                    writer,
+                    "\t{}",
+                    format_double(self.enrichments[adapter_idx][group_idx])
+                )?;
             }
             write!(writer, "\n")?;
         }
         return Ok(());
     }
 }
-impl<'a> AdapterContent<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> AdapterContent<'a> {
-        let adapters = Adapter::load_adapters(BufReader::new(ADAPTER_LIST.as_bytes()));
+impl AdapterContent {
+    pub fn new(config: Arc<TrustSeqConfig>) -> AdapterContent {
+        let adapters: Vec<Adapter> = config.adapters.clone();
 
         let length = adapters
             .iter()
@@ -73,12 +91,12 @@ impl<'a> AdapterContent<'a> {
             adapters: adapters,
         };
     }
+}
+
+impl QCModule for AdapterContent {
     fn ignore_filtered_sequences(&self) -> bool {
         return true;
     }
-}
-
-impl<'a> QCModule for AdapterContent<'a> {
     fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         let groups = BaseGroup::make_base_groups(&self.config.group_type, self.longest_sequence);
         let mut enrichments: Vec<Vec<f64>> = Vec::new();
@@ -89,13 +107,20 @@ impl<'a> QCModule for AdapterContent<'a> {
             enrichments.push(vec![0.0; groups.len()]);
         }
         for (a, adapter) in self.adapters.iter().enumerate() {
+            // A hit at one position also covers every later position.
+            let mut coverage: Vec<u64> = vec![0; self.longest_sequence];
+            let mut total = 0;
+            for (idx, cov) in coverage.iter_mut().enumerate() {
+                total += adapter.positions.get(idx).map_or(0, |c| *c);
+                *cov = total;
+            }
             for (g, group) in groups.iter().enumerate() {
                 for idx in (group.lower_count - 1)..group.upper_count {
-                    enrichments[a][g] += adapter.positions[idx] as f64;
+                    enrichments[a][g] += coverage[idx] as f64;
                 }
                 enrichments[a][g] *= 100.0
                     / self.total_count as f64
-                    / (group.lower_count - group.upper_count + 1) as f64;
+                    / (group.upper_count - group.lower_count + 1) as f64;
                 max_enrichment = max_enrichment.max(enrichments[a][g]);
             }
         }
@@ -114,14 +139,26 @@ impl<'a> QCModule for AdapterContent<'a> {
         }));
         return Ok(());
     }
-    fn process_sequence(&mut self, seq: &Sequence) -> () {
-        self.total_count += 1;
-        if seq.sequence.len() - self.longest_adapter as usize > self.longest_sequence {
-            self.longest_sequence = seq.sequence.len() - self.longest_adapter;
-            for adapter in &mut self.adapters {
-                adapter.positions.resize(self.longest_sequence, 0 as u64)
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<AdapterContent>().unwrap();
+        self.total_count += other.total_count;
+        self.longest_sequence = cmp::max(self.longest_sequence, other.longest_sequence);
+        for (adapter, other_adapter) in self.adapters.iter_mut().zip(other.adapters.iter()) {
+            if adapter.positions.len() < other_adapter.positions.len() {
+                adapter.positions.resize(other_adapter.positions.len(), 0);
+            }
+            for (idx, count) in other_adapter.positions.iter().enumerate() {
+                adapter.positions[idx] += *count;
             }
         }
+    }
+    fn process_sequence(&mut self, seq: &Sequence) -> () {
+        self.total_count += 1;
+        let length = seq.sequence.len().saturating_sub(self.longest_adapter);
+        self.longest_sequence = cmp::max(self.longest_sequence, length);
         for adapter in &mut self.adapters {
             let seq = unsafe { str::from_utf8_unchecked(seq.sequence) };
             match seq.find(&adapter.sequence) {
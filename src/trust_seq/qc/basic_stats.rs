@@ -6,9 +6,11 @@ use serde_json::map::Map;
 use serde_json::value;
 use serde_json::Value;
 use std::cmp;
+use std::any::Any;
 use std::io::Write;
 
 pub struct BasicStats {
+    file_name: String,
     actual_count: u64,
     filtered_count: u64,
     min_length: u32,
@@ -18,8 +20,9 @@ pub struct BasicStats {
 }
 
 impl BasicStats {
-    pub fn new() -> BasicStats {
+    pub fn new(file_name: &str) -> BasicStats {
         return BasicStats {
+            file_name: file_name.to_string(),
             actual_count: 0,
             filtered_count: 0,
             min_length: 0,
@@ -31,32 +34,79 @@ impl BasicStats {
 }
 
 #[derive(Serialize)]
-struct BasicStatsReport {
-    status: QCResult,
-    encoding: String,
-    total_sequence: u64,
-    filtered_sequence: u64,
-    sequence_min_length: u32,
-    sequence_max_length: u32,
-    gc_percent: u32,
+pub struct BasicStatsReport {
+    pub status: QCResult,
+    pub file_name: String,
+    pub file_type: &'static str,
+    pub encoding: String,
+    pub total_sequence: u64,
+    pub filtered_sequence: u64,
+    pub sequence_min_length: u32,
+    pub sequence_max_length: u32,
+    pub gc_percent: u32,
 }
 impl QCModule for BasicStats {
     fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
-        let encoding = PhreadEncoding::get_phread_encoding(self.lowest_char)?;
+        // FASTA reads have no quality scores to tell the encoding from.
+        let encoding = if self.lowest_char == 255 {
+            "None"
+        } else {
+            PhreadEncoding::get_phread_encoding(self.lowest_char)?.name
+        };
         let gc_count = self.gatcn_count[0] + self.gatcn_count[3];
         let at_count = self.gatcn_count[1] + self.gatcn_count[2];
+        // Like FastQC, an empty file reports a GC content of 0.
+        let gc_percent = if gc_count + at_count == 0 {
+            0
+        } else {
+            (gc_count * 100) / (gc_count + at_count)
+        };
         results.push(Box::new(BasicStatsReport {
             status: QCResult::Pass,
-            encoding: encoding.name.to_string(),
+            file_name: self.file_name.clone(),
+            file_type: "Conventional base calls",
+            encoding: encoding.to_string(),
             total_sequence: self.actual_count,
             filtered_sequence: self.filtered_count,
-            sequence_min_length: self.max_length,
-            sequence_max_length: self.min_length,
-            gc_percent: ((gc_count * 100) / (gc_count + at_count)) as u32,
+            sequence_min_length: self.min_length,
+            sequence_max_length: self.max_length,
+            gc_percent: gc_percent as u32,
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<BasicStats>().unwrap();
+        self.filtered_count += other.filtered_count;
+        if other.actual_count == 0 {
+            return;
+        }
+        if self.actual_count == 0 {
+            self.min_length = other.min_length;
+            self.max_length = other.max_length;
+        } else {
+            self.min_length = cmp::min(self.min_length, other.min_length);
+            self.max_length = cmp::max(self.max_length, other.max_length);
+        }
+        self.actual_count += other.actual_count;
+        self.lowest_char = cmp::min(self.lowest_char, other.lowest_char);
+        for (count, other_count) in self.gatcn_count.iter_mut().zip(other.gatcn_count.iter()) {
+            *count += *other_count;
+        }
+    }
+    fn ignore_filtered_sequences(&self) -> bool {
+        return false;
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
+        if seq.excluded {
+            return;
+        }
+        if seq.filtered {
+            self.filtered_count += 1;
+            return;
+        }
         self.actual_count += 1;
         let len = seq.sequence.len() as u32;
         if self.actual_count == 1 {
@@ -64,7 +114,7 @@ impl QCModule for BasicStats {
             self.max_length = len;
         } else {
             self.min_length = cmp::min(self.min_length, len);
-            self.max_length = cmp::max(self.min_length, len);
+            self.max_length = cmp::max(self.max_length, len);
         }
         for q in seq.sequence {
             let ch = *q as char;
@@ -77,11 +127,8 @@ impl QCModule for BasicStats {
                 'a' => 1,
                 't' => 2,
                 'c' => 3,
-                'N' => 4,
-                _ => {
-                    println!("unexpected char={}", ch);
-                    4
-                }
+                // Ambiguity codes such as R or Y are counted as N.
+                _ => 4,
             };
             self.gatcn_count[idx] += 1;
         }
@@ -93,6 +140,9 @@ impl QCModule for BasicStats {
     }
 }
 impl QCReport for BasicStatsReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Basic Statistics";
     }
@@ -104,9 +154,16 @@ impl QCReport for BasicStatsReport {
         return Ok(());
     }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        write!(writer, "#Measure\tValue\n")?;
+        write!(writer, "Filename\t{}\n", self.file_name)?;
+        write!(writer, "File type\t{}\n", self.file_type)?;
         write!(writer, "Encoding\t{}\n", self.encoding)?;
         write!(writer, "Total Sequences\t{}\n", self.total_sequence)?;
-        write!(writer, "Filtered Sequences\t{}\n", self.filtered_sequence)?;
+        write!(
+            writer,
+            "Sequences flagged as poor quality\t{}\n",
+            self.filtered_sequence
+        )?;
         if self.sequence_min_length == self.sequence_max_length {
             write!(writer, "Sequence length\t{}\n", self.sequence_min_length)?;
         } else {
@@ -120,3 +177,33 @@ impl QCReport for BasicStatsReport {
         return Ok(());
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::BasicStats;
+    use crate::trust_seq::fasta::FastaReader;
+    use crate::trust_seq::qc::QCModule;
+    use crate::trust_seq::utils::Sequence;
+
+    #[test]
+    fn test_ambiguity_codes() {
+        let fasta = b">contig1\nACGTN\nRYKMSWBDHV\n";
+        let mut reader = FastaReader::new(&fasta[..]);
+        let mut module = BasicStats::new("contig.fa");
+        while let Some(seq) = reader.next_seq().unwrap() {
+            module.process_sequence(&seq);
+        }
+        assert_eq!([1, 1, 1, 1, 11], module.gatcn_count);
+
+        // The bases BAM records can hold, with = for a reference match.
+        let mut module = BasicStats::new("reads.bam");
+        module.process_sequence(&Sequence {
+            id: b"r1",
+            sequence: b"=ACMGRSVTWYHKDBN",
+            quality: b"IIIIIIIIIIIIIIII",
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        });
+        assert_eq!([1, 1, 1, 1, 12], module.gatcn_count);
+    }
+}
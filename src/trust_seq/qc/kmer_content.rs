@@ -1,68 +1,142 @@
-use crate::trust_seq::group::BaseGroup;
-use crate::trust_seq::math::calc_binomial_distribution_cummulative;
+use crate::trust_seq::group::{BaseGroup, GroupType};
+use crate::trust_seq::html_report;
+use crate::trust_seq::math::{adjust_benjamini_hochberg, calc_binomial_distribution_cummulative};
+use crate::trust_seq::module_config::ModuleConfig;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::BarGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
 use crate::trust_seq::utils::Sequence;
+use crate::trust_seq::utils::{format_double, format_float};
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
+use std::any::Any;
 use std::cmp;
-use std::collections::HashMap;
 use std::f64;
+use std::io;
+use std::io::ErrorKind;
 use std::io::Write;
-use std::str;
+use std::sync::Arc;
 
-#[derive(Clone, Debug)]
+pub const DEFAULT_KMER_SIZE: usize = 7;
+pub const MIN_KMER_SIZE: usize = 2;
+pub const MAX_KMER_SIZE: usize = 10;
+// Reads are sampled 1 in SAMPLING_RATE unless the count is exhaustive.
+const SAMPLING_RATE: u64 = 50;
+// Bases past this position are not counted, unless the positions are
+// kept by logarithmic group.
+const MAX_READ_LENGTH: usize = 500;
 
+/// The multiple testing correction applied to the binomial p-values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Correction {
+    /// p * 4^k, as FastQC does.
+    Legacy,
+    /// p * the number of k-mer and position tests.
+    Bonferroni,
+    /// Benjamini-Hochberg over all k-mer and position tests.
+    Fdr,
+}
+impl Correction {
+    pub fn from_config(module_config: &ModuleConfig) -> Result<Correction, TrustSeqErr> {
+        let name = module_config.get_option("kmer:correction");
+        return match &name.to_lowercase()[..] {
+            "legacy" => Ok(Correction::Legacy),
+            "bonferroni" => Ok(Correction::Bonferroni),
+            "fdr" => Ok(Correction::Fdr),
+            _ => Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "{} is not a kmer correction! Use legacy, bonferroni or fdr.",
+                    name
+                ),
+            ))),
+        };
+    }
+}
+#[derive(Clone, Debug, Default)]
 struct Kmer {
-    sequence: String,
     count: u64,
     positions: Vec<u64>,
 }
 
 impl Kmer {
-    fn new(sequence: &str, position: usize, seq_length: usize) -> Kmer {
-        let mut kmer = Kmer {
-            sequence: sequence.to_string(),
-            count: 1,
-            positions: vec![0; seq_length],
-        };
-        kmer.positions[position] += 1;
-        return kmer;
-    }
     fn increment_count(&mut self, position: usize) {
         self.count += 1;
         if self.positions.len() <= position {
-            self.positions.resize(position, 0);
+            self.positions.resize(position + 1, 0);
         }
         self.positions[position] += 1;
     }
+    fn merge(&mut self, other: &Kmer) {
+        self.count += other.count;
+        if self.positions.len() < other.positions.len() {
+            self.positions.resize(other.positions.len(), 0);
+        }
+        for (idx, count) in other.positions.iter().enumerate() {
+            self.positions[idx] += *count;
+        }
+    }
+}
+fn encode_base(base: u8) -> Option<usize> {
+    return match base {
+        b'A' => Some(0),
+        b'C' => Some(1),
+        b'G' => Some(2),
+        b'T' => Some(3),
+        _ => None,
+    };
+}
+/// Decodes a k-mer packed 2 bits per base, first base in the high bits.
+fn decode_kmer(code: usize, kmer_size: usize) -> String {
+    let mut kmer = String::with_capacity(kmer_size);
+    for i in (0..kmer_size).rev() {
+        kmer.push(['A', 'C', 'G', 'T'][(code >> (2 * i)) & 3]);
+    }
+    return kmer;
 }
 #[derive(Debug)]
-pub struct KmerContent<'a> {
-    config: &'a TrustSeqConfig,
+pub struct KmerContent {
+    config: Arc<TrustSeqConfig>,
+    kmer_size: usize,
+    exhaustive: bool,
     skip_count: u64,
     longest_sequence: usize,
-    kmers: HashMap<String, Kmer>,
-    total_kmer_counts: Vec<Vec<u64>>,
+    // Whether the positions below are logarithmic groups, for long reads.
+    grouped: bool,
+    // Indexed by the 2-bit encoded k-mer, allocated on the first k-mer.
+    kmers: Vec<Kmer>,
+    // The number of k-mers without an N starting at each position.
+    total_kmer_counts: Vec<u64>,
 }
 #[derive(Serialize)]
-struct KmerContentReport {
-    status: QCResult,
-    kmers: Vec<KmerReport>,
+pub struct KmerContentReport {
+    pub status: QCResult,
+    pub groups: Vec<BaseGroup>,
+    pub kmers: Vec<KmerReport>,
 }
 #[derive(Serialize)]
-struct KmerReport {
-    sequence: String,
-    count: u64,
-    p_value: f64,
-    max_obs_exp: f32,
-    max_lower_position: usize,
-    max_upper_position: usize,
+pub struct KmerReport {
+    pub sequence: String,
+    pub count: u64,
+    pub p_value: f64,
+    pub max_obs_exp: f32,
+    pub max_lower_position: usize,
+    pub max_upper_position: usize,
+    pub obs_exp_positions: Vec<f32>,
+}
+/// A k-mer enriched enough somewhere to be reported before correction.
+struct Candidate {
+    code: usize,
+    obs_exp_positions: Vec<f32>,
+    p_values: Vec<f64>,
 }
 impl QCReport for KmerContentReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
-        "Adapter Content"
+        "Kmer Content"
     }
     fn get_status(&self) -> QCResult {
         return self.status;
@@ -71,92 +145,110 @@ impl QCReport for KmerContentReport {
         map.insert(self.get_name().to_string(), value::to_value(&self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        if self.kmers.is_empty() {
+            writeln!(writer, "<p>No overrepresented Kmers</p>")?;
+            return Ok(());
+        }
+        let graph = BarGraph {
+            title: "Enrichment of the most overrepresented Kmers".to_string(),
+            value_title: "Max Obs/Exp".to_string(),
+            labels: self.kmers.iter().map(|k| k.sequence.clone()).collect(),
+            values: self.kmers.iter().map(|k| k.max_obs_exp as f64).collect(),
+        };
+        graph.write_svg(writer)?;
+        let mut text: Vec<u8> = Vec::new();
+        self.print_text_report(&mut text)?;
+        return html_report::write_text_table(&text, writer);
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        if self.kmers.is_empty() {
+            return Ok(());
+        }
         writeln!(
             writer,
             "#Sequence\tCount\tPValue\tObs/Exp Max\tMax Obs/Exp Position"
         )?;
         for kmer in &self.kmers {
+            write!(
+                writer,
+                "{}\t{}\t{}\t{}\t",
+                &kmer.sequence,
+                kmer.count,
+                format_double(kmer.p_value),
+                format_float(kmer.max_obs_exp)
+            )?;
             if kmer.max_lower_position == kmer.max_upper_position {
-                writeln!(
-                    writer,
-                    "{}\t{}\t{}\t{}\t{}",
-                    &kmer.sequence,
-                    kmer.count,
-                    kmer.p_value,
-                    kmer.max_obs_exp,
-                    kmer.max_lower_position
-                )?;
+                writeln!(writer, "{}", kmer.max_lower_position)?;
             } else {
                 writeln!(
                     writer,
-                    "{}\t{}\t{}\t{}\t{}-{}",
-                    &kmer.sequence,
-                    kmer.count,
-                    kmer.p_value,
-                    kmer.max_obs_exp,
-                    kmer.max_lower_position,
-                    kmer.max_upper_position
+                    "{}-{}",
+                    kmer.max_lower_position, kmer.max_upper_position
                 )?;
             }
         }
         return Ok(());
     }
 }
-impl<'a> KmerContent<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> KmerContent<'a> {
+impl KmerContent {
+    pub fn new(config: Arc<TrustSeqConfig>) -> KmerContent {
+        let kmer_size = config.kmer_size.map_or(DEFAULT_KMER_SIZE, |k| k as usize);
+        let exhaustive = config.kmer_exhaustive;
+        let grouped = match config.group_type {
+            GroupType::Logarithmic => true,
+            _ => false,
+        };
         return KmerContent {
             config: config,
+            kmer_size: kmer_size,
+            exhaustive: exhaustive,
             skip_count: 0,
             longest_sequence: 0,
-            kmers: HashMap::new(),
+            grouped: grouped,
+            kmers: Vec::new(),
             total_kmer_counts: Vec::new(),
         };
     }
-    fn ignore_filtered_sequences(&self) -> bool {
-        return true;
-    }
 }
-const MIN_KMER_SIZE: usize = 7;
-const MAX_KMER_SIZE: usize = 7;
 
-impl<'a> KmerContent<'a> {
-    fn add_kmer_count(&mut self, position: usize, kmer_length: usize, kmer: &[u8]) -> bool {
-        if position >= self.total_kmer_counts.len() {
-            self.total_kmer_counts
-                .resize(position + 1, vec![0; MAX_KMER_SIZE]);
-        }
-        if kmer.iter().any(|&c| c == 'N' as u8) {
-            return true;
-        }
-        // println!("position={},kmer_length={},total_kmer_counts.len()={}",
-        //          position,
-        //          kmer_length,
-        //          self.total_kmer_counts.len());
-        self.total_kmer_counts[position][kmer_length - 1] += 1;
+impl QCModule for KmerContent {
+    /// Filtered reads are counted for the sampling, so the reads sampled
+    /// do not depend on how the file is split between threads.
+    fn ignore_filtered_sequences(&self) -> bool {
         return false;
     }
-}
-
-impl<'a> QCModule for KmerContent<'a> {
     fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        if self.longest_sequence < self.kmer_size {
+            results.push(Box::new(KmerContentReport {
+                status: QCResult::Pass,
+                groups: Vec::new(),
+                kmers: Vec::new(),
+            }));
+            return Ok(());
+        }
+        let module_config = &self.config.module_config;
+        let correction = Correction::from_config(module_config)?;
+        let p_value_cutoff = module_config.get("kmer:pvalue");
+        let obs_exp_cutoff = module_config.get("kmer:obs_exp") as f32;
+        let top_n = module_config.get("kmer:top_n") as usize;
         let groups = BaseGroup::make_base_groups(
             &self.config.group_type,
-            self.longest_sequence - MIN_KMER_SIZE + 1,
+            self.longest_sequence - self.kmer_size + 1,
         );
+        let total_kmer_count: u64 = self.total_kmer_counts.iter().sum();
 
-        let mut uneven_kmers: Vec<KmerReport> = Vec::new();
-        for kmer in &mut self.kmers.values() {
-            let total_kmer_count = self
-                .total_kmer_counts
-                .iter()
-                .fold(0u64, |acc, ref k| acc + k[kmer.sequence.len() - 1]);
-            if kmer.sequence == "GAGCTCA" {
-                println!("{} {} {:?}", kmer.sequence, kmer.count, kmer.positions);
+        let mut candidates: Vec<Candidate> = Vec::new();
+        // The p-values of every test where a k-mer was enriched, for fdr.
+        let mut enriched_p_values: Vec<f64> = Vec::new();
+        let mut test_count = 0usize;
+        for (code, kmer) in self.kmers.iter().enumerate() {
+            if kmer.count == 0 {
+                continue;
             }
             let expected_proportion = kmer.count as f32 / total_kmer_count as f32;
             let mut obs_exp_positions: Vec<f32> = vec![0f32; groups.len()];
-            let mut binomial_p_values: Vec<f32> = vec![0f32; groups.len()];
+            let mut binomial_p_values: Vec<f64> = vec![1.0; groups.len()];
             for (g, group) in groups.iter().enumerate() {
                 // This is a summation of the number of Kmers of this length which
                 // fall into this base group
@@ -165,38 +257,80 @@ impl<'a> QCModule for KmerContent<'a> {
                 // This is a summation of the number of hit Kmers which fall within
                 // this base group.
                 let mut total_group_hits = 0u64;
-                let p_max = cmp::min(group.upper_count, kmer.positions.len());
-                for p in (group.lower_count - 1)..p_max {
-                    total_group_count += self.total_kmer_counts[p][kmer.sequence.len() - 1];
-                    total_group_hits += kmer.positions[p];
+                let (p_min, p_max) = if self.grouped {
+                    (g, g + 1)
+                } else {
+                    (group.lower_count - 1, group.upper_count)
+                };
+                let p_max = cmp::min(p_max, self.total_kmer_counts.len());
+                for p in p_min..p_max {
+                    total_group_count += self.total_kmer_counts[p];
+                    total_group_hits += kmer.positions.get(p).map_or(0, |c| *c);
                 }
                 let predicted: f32 = expected_proportion as f32 * total_group_count as f32;
                 obs_exp_positions[g] = total_group_hits as f32 / predicted;
                 if total_group_hits as f32 > predicted {
-                    let val =
-                        (1.0 - calc_binomial_distribution_cummulative(
+                    binomial_p_values[g] = 1.0
+                        - calc_binomial_distribution_cummulative(
                             total_group_count as usize,
                             expected_proportion as f64,
                             total_group_hits as i32,
-                        )) * (4.0f64).powi(kmer.sequence.len() as i32);
-                    binomial_p_values[g] = val as f32;
-                } else {
-                    binomial_p_values[g] = 1.0;
+                        );
+                    if correction == Correction::Fdr {
+                        enriched_p_values.push(binomial_p_values[g]);
+                    }
                 }
             }
+            test_count += groups.len();
+            // Corrections only raise p-values, so this drops nothing.
+            let is_candidate = (0..groups.len()).any(|g| {
+                binomial_p_values[g] < p_value_cutoff && obs_exp_positions[g] > obs_exp_cutoff
+            });
+            if is_candidate {
+                candidates.push(Candidate {
+                    code: code,
+                    obs_exp_positions: obs_exp_positions,
+                    p_values: binomial_p_values,
+                });
+            }
+        }
+        enriched_p_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let adjusted_p_values = adjust_benjamini_hochberg(&enriched_p_values, test_count);
+        let correct = |p: f64| -> f32 {
+            let corrected = match correction {
+                Correction::Legacy => p * (4.0f64).powi(self.kmer_size as i32),
+                Correction::Bonferroni => (p * test_count as f64).min(1.0),
+                Correction::Fdr => {
+                    let rank = enriched_p_values.partition_point(|v| *v <= p);
+                    if rank == 0 {
+                        1.0
+                    } else {
+                        adjusted_p_values[rank - 1]
+                    }
+                }
+            };
+            return corrected as f32;
+        };
 
-            let mut lowest_p_value = 0.01;
-            for i in 0..groups.len() {
-                if binomial_p_values[i] < lowest_p_value && obs_exp_positions[i] > 5f32 {
-                    lowest_p_value = binomial_p_values[i];
+        let scale = if self.exhaustive { 1 } else { SAMPLING_RATE };
+        let mut uneven_kmers: Vec<KmerReport> = Vec::new();
+        for candidate in candidates {
+            let mut lowest_p_value = p_value_cutoff as f32;
+            for (i, p) in candidate.p_values.iter().enumerate() {
+                if candidate.obs_exp_positions[i] <= obs_exp_cutoff {
+                    continue;
+                }
+                let corrected = correct(*p);
+                if corrected < lowest_p_value {
+                    lowest_p_value = corrected;
                 }
             }
-            if lowest_p_value < 0.01 {
+            if lowest_p_value < p_value_cutoff as f32 {
                 let mut max_obs_exp = 0.0;
                 let mut max_lower_position = 0usize;
                 let mut max_upper_position = 0usize;
 
-                for (idx, val) in obs_exp_positions.iter().enumerate() {
+                for (idx, val) in candidate.obs_exp_positions.iter().enumerate() {
                     if max_obs_exp < *val {
                         max_obs_exp = *val;
                         max_lower_position = groups[idx].lower_count;
@@ -204,66 +338,207 @@ impl<'a> QCModule for KmerContent<'a> {
                     }
                 }
                 uneven_kmers.push(KmerReport {
-                    sequence: kmer.sequence.clone(),
-                    count: kmer.count * 5,
+                    sequence: decode_kmer(candidate.code, self.kmer_size),
+                    count: self.kmers[candidate.code].count * scale,
                     p_value: lowest_p_value as f64,
                     max_obs_exp: max_obs_exp,
                     max_lower_position: max_lower_position,
                     max_upper_position: max_upper_position,
+                    obs_exp_positions: candidate.obs_exp_positions,
                 });
             }
         }
-        uneven_kmers.sort_by(|a, b| b.max_obs_exp.partial_cmp(&a.max_obs_exp).unwrap());
-        let mut final_kmers: Vec<KmerReport> = Vec::new();
-        for kmer in uneven_kmers {
-            final_kmers.push(kmer);
-            if 20 <= final_kmers.len() {
-                break;
-            }
-        }
-        let min_p_value = if final_kmers.len() > 0 {
-            -1.0 * final_kmers[0].p_value.log10()
+        uneven_kmers.sort_by(|a, b| {
+            b.max_obs_exp
+                .partial_cmp(&a.max_obs_exp)
+                .unwrap()
+                .then_with(|| a.sequence.cmp(&b.sequence))
+        });
+        uneven_kmers.truncate(top_n);
+        let min_p_value = if uneven_kmers.len() > 0 {
+            -1.0 * uneven_kmers[0].p_value.log10()
         } else {
             1.0
         };
-        let status = if min_p_value > self.config.module_config.get("kmer:error") {
+        let status = if min_p_value > module_config.get("kmer:error") {
             QCResult::Fail
-        } else if min_p_value > self.config.module_config.get("kmer:warn") {
+        } else if min_p_value > module_config.get("kmer:warn") {
             QCResult::Warn
         } else {
             QCResult::Pass
         };
         results.push(Box::new(KmerContentReport {
             status: status,
-            kmers: final_kmers,
+            groups: groups,
+            kmers: uneven_kmers,
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<KmerContent>().unwrap();
+        self.skip_count += other.skip_count;
+        self.longest_sequence = cmp::max(self.longest_sequence, other.longest_sequence);
+        if self.total_kmer_counts.len() < other.total_kmer_counts.len() {
+            self.total_kmer_counts
+                .resize(other.total_kmer_counts.len(), 0);
+        }
+        for (c, o) in self
+            .total_kmer_counts
+            .iter_mut()
+            .zip(other.total_kmer_counts.iter())
+        {
+            *c += *o;
+        }
+        if self.kmers.is_empty() {
+            self.kmers = other.kmers.clone();
+            return;
+        }
+        for (kmer, other_kmer) in self.kmers.iter_mut().zip(other.kmers.iter()) {
+            if other_kmer.count > 0 {
+                kmer.merge(other_kmer);
+            }
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         self.skip_count += 1;
-        if self.skip_count % 50 != 0 {
+        if seq.filtered
+            || seq.excluded
+            || (!self.exhaustive && self.skip_count % SAMPLING_RATE != 0)
+        {
             return;
         }
         let mut seq = seq.sequence;
-        if seq.len() > 500 {
-            seq = &seq[0..500];
+        if seq.len() > MAX_READ_LENGTH && !self.grouped {
+            seq = &seq[0..MAX_READ_LENGTH];
         }
         self.longest_sequence = cmp::max(self.longest_sequence, seq.len());
-        let kmer_size = 7;
-        for i in 0..(seq.len() - kmer_size + 1) {
-            let kmer = &seq[i..(i + kmer_size)];
-            if self.add_kmer_count(i, kmer_size, kmer) {
-                continue;
+        if seq.len() < self.kmer_size {
+            return;
+        }
+        let kmer_count = seq.len() - self.kmer_size + 1;
+        let position_count = if self.grouped {
+            BaseGroup::get_logarithmic_index(kmer_count - 1) + 1
+        } else {
+            kmer_count
+        };
+        if self.total_kmer_counts.len() < position_count {
+            self.total_kmer_counts.resize(position_count, 0);
+        }
+        if self.kmers.is_empty() {
+            self.kmers = vec![Kmer::default(); 1 << (2 * self.kmer_size)];
+        }
+        let mask = (1usize << (2 * self.kmer_size)) - 1;
+        let mut code = 0usize;
+        // The number of bases other than N ending at the current one.
+        let mut valid = 0;
+        for (idx, base) in seq.iter().enumerate() {
+            match encode_base(*base) {
+                Some(b) => {
+                    code = ((code << 2) | b) & mask;
+                    valid += 1;
+                }
+                None => valid = 0,
             }
-            let kmer_str = unsafe { str::from_utf8_unchecked(kmer) }.to_string();
-            if self.kmers.contains_key(&kmer_str) {
-                self.kmers.get_mut(&kmer_str).unwrap().increment_count(i);
-            } else {
-                self.kmers.insert(
-                    kmer_str.to_string(),
-                    Kmer::new(&kmer_str, i, seq.len() - kmer_size + 1),
-                );
+            if valid >= self.kmer_size {
+                let mut position = idx + 1 - self.kmer_size;
+                if self.grouped {
+                    position = BaseGroup::get_logarithmic_index(position);
+                }
+                self.total_kmer_counts[position] += 1;
+                self.kmers[code].increment_count(position);
             }
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::{decode_kmer, Correction, KmerContent};
+    use crate::trust_seq::group::GroupType;
+    use crate::trust_seq::module_config::ModuleConfig;
+    use crate::trust_seq::qc::QCModule;
+    use crate::trust_seq::trust_seq::TrustSeqConfig;
+    use crate::trust_seq::utils::Sequence;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_decode_kmer() {
+        assert_eq!("ACGT", decode_kmer(0b00011011, 4));
+        assert_eq!("TTAAC", decode_kmer(0b1111000001, 5));
+    }
+    #[test]
+    fn test_correction_from_config() {
+        let mut module_config = ModuleConfig::new();
+        assert_eq!(
+            Correction::Legacy,
+            Correction::from_config(&module_config).unwrap()
+        );
+        module_config.load(&b"kmer\tcorrection\tFDR\n"[..]).unwrap();
+        assert_eq!(
+            Correction::Fdr,
+            Correction::from_config(&module_config).unwrap()
+        );
+        module_config
+            .load(&b"kmer\tcorrection\tholm\n"[..])
+            .unwrap();
+        assert!(Correction::from_config(&module_config).is_err());
+    }
+    #[test]
+    fn test_exhaustive_count() {
+        let mut config = TrustSeqConfig::new();
+        config.kmer_size = Some(3);
+        config.kmer_exhaustive = true;
+        let mut module = KmerContent::new(Arc::new(config));
+        module.process_sequence(&Sequence {
+            id: b"@r1",
+            sequence: b"ACGNACGTAC",
+            quality: b"IIIIIIIIII",
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        });
+        // ACG at 0 and 4, CGT at 5, GTA at 6 and TAC at 7. None spans the N.
+        assert_eq!(vec![1, 0, 0, 0, 1, 1, 1, 1], module.total_kmer_counts);
+        let acg = &module.kmers[0b000110];
+        assert_eq!(2, acg.count);
+        assert_eq!(vec![1, 0, 0, 0, 1], acg.positions);
+        assert_eq!(1, module.kmers[0b011011].count);
+        // Reads shorter than k are skipped.
+        module.process_sequence(&Sequence {
+            id: b"@r2",
+            sequence: b"AC",
+            quality: b"II",
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        });
+        assert_eq!(5, module.kmers.iter().map(|k| k.count).sum::<u64>());
+    }
+    #[test]
+    fn test_grouped_positions() {
+        let mut config = TrustSeqConfig::new();
+        config.kmer_size = Some(3);
+        config.kmer_exhaustive = true;
+        config.group_type = GroupType::Logarithmic;
+        let mut module = KmerContent::new(Arc::new(config));
+        let mut sequence = vec![b'A'; 2000];
+        sequence[1500..1503].copy_from_slice(b"CGT");
+        module.process_sequence(&Sequence {
+            id: b"@r1",
+            sequence: &sequence,
+            quality: &vec![b'I'; 2000],
+            filtered: false,
+            excluded: false,
+            mapping: None,
+        });
+        // Past 500 bases, and by group: 1000-1499 and 1500-1999.
+        assert_eq!(9 + 18 * 2 + 2, module.total_kmer_counts.len());
+        assert_eq!(500, module.total_kmer_counts[9 + 18 * 2]);
+        assert_eq!(499, module.total_kmer_counts[9 + 18 * 2 + 1]);
+        let cgt = &module.kmers[0b011011].positions;
+        assert_eq!(1, cgt.iter().sum::<u64>());
+        assert_eq!(1, cgt[9 + 18 * 2 + 1]);
+    }
+}
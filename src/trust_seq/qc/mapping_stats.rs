@@ -0,0 +1,167 @@
+use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
+use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Mapped and unmapped reads and the MAPQ distribution of SAM and BAM
+/// input. Reads from other formats have no mapping and are not counted.
+pub struct MappingStats {
+    config: Arc<TrustSeqConfig>,
+    unmapped_count: u64,
+    reverse_count: u64,
+    // Mapped reads by MAPQ, 255 being an unavailable MAPQ.
+    mapq_counts: Vec<u64>,
+}
+#[derive(Serialize)]
+pub struct MappingStatsReport {
+    pub status: QCResult,
+    pub total_count: u64,
+    pub mapped_count: u64,
+    pub unmapped_count: u64,
+    pub reverse_count: u64,
+    pub unmapped_percentage: f64,
+    pub mapq_counts: Vec<(u8, u64)>,
+}
+impl MappingStats {
+    pub fn new(config: Arc<TrustSeqConfig>) -> MappingStats {
+        return MappingStats {
+            config: config,
+            unmapped_count: 0,
+            reverse_count: 0,
+            mapq_counts: vec![0; 256],
+        };
+    }
+    fn mapped_count(&self) -> u64 {
+        return self.mapq_counts.iter().sum();
+    }
+}
+impl QCReport for MappingStatsReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Mapping statistics";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(
+            writer,
+            "<p>{} of {} reads mapped, {}% unmapped.</p>",
+            self.mapped_count,
+            self.total_count,
+            format_double(self.unmapped_percentage)
+        )?;
+        let percentages: Vec<f64> = self
+            .mapq_counts
+            .iter()
+            .map(|c| c.1 as f64 * 100.0 / self.mapped_count.max(1) as f64)
+            .collect();
+        let graph = LineGraph {
+            title: "MAPQ distribution of the mapped reads".to_string(),
+            x_title: "Mapping Quality",
+            x_labels: self.mapq_counts.iter().map(|c| c.0.to_string()).collect(),
+            series_names: vec!["% Mapped Reads".to_string()],
+            series: vec![percentages],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Measure\tValue")?;
+        writeln!(writer, "Total Sequences\t{}", self.total_count)?;
+        writeln!(writer, "Mapped\t{}", self.mapped_count)?;
+        writeln!(writer, "Unmapped\t{}", self.unmapped_count)?;
+        writeln!(
+            writer,
+            "%Unmapped\t{}",
+            format_double(self.unmapped_percentage)
+        )?;
+        writeln!(writer, "Reverse strand\t{}", self.reverse_count)?;
+        writeln!(writer, "#MAPQ\tCount")?;
+        for count in &self.mapq_counts {
+            writeln!(writer, "{}\t{}", count.0, count.1)?;
+        }
+        return Ok(());
+    }
+}
+impl QCModule for MappingStats {
+    fn ignore_in_report(&self) -> bool {
+        return self.unmapped_count + self.mapped_count() == 0;
+    }
+    /// Unmapped reads excluded by --mapped_only still count here.
+    fn ignore_filtered_sequences(&self) -> bool {
+        return false;
+    }
+    fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        let mapped_count = self.mapped_count();
+        let total_count = mapped_count + self.unmapped_count;
+        if total_count == 0 {
+            return Ok(());
+        }
+        let unmapped_percentage = self.unmapped_count as f64 * 100.0 / total_count as f64;
+        let status = if unmapped_percentage > self.config.module_config.get("mapping:error") {
+            QCResult::Fail
+        } else if unmapped_percentage > self.config.module_config.get("mapping:warn") {
+            QCResult::Warn
+        } else {
+            QCResult::Pass
+        };
+        let mapq_counts = self
+            .mapq_counts
+            .iter()
+            .enumerate()
+            .filter(|c| *c.1 > 0)
+            .map(|c| (c.0 as u8, *c.1))
+            .collect();
+        reports.push(Box::new(MappingStatsReport {
+            status: status,
+            total_count: total_count,
+            mapped_count: mapped_count,
+            unmapped_count: self.unmapped_count,
+            reverse_count: self.reverse_count,
+            unmapped_percentage: unmapped_percentage,
+            mapq_counts: mapq_counts,
+        }));
+        return Ok(());
+    }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<MappingStats>().unwrap();
+        self.unmapped_count += other.unmapped_count;
+        self.reverse_count += other.reverse_count;
+        for (count, other_count) in self.mapq_counts.iter_mut().zip(other.mapq_counts.iter()) {
+            *count += *other_count;
+        }
+    }
+    fn process_sequence(&mut self, seq: &Sequence) -> () {
+        let mapping = match seq.mapping {
+            Some(m) => m,
+            None => return,
+        };
+        if mapping.is_unmapped() {
+            self.unmapped_count += 1;
+            return;
+        }
+        if mapping.is_reverse() {
+            self.reverse_count += 1;
+        }
+        self.mapq_counts[mapping.mapq as usize] += 1;
+    }
+}
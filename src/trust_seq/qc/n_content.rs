@@ -1,25 +1,29 @@
 use crate::trust_seq::group::BaseGroup;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 
-pub struct NContent<'a> {
-    config: &'a TrustSeqConfig,
+pub struct NContent {
+    config: Arc<TrustSeqConfig>,
     n_counts: Vec<u64>,
     not_n_counts: Vec<u64>,
 }
 #[derive(Serialize)]
-struct NContentReport {
-    status: QCResult,
-    groups: Vec<BaseGroup>,
-    percentages: Vec<f64>,
+pub struct NContentReport {
+    pub status: QCResult,
+    pub groups: Vec<BaseGroup>,
+    pub percentages: Vec<f64>,
 }
-impl<'a> NContent<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> NContent {
+impl NContent {
+    pub fn new(config: Arc<TrustSeqConfig>) -> NContent {
         return NContent {
             config: config,
             n_counts: Vec::new(),
@@ -28,6 +32,9 @@ impl<'a> NContent<'a> {
     }
 }
 impl QCReport for NContentReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         "Per base N content"
     }
@@ -39,24 +46,28 @@ impl QCReport for NContentReport {
         return Ok(());
     }
 
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let graph = LineGraph {
+            title: "N content across all bases".to_string(),
+            x_title: "Position in read (bp)",
+            x_labels: self.groups.iter().map(|g| g.to_string()).collect(),
+            series_names: vec!["%N".to_string()],
+            series: vec![self.percentages.clone()],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
         writeln!(writer, "#Base\tN-Count")?;
-        for idx in 0..self.groups.len() {
-            let group = &self.groups[idx];
-            if group.lower_count == group.upper_count {
-                writeln!(writer, "{}\t{}", group.lower_count, self.percentages[idx])?;
-            } else {
-                writeln!(
-                    writer,
-                    "{}-{}\t{}",
-                    group.lower_count, group.upper_count, self.percentages[idx]
-                )?;
-            }
+        for (idx, group) in self.groups.iter().enumerate() {
+            writeln!(writer, "{}\t{}", group, format_double(self.percentages[idx]))?;
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for NContent<'a> {
+impl QCModule for NContent {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         let mut percentages: Vec<f64> = Vec::new();
         let mut max_percentage: f64 = 0.0;
@@ -89,6 +100,20 @@ impl<'a> QCModule for NContent<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<NContent>().unwrap();
+        if self.n_counts.len() < other.n_counts.len() {
+            self.n_counts.resize(other.n_counts.len(), 0);
+            self.not_n_counts.resize(other.n_counts.len(), 0);
+        }
+        for (idx, count) in other.n_counts.iter().enumerate() {
+            self.n_counts[idx] += *count;
+            self.not_n_counts[idx] += other.not_n_counts[idx];
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         let len = seq.sequence.len();
         if self.n_counts.len() < len {
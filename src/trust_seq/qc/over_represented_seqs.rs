@@ -1,39 +1,50 @@
 use crate::trust_seq::contaminant::find_contaminant;
-use crate::trust_seq::contaminant::Contaminant;
-use crate::trust_seq::contaminant_list::CONTAMINANT_LIST;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::Value;
+use std::any::Any;
 use std::collections::hash_map::HashMap;
-use std::io::BufReader;
+use std::collections::BTreeMap;
 use std::io::Write;
+use std::mem;
+use std::sync::Arc;
 
-const OBSERVATION_CUTOFF: usize = 100000;
+/// The distinct sequences tracked before the set is frozen, as in FastQC.
+pub const OBSERVATION_CUTOFF: usize = 100000;
 
-pub struct OverRepresentedSeqs<'a> {
-    config: &'a TrustSeqConfig,
+pub struct OverRepresentedSeqs {
+    config: Arc<TrustSeqConfig>,
+    observation_cutoff: usize,
     count: u64,
     unique_sequence_count: usize,
     count_at_unique_limit: u64,
     frozen: bool,
-    sequences: HashMap<String, u32>,
+    sequences: HashMap<String, SequenceCount>,
+}
+struct SequenceCount {
+    count: u32,
+    // The 1-based index of the read this sequence was first seen in.
+    first_seen: u64,
 }
 #[derive(Serialize)]
-struct OverRepresentedReport {
-    status: QCResult,
-    over_represented: Vec<OverRepresentedSeq>,
+pub struct OverRepresentedReport {
+    pub status: QCResult,
+    pub over_represented: Vec<OverRepresentedSeq>,
 }
 #[derive(Serialize)]
-struct OverRepresentedSeq {
-    seq: String,
-    count: u32,
-    percentage: f64,
-    possible_source: String,
+pub struct OverRepresentedSeq {
+    pub seq: String,
+    pub count: u32,
+    pub percentage: f64,
+    pub possible_source: String,
 }
-const DUP_LEVEL_LABELS: [(usize, &'static str); 16] = [
+/// The lowest `duplication level - 1` counted in each bin, and its label.
+const DUP_LEVEL_LABELS: [(u32, &'static str); 16] = [
     (0, "1"),
     (1, "2"),
     (2, "3"),
@@ -44,16 +55,24 @@ const DUP_LEVEL_LABELS: [(usize, &'static str); 16] = [
     (7, "8"),
     (8, "9"),
     (9, ">10"),
-    (49, ">50"),
-    (99, ">100"),
-    (499, "500"),
-    (999, ">1k"),
-    (4999, ">5k"),
-    (9999, ">10k"),
+    (50, ">50"),
+    (100, ">100"),
+    (500, ">500"),
+    (1000, ">1k"),
+    (5000, ">5k"),
+    (10000, ">10k+"),
 ];
-impl<'a> OverRepresentedSeqs<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> OverRepresentedSeqs<'a> {
+fn get_dup_slot(dup_level: u32) -> usize {
+    let dup_slot = dup_level - 1;
+    return DUP_LEVEL_LABELS
+        .iter()
+        .rposition(|l| dup_slot >= l.0)
+        .unwrap_or(0);
+}
+impl OverRepresentedSeqs {
+    pub fn new(config: Arc<TrustSeqConfig>) -> OverRepresentedSeqs {
         return OverRepresentedSeqs {
+            observation_cutoff: config.observation_cutoff,
             config: config,
             count: 0,
             unique_sequence_count: 0,
@@ -64,18 +83,20 @@ impl<'a> OverRepresentedSeqs<'a> {
     }
 }
 #[derive(Serialize)]
-struct DuplicationLevelReport {
-    status: QCResult,
-    total_dedup_percentage: f64,
-    duplication_levels: Vec<DuplicationLevel>,
+pub struct DuplicationLevelReport {
+    pub status: QCResult,
+    pub total_dedup_percentage: f64,
+    pub duplication_levels: Vec<DuplicationLevel>,
 }
 #[derive(Serialize)]
-struct DuplicationLevel {
-    label: &'static str,
-    deduplicated_percentage: f64,
-    total_percentage: f64,
+pub struct DuplicationLevel {
+    pub label: &'static str,
+    pub deduplicated_percentage: f64,
+    pub total_percentage: f64,
 }
 
+/// Estimates how many distinct sequences had `duplication_level` copies,
+/// including the ones missed after the unique sequence limit was reached.
 fn get_corrected_count(
     count_at_limit: u64,
     total_count: u64,
@@ -89,10 +110,19 @@ fn get_corrected_count(
         return number_of_observations as f64;
     }
 
+    // Probability of not seeing a sequence with this duplication level in
+    // the first count_at_limit reads. Below limit_of_caring the correction
+    // adds less than 0.01 of an observation.
     let mut p_not_seeing_at_limit = 1f64;
+    let limit_of_caring =
+        1.0 - (number_of_observations as f64 / (number_of_observations as f64 + 0.01));
     for i in 0..count_at_limit {
-        p_not_seeing_at_limit =
+        p_not_seeing_at_limit *=
             ((total_count - i) - duplication_level) as f64 / (total_count - i) as f64;
+        if p_not_seeing_at_limit < limit_of_caring {
+            p_not_seeing_at_limit = 0.0;
+            break;
+        }
     }
     return number_of_observations as f64 / (1.0 - p_not_seeing_at_limit);
 }
@@ -101,56 +131,66 @@ fn calculate_report(
 ) -> Result<DuplicationLevelReport, TrustSeqErr> {
     let mut deduplicated_percentages: [f64; 16] = [0.0; 16];
     let mut total_percentages: [f64; 16] = [0.0; 16];
-    let mut collated_counts: HashMap<u32, u32> = HashMap::new();
+    let mut collated_counts: BTreeMap<u32, u64> = BTreeMap::new();
     for count in over_represented_seqs.sequences.values() {
-        let c = collated_counts.entry(*count).or_insert(0);
+        let c = collated_counts.entry(count.count).or_insert(0);
         *c += 1;
     }
-    let mut corrected_counts: HashMap<u32, f64> = HashMap::new();
-    for (dup_level, count) in &collated_counts {
-        corrected_counts.insert(
-            *dup_level,
-            get_corrected_count(
-                over_represented_seqs.count_at_unique_limit,
-                over_represented_seqs.count,
-                *dup_level as u64,
-                *count as u64,
-            ),
-        );
-    }
     let mut dedup_total: f64 = 0.0;
     let mut row_total: f64 = 0.0;
-    for (dl, c) in &corrected_counts {
+    for (dl, c) in &collated_counts {
+        let count = get_corrected_count(
+            over_represented_seqs.count_at_unique_limit,
+            over_represented_seqs.count,
+            *dl as u64,
+            *c,
+        );
         let dup_level = *dl as f64;
-        let count = *c as f64;
         dedup_total += count;
         row_total += count * dup_level;
-        let mut dup_slot: usize = (*dl - 1) as usize;
-        for i in 0..DUP_LEVEL_LABELS.len() {
-            let idx = DUP_LEVEL_LABELS.len() - i - 1;
-            if dup_level > DUP_LEVEL_LABELS[idx].0 as f64 {
-                dup_slot = idx;
-                break;
-            }
-        }
+        let dup_slot = get_dup_slot(*dl);
         deduplicated_percentages[dup_slot] += count;
         total_percentages[dup_slot] += count * dup_level;
     }
     let mut vecs: Vec<DuplicationLevel> = Vec::new();
     for idx in 0..deduplicated_percentages.len() {
+        let (dedup, total) = if row_total > 0.0 {
+            (
+                deduplicated_percentages[idx] / dedup_total * 100.0,
+                total_percentages[idx] / row_total * 100.0,
+            )
+        } else {
+            (0.0, 0.0)
+        };
         vecs.push(DuplicationLevel {
             label: DUP_LEVEL_LABELS[idx].1,
-            deduplicated_percentage: deduplicated_percentages[idx] * 100.0 / dedup_total,
-            total_percentage: total_percentages[idx] * 100.0 / row_total,
+            deduplicated_percentage: dedup,
+            total_percentage: total,
         });
     }
+    let total_dedup_percentage = if row_total > 0.0 {
+        dedup_total / row_total * 100.0
+    } else {
+        100.0
+    };
+    let config = &over_represented_seqs.config.module_config;
+    let status = if total_dedup_percentage < config.get("duplication:error") {
+        QCResult::Fail
+    } else if total_dedup_percentage < config.get("duplication:warn") {
+        QCResult::Warn
+    } else {
+        QCResult::Pass
+    };
     return Ok(DuplicationLevelReport {
-        status: QCResult::Pass,
-        total_dedup_percentage: dedup_total / row_total * 100.0,
+        status: status,
+        total_dedup_percentage: total_dedup_percentage,
         duplication_levels: vecs,
     });
 }
 impl QCReport for DuplicationLevelReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Sequence Duplication Levels";
     }
@@ -161,26 +201,66 @@ impl QCReport for DuplicationLevelReport {
         map.insert(self.get_name().to_string(), value::to_value(self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let graph = LineGraph {
+            title: format!(
+                "Percent of seqs remaining if deduplicated {:.2}%",
+                self.total_dedup_percentage
+            ),
+            x_title: "Sequence Duplication Level",
+            x_labels: self
+                .duplication_levels
+                .iter()
+                .map(|l| l.label.to_string())
+                .collect(),
+            series_names: vec![
+                "% Deduplicated sequences".to_string(),
+                "% Total sequences".to_string(),
+            ],
+            series: vec![
+                self.duplication_levels
+                    .iter()
+                    .map(|l| l.deduplicated_percentage)
+                    .collect(),
+                self.duplication_levels
+                    .iter()
+                    .map(|l| l.total_percentage)
+                    .collect(),
+            ],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
-        writeln!(writer, "#Total Deduplicated Percentage")?;
-        writeln!(writer, "{}", self.total_dedup_percentage)?;
         writeln!(
             writer,
-            "#Duplication Level\tPercentage of deduplicated\tPercentage of total\n"
+            "#Total Deduplicated Percentage\t{}",
+            format_double(self.total_dedup_percentage)
+        )?;
+        writeln!(
+            writer,
+            "#Duplication Level\tPercentage of deduplicated\tPercentage of total"
         )?;
         for seq in &self.duplication_levels {
             writeln!(
                 writer,
                 "{}\t{}\t{}",
-                seq.label, seq.deduplicated_percentage, seq.total_percentage
+                seq.label,
+                format_double(seq.deduplicated_percentage),
+                format_double(seq.total_percentage)
             )?;
         }
         return Ok(());
     }
 }
 impl QCReport for OverRepresentedReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
-        return "Sequence Duplication Levels";
+        return "Overrepresented sequences";
     }
     fn get_status(&self) -> QCResult {
         return self.status;
@@ -190,47 +270,53 @@ impl QCReport for OverRepresentedReport {
         return Ok(());
     }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        if self.over_represented.is_empty() {
+            return Ok(());
+        }
         writeln!(writer, "#Sequence\tCount\tPercentage\tPossible Source")?;
         for seq in &self.over_represented {
             writeln!(
                 writer,
                 "{}\t{}\t{}\t{}",
-                seq.seq, seq.count, seq.percentage, seq.possible_source
+                seq.seq,
+                seq.count,
+                format_double(seq.percentage),
+                seq.possible_source
             )?;
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for OverRepresentedSeqs<'a> {
+impl QCModule for OverRepresentedSeqs {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         reports.push(Box::new(calculate_report(self)?));
+        let error_th = self.config.module_config.get("overrepresented:error");
+        let warn_th = self.config.module_config.get("overrepresented:warn");
         let mut seqs: Vec<OverRepresentedSeq> = Vec::new();
-        let cons = Contaminant::load_contaminants(BufReader::new(CONTAMINANT_LIST.as_bytes()));
         for (sequence, count) in &self.sequences {
-            let percantage: f64 = *count as f64 * 100.0 / self.count as f64;
+            let percantage: f64 = count.count as f64 / self.count as f64 * 100.0;
 
-            if 0.1 < percantage {
-                let possible_source = match find_contaminant(&cons, sequence.as_bytes()) {
-                    Some(hit) => format!(
-                        "{} ({}% over {} bp) {}",
-                        hit.contaminant.name, hit.percent_id, hit.length, hit.direction
-                    ),
-                    None => "No Hit".to_string(),
-                };
+            if warn_th < percantage {
+                let possible_source =
+                    match find_contaminant(&self.config.contaminants, sequence.as_bytes()) {
+                        Some(hit) => format!(
+                            "{} ({}% over {}bp)",
+                            hit.contaminant.name, hit.percent_id, hit.length
+                        ),
+                        None => "No Hit".to_string(),
+                    };
                 seqs.push(OverRepresentedSeq {
                     seq: sequence.to_string(),
-                    count: *count,
+                    count: count.count,
                     percentage: percantage,
                     possible_source: possible_source,
                 });
             }
         }
 
-        seqs.sort_by(|a, b| b.count.cmp(&a.count));
+        seqs.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.seq.cmp(&b.seq)));
 
         let max_percant = seqs.get(0).map_or(0.0, |s| s.percentage);
-        let error_th = self.config.module_config.get("overrepresented:error");
-        let warn_th = self.config.module_config.get("overrepresented:warn");
         let status = if max_percant > error_th {
             QCResult::Fail
         } else if max_percant > warn_th {
@@ -244,6 +330,52 @@ impl<'a> QCModule for OverRepresentedSeqs<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    /// Which sequences are tracked depends on the order they come in.
+    fn take_chunk(&mut self) -> Option<Box<QCModule>> {
+        let empty = OverRepresentedSeqs::new(self.config.clone());
+        return Some(Box::new(mem::replace(self, empty)));
+    }
+    /// Exact as long as `other` did not reach the observation cutoff itself.
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<OverRepresentedSeqs>()
+            .unwrap();
+        let mut new_seqs: Vec<(&String, &SequenceCount)> = Vec::new();
+        for (sequence, count) in &other.sequences {
+            match self.sequences.get_mut(sequence) {
+                Some(c) => c.count += count.count,
+                None => new_seqs.push((sequence, count)),
+            }
+        }
+        // Unseen sequences are added in the order they were first seen, so
+        // the set is frozen at the same read as in a single pass.
+        new_seqs.sort_by_key(|s| s.1.first_seen);
+        for (sequence, count) in new_seqs {
+            if self.frozen {
+                break;
+            }
+            let first_seen = self.count + count.first_seen;
+            self.sequences.insert(
+                sequence.clone(),
+                SequenceCount {
+                    count: count.count,
+                    first_seen: first_seen,
+                },
+            );
+            if self.sequences.len() == self.observation_cutoff {
+                self.frozen = true;
+                self.count_at_unique_limit = first_seen;
+            }
+        }
+        self.count += other.count;
+        if !self.frozen {
+            self.count_at_unique_limit = self.count;
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         self.count += 1;
         if !self.frozen {
@@ -255,14 +387,117 @@ impl<'a> QCModule for OverRepresentedSeqs<'a> {
             String::from_utf8_lossy(seq.sequence).to_string()
         };
         if self.sequences.contains_key(&seq2) {
-            *self.sequences.get_mut(&seq2).unwrap() += 1;
+            self.sequences.get_mut(&seq2).unwrap().count += 1;
         } else {
             if !self.frozen {
-                self.sequences.insert(seq2, 1);
-                if self.sequences.len() == OBSERVATION_CUTOFF {
+                self.sequences.insert(
+                    seq2,
+                    SequenceCount {
+                        count: 1,
+                        first_seen: self.count,
+                    },
+                );
+                if self.sequences.len() == self.observation_cutoff {
                     self.frozen = true;
                 }
             }
         }
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::get_corrected_count;
+    use super::get_dup_slot;
+    use super::OverRepresentedSeqs;
+    use crate::trust_seq::qc::{write_text_reports, QCModule, QCReport, QCResult};
+    use crate::trust_seq::trust_seq::TrustSeqConfig;
+    use crate::trust_seq::utils::{FastQReader, Sequence};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_get_dup_slot() {
+        assert_eq!(0, get_dup_slot(1));
+        assert_eq!(8, get_dup_slot(9));
+        assert_eq!(9, get_dup_slot(10));
+        assert_eq!(9, get_dup_slot(50));
+        assert_eq!(10, get_dup_slot(51));
+        assert_eq!(14, get_dup_slot(10000));
+        assert_eq!(15, get_dup_slot(10001));
+    }
+    #[test]
+    fn test_get_corrected_count() {
+        // No correction before the unique sequence limit is reached.
+        assert_eq!(3.0, get_corrected_count(20, 20, 2, 3));
+        // 1 / (1 - (18 * 17 * ... * 9) / (20 * 19 * ... * 11)) = 380 / 290
+        let count = get_corrected_count(10, 20, 2, 1);
+        assert!((count - 380.0 / 290.0).abs() < 1e-9);
+    }
+    #[test]
+    fn test_duplication_levels() {
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut module = OverRepresentedSeqs::new(config);
+        let qual = b"IIIIIIII";
+        for s in &[
+            "AAAAAAAA", "AAAAAAAA", "AAAAAAAA", "AAAAAAAA", "AAAAAAAA", "CCCCCCCC", "CCCCCCCC",
+            "GGGGGGGG", "TTTTTTTT", "ACACACAC",
+        ] {
+            module.process_sequence(&Sequence {
+                id: b"@r",
+                sequence: s.as_bytes(),
+                quality: qual,
+                filtered: false,
+                excluded: false,
+                mapping: None,
+            });
+        }
+        let report = super::calculate_report(&module).unwrap();
+        // 5 distinct sequences out of 10 reads.
+        assert_eq!(50.0, report.total_dedup_percentage);
+        assert_eq!(QCResult::Warn, report.status);
+        let levels = &report.duplication_levels;
+        assert_eq!(16, levels.len());
+        assert_eq!(
+            (60.0, 30.0),
+            (
+                levels[0].deduplicated_percentage,
+                levels[0].total_percentage
+            )
+        );
+        assert_eq!(
+            (20.0, 20.0),
+            (
+                levels[1].deduplicated_percentage,
+                levels[1].total_percentage
+            )
+        );
+        assert_eq!(
+            (20.0, 50.0),
+            (
+                levels[4].deduplicated_percentage,
+                levels[4].total_percentage
+            )
+        );
+    }
+    #[test]
+    fn test_text_report() {
+        // test_over_represented_seqs.txt is not FastQC output: its values
+        // were worked out separately from FastQC 0.11.9's algorithm and have
+        // not been checked against a FastQC run yet.
+        let config = Arc::new(TrustSeqConfig::new());
+        let mut module = OverRepresentedSeqs::new(config);
+        let mut reader = FastQReader::new(&include_bytes!("../../../test.fastq")[..]);
+        while let Some(seq) = reader.next_seq().unwrap() {
+            module.process_sequence(&seq);
+        }
+        let mut reports: Vec<Box<QCReport>> = Vec::new();
+        module.calculate(&mut reports).unwrap();
+        let mut text: Vec<u8> = Vec::new();
+        write_text_reports(&reports, &mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+
+        let expected: Vec<&str> = include_str!("../../../test_over_represented_seqs.txt")
+            .lines()
+            .collect();
+        assert_eq!(expected, text.lines().collect::<Vec<&str>>());
+    }
+}
@@ -0,0 +1,135 @@
+use crate::trust_seq::qc::{PairQCModule, QCReport, QCResult};
+use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::io::Write;
+use std::sync::Arc;
+
+pub struct PairNames {
+    config: Arc<TrustSeqConfig>,
+    pair_count: u64,
+    mismatch_count: u64,
+    first_mismatch: Option<PairMismatch>,
+}
+#[derive(Serialize, Clone)]
+pub struct PairMismatch {
+    pub pair: u64,
+    pub r1_name: String,
+    pub r2_name: String,
+}
+#[derive(Serialize)]
+pub struct PairNamesReport {
+    pub status: QCResult,
+    pub total_pairs: u64,
+    pub mismatched_pairs: u64,
+    pub first_mismatch: Option<PairMismatch>,
+}
+/// Returns the part of a read id both mates share: the name up to the
+/// first whitespace, without the leading '@' and a trailing /1 or /2.
+pub fn get_pair_name(id: &[u8]) -> &[u8] {
+    let id = if id.first() == Some(&('@' as u8)) {
+        &id[1..]
+    } else {
+        id
+    };
+    let end = id
+        .iter()
+        .position(|c| *c == ' ' as u8 || *c == '\t' as u8)
+        .unwrap_or(id.len());
+    let name = &id[..end];
+    let len = name.len();
+    if len >= 2 && name[len - 2] == '/' as u8 && (name[len - 1] == '1' as u8 || name[len - 1] == '2' as u8) {
+        return &name[..(len - 2)];
+    }
+    return name;
+}
+impl PairNames {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PairNames {
+        return PairNames {
+            config: config,
+            pair_count: 0,
+            mismatch_count: 0,
+            first_mismatch: None,
+        };
+    }
+}
+impl QCReport for PairNamesReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Read name pairing";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Measure\tValue")?;
+        writeln!(writer, "Total pairs\t{}", self.total_pairs)?;
+        writeln!(writer, "Pairs with mismatched names\t{}", self.mismatched_pairs)?;
+        if let Some(ref mismatch) = self.first_mismatch {
+            writeln!(writer, "First mismatched pair\t{}", mismatch.pair)?;
+            writeln!(writer, "R1 name\t{}", mismatch.r1_name)?;
+            writeln!(writer, "R2 name\t{}", mismatch.r2_name)?;
+        }
+        return Ok(());
+    }
+}
+impl PairQCModule for PairNames {
+    fn process_pair(&mut self, r1: &Sequence, r2: &Sequence) -> () {
+        self.pair_count += 1;
+        if get_pair_name(r1.id) != get_pair_name(r2.id) {
+            self.mismatch_count += 1;
+            if self.first_mismatch.is_none() {
+                self.first_mismatch = Some(PairMismatch {
+                    pair: self.pair_count,
+                    r1_name: String::from_utf8_lossy(r1.id).to_string(),
+                    r2_name: String::from_utf8_lossy(r2.id).to_string(),
+                });
+            }
+        }
+    }
+    fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        let percentage = if self.pair_count > 0 {
+            self.mismatch_count as f64 * 100.0 / self.pair_count as f64
+        } else {
+            0.0
+        };
+        let status = if percentage > self.config.module_config.get("pair_names:error") {
+            QCResult::Fail
+        } else if percentage > self.config.module_config.get("pair_names:warn") {
+            QCResult::Warn
+        } else {
+            QCResult::Pass
+        };
+        results.push(Box::new(PairNamesReport {
+            status: status,
+            total_pairs: self.pair_count,
+            mismatched_pairs: self.mismatch_count,
+            first_mismatch: self.first_mismatch.clone(),
+        }));
+        return Ok(());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::get_pair_name;
+
+    #[test]
+    fn test_get_pair_name() {
+        assert_eq!(b"read1", get_pair_name(b"@read1/1"));
+        assert_eq!(b"read1", get_pair_name(b"@read1/2"));
+        assert_eq!(
+            &b"SIM:1:FCX:1:1101:1000:2000"[..],
+            get_pair_name(b"@SIM:1:FCX:1:1101:1000:2000 2:N:0:ATCACG")
+        );
+        assert_eq!(b"read1/3", get_pair_name(b"read1/3"));
+    }
+}
@@ -0,0 +1,296 @@
+use crate::trust_seq::group::BaseGroup;
+use crate::trust_seq::qc::{PairQCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
+use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
+use crate::trust_seq::utils::revcomp;
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::cmp;
+use std::io::Write;
+use std::str;
+use std::sync::Arc;
+
+// The same overlap criteria fastp uses.
+const MIN_OVERLAP: usize = 30;
+const MAX_MISMATCHES: usize = 5;
+const MAX_MISMATCH_PERCENT: usize = 20;
+
+/// Estimates the insert size of a pair and the adapter read-through it
+/// implies from the overlap between R1 and the reverse complement of R2.
+pub struct PairOverlap {
+    config: Arc<TrustSeqConfig>,
+    pair_count: u64,
+    overlap_count: u64,
+    insert_sizes: Vec<u64>,
+    longest_read: usize,
+    // Pairs whose adapter starts at each position of R1 and R2.
+    read_through: [Vec<u64>; 2],
+}
+#[derive(Serialize)]
+pub struct InsertSizeReport {
+    pub status: QCResult,
+    pub total_pairs: u64,
+    pub overlapping_pairs: u64,
+    pub insert_sizes: Vec<(usize, u64)>,
+}
+#[derive(Serialize)]
+pub struct ReadThroughReport {
+    pub status: QCResult,
+    pub groups: Vec<BaseGroup>,
+    pub r1_percentages: Vec<f64>,
+    pub r2_percentages: Vec<f64>,
+}
+
+fn is_overlap(a: &[u8], b: &[u8]) -> bool {
+    let limit = cmp::min(MAX_MISMATCHES, a.len() * MAX_MISMATCH_PERCENT / 100);
+    let mut mismatches = 0;
+    for (x, y) in a.iter().zip(b.iter()) {
+        if x != y || *x == 'N' as u8 {
+            mismatches += 1;
+            if mismatches > limit {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+/// Returns the insert size of a pair whose reads overlap, or None when no
+/// overlap of at least `MIN_OVERLAP` bases is found.
+pub fn find_insert_size(r1: &[u8], r2: &[u8]) -> Option<usize> {
+    let rc = revcomp(unsafe { str::from_utf8_unchecked(r2) });
+    let rc = rc.as_bytes();
+    // R2 ends at or after the end of R1: the insert is at least R1 long.
+    for offset in 0..r1.len() {
+        let len = cmp::min(r1.len() - offset, rc.len());
+        if len < MIN_OVERLAP {
+            break;
+        }
+        if is_overlap(&r1[offset..(offset + len)], &rc[..len]) {
+            return Some(offset + rc.len());
+        }
+    }
+    // R2 starts before R1: the insert is shorter than R2, and the reads
+    // run on into the adapter.
+    for offset in 1..rc.len() {
+        let len = cmp::min(r1.len(), rc.len() - offset);
+        if len < MIN_OVERLAP {
+            break;
+        }
+        if is_overlap(&r1[..len], &rc[offset..(offset + len)]) {
+            return Some(rc.len() - offset);
+        }
+    }
+    return None;
+}
+fn get_cumulative_percentages(starts: &Vec<u64>, groups: &Vec<BaseGroup>, total: u64) -> Vec<f64> {
+    let mut coverage: Vec<u64> = Vec::new();
+    let mut sum = 0;
+    for count in starts {
+        sum += *count;
+        coverage.push(sum);
+    }
+    let mut percentages = Vec::new();
+    for group in groups {
+        let mut value = 0.0;
+        for idx in (group.lower_count - 1)..group.upper_count {
+            value += coverage.get(idx).map_or(sum, |c| *c) as f64;
+        }
+        percentages.push(
+            value * 100.0 / total as f64 / (group.upper_count - group.lower_count + 1) as f64,
+        );
+    }
+    return percentages;
+}
+impl PairOverlap {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PairOverlap {
+        return PairOverlap {
+            config: config,
+            pair_count: 0,
+            overlap_count: 0,
+            insert_sizes: Vec::new(),
+            longest_read: 0,
+            read_through: [Vec::new(), Vec::new()],
+        };
+    }
+    fn calculate_insert_sizes(&self) -> InsertSizeReport {
+        let mut insert_sizes = Vec::new();
+        let min = self.insert_sizes.iter().position(|c| *c > 0);
+        let max = self.insert_sizes.iter().rposition(|c| *c > 0);
+        if let (Some(min), Some(max)) = (min, max) {
+            for size in min..(max + 1) {
+                insert_sizes.push((size, self.insert_sizes[size]));
+            }
+        }
+        return InsertSizeReport {
+            status: QCResult::Pass,
+            total_pairs: self.pair_count,
+            overlapping_pairs: self.overlap_count,
+            insert_sizes: insert_sizes,
+        };
+    }
+    fn calculate_read_through(&self) -> ReadThroughReport {
+        let groups = BaseGroup::make_base_groups(&self.config.group_type, self.longest_read);
+        let total = cmp::max(self.pair_count, 1);
+        let r1 = get_cumulative_percentages(&self.read_through[0], &groups, total);
+        let r2 = get_cumulative_percentages(&self.read_through[1], &groups, total);
+        let max = r1.iter().chain(r2.iter()).fold(0.0f64, |acc, v| acc.max(*v));
+        let status = if max > self.config.module_config.get("read_through:error") {
+            QCResult::Fail
+        } else if max > self.config.module_config.get("read_through:warn") {
+            QCResult::Warn
+        } else {
+            QCResult::Pass
+        };
+        return ReadThroughReport {
+            status: status,
+            groups: groups,
+            r1_percentages: r1,
+            r2_percentages: r2,
+        };
+    }
+}
+impl QCReport for InsertSizeReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Insert size distribution";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(
+            writer,
+            "<p>{} of {} pairs overlap.</p>",
+            self.overlapping_pairs, self.total_pairs
+        )?;
+        if self.insert_sizes.is_empty() {
+            return Ok(());
+        }
+        let counts: Vec<f64> = self.insert_sizes.iter().map(|s| s.1 as f64).collect();
+        let graph = LineGraph {
+            title: "Insert size of overlapping pairs".to_string(),
+            x_title: "Insert size (bp)",
+            x_labels: self.insert_sizes.iter().map(|s| s.0.to_string()).collect(),
+            series_names: vec!["Pairs".to_string()],
+            y_min: 0.0,
+            y_max: counts.iter().fold(0.0f64, |acc, c| acc.max(*c)),
+            series: vec![counts],
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Total Pairs\t{}", self.total_pairs)?;
+        writeln!(writer, "#Overlapping Pairs\t{}", self.overlapping_pairs)?;
+        writeln!(writer, "#Insert Size\tCount")?;
+        for size in &self.insert_sizes {
+            writeln!(writer, "{}\t{}", size.0, format_double(size.1 as f64))?;
+        }
+        return Ok(());
+    }
+}
+impl QCReport for ReadThroughReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Adapter read-through";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let graph = LineGraph {
+            title: "% Pairs reading through into adapter".to_string(),
+            x_title: "Position in read (bp)",
+            x_labels: self.groups.iter().map(|g| g.to_string()).collect(),
+            series_names: vec!["R1".to_string(), "R2".to_string()],
+            series: vec![self.r1_percentages.clone(), self.r2_percentages.clone()],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Position\tR1\tR2")?;
+        for (idx, group) in self.groups.iter().enumerate() {
+            writeln!(
+                writer,
+                "{}\t{}\t{}",
+                group,
+                format_double(self.r1_percentages[idx]),
+                format_double(self.r2_percentages[idx])
+            )?;
+        }
+        return Ok(());
+    }
+}
+impl PairQCModule for PairOverlap {
+    fn process_pair(&mut self, r1: &Sequence, r2: &Sequence) -> () {
+        self.pair_count += 1;
+        self.longest_read = cmp::max(
+            self.longest_read,
+            cmp::max(r1.sequence.len(), r2.sequence.len()),
+        );
+        let insert_size = match find_insert_size(r1.sequence, r2.sequence) {
+            Some(size) => size,
+            None => return,
+        };
+        self.overlap_count += 1;
+        if self.insert_sizes.len() <= insert_size {
+            self.insert_sizes.resize(insert_size + 1, 0);
+        }
+        self.insert_sizes[insert_size] += 1;
+        for (idx, seq) in [r1, r2].iter().enumerate() {
+            if insert_size < seq.sequence.len() {
+                let starts = &mut self.read_through[idx];
+                if starts.len() <= insert_size {
+                    starts.resize(insert_size + 1, 0);
+                }
+                starts[insert_size] += 1;
+            }
+        }
+    }
+    fn calculate(&self, results: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        results.push(Box::new(self.calculate_insert_sizes()));
+        results.push(Box::new(self.calculate_read_through()));
+        return Ok(());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::find_insert_size;
+    use crate::trust_seq::utils::revcomp;
+
+    const FRAGMENT: &'static str =
+        "ACGTTGCAAGGCTTAACGGTACCGATTGACCATGGCATTACGGATCCAGTTCAAGCTGGATCCGTAAGCTTGCAGT";
+
+    #[test]
+    fn test_find_insert_size() {
+        let fragment = FRAGMENT.as_bytes();
+        // A 70bp insert read from both ends with 50bp reads.
+        let r2 = revcomp(&FRAGMENT[..70]);
+        assert_eq!(Some(70), find_insert_size(&fragment[..50], r2[..50].as_bytes()));
+        // A 40bp insert read with 50bp reads runs into the adapter.
+        let r1 = format!("{}AGATCGGAAG", &FRAGMENT[..40]);
+        let r2 = format!("{}AGATCGGAAG", revcomp(&FRAGMENT[..40]));
+        assert_eq!(Some(40), find_insert_size(r1.as_bytes(), r2.as_bytes()));
+        // Reads from unrelated places do not overlap.
+        assert_eq!(None, find_insert_size(&fragment[..50], &fragment[..50]));
+    }
+}
@@ -1,43 +1,51 @@
-use crate::trust_seq::group::BaseGroup;
+use crate::trust_seq::group::{BaseGroup, GroupType};
 use crate::trust_seq::qc::quality_counts::QualityCounts;
 use crate::trust_seq::qc::PhreadEncoding;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::QualityBoxPlot;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
 use std::cmp;
 use std::f64;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 
-pub struct PerBaseQualityScores<'a> {
+pub struct PerBaseQualityScores {
     min_char: u8,
     max_char: u8,
     qualities: QualityCounts,
-    config: &'a TrustSeqConfig,
+    config: Arc<TrustSeqConfig>,
 }
 #[derive(Serialize)]
-struct PerBaseQualityReport {
-    status: QCResult,
-    quality_data: Vec<Quality>,
+pub struct PerBaseQualityReport {
+    pub status: QCResult,
+    pub quality_data: Vec<Quality>,
 }
 #[derive(Serialize)]
-struct Quality {
-    lower_base: usize,
-    upper_base: usize,
-    mean: f64,
-    median: f64,
-    lower_quartile: f64,
-    upper_quartile: f64,
-    percentile_10: f64,
-    percentile_90: f64,
+pub struct Quality {
+    pub lower_base: usize,
+    pub upper_base: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub lower_quartile: f64,
+    pub upper_quartile: f64,
+    pub percentile_10: f64,
+    pub percentile_90: f64,
 }
 
-impl<'a> PerBaseQualityScores<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> PerBaseQualityScores {
+impl PerBaseQualityScores {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PerBaseQualityScores {
+        let qualities = match config.group_type {
+            GroupType::Logarithmic => QualityCounts::new_grouped(),
+            _ => QualityCounts::new(),
+        };
         return PerBaseQualityScores {
-            qualities: QualityCounts::new(),
+            qualities: qualities,
             min_char: 255,
             max_char: 0,
             config: config,
@@ -45,6 +53,9 @@ impl<'a> PerBaseQualityScores<'a> {
     }
 }
 impl QCReport for PerBaseQualityReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Per base sequence quality";
     }
@@ -55,39 +66,59 @@ impl QCReport for PerBaseQualityReport {
         map.insert(self.get_name().to_string(), value::to_value(self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let q = &self.quality_data;
+        let labels = q
+            .iter()
+            .map(|q| {
+                if q.lower_base == q.upper_base {
+                    q.lower_base.to_string()
+                } else {
+                    format!("{}-{}", q.lower_base, q.upper_base)
+                }
+            })
+            .collect();
+        let highest = q.iter().fold(0.0f64, |acc, q| acc.max(q.percentile_90));
+        let plot = QualityBoxPlot {
+            title: "Quality scores across all bases".to_string(),
+            x_labels: labels,
+            means: q.iter().map(|q| q.mean).collect(),
+            medians: q.iter().map(|q| q.median).collect(),
+            lower_quartiles: q.iter().map(|q| q.lower_quartile).collect(),
+            upper_quartiles: q.iter().map(|q| q.upper_quartile).collect(),
+            lowest: q.iter().map(|q| q.percentile_10).collect(),
+            highest: q.iter().map(|q| q.percentile_90).collect(),
+            y_max: highest.max(36.0) + 2.0,
+        };
+        plot.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(
+            writer,
+            "#Base\tMean\tMedian\tLower Quartile\tUpper Quartile\t10th Percentile\t90th Percentile"
+        )?;
         for q in &self.quality_data {
             if q.lower_base == q.upper_base {
-                write!(
-                    writer,
-                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
-                    q.lower_base,
-                    q.mean,
-                    q.median,
-                    q.lower_quartile,
-                    q.upper_quartile,
-                    q.percentile_10,
-                    q.percentile_90
-                )?;
+                write!(writer, "{}", q.lower_base)?;
             } else {
-                write!(
-                    writer,
-                    "{}-{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
-                    q.lower_base,
-                    q.upper_base,
-                    q.mean,
-                    q.median,
-                    q.lower_quartile,
-                    q.upper_quartile,
-                    q.percentile_10,
-                    q.percentile_90
-                )?;
+                write!(writer, "{}-{}", q.lower_base, q.upper_base)?;
             }
+            writeln!(
+                writer,
+                "\t{}\t{}\t{}\t{}\t{}\t{}",
+                format_double(q.mean),
+                format_double(q.median),
+                format_double(q.lower_quartile),
+                format_double(q.upper_quartile),
+                format_double(q.percentile_10),
+                format_double(q.percentile_90)
+            )?;
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for PerBaseQualityScores<'a> {
+impl QCModule for PerBaseQualityScores {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         let encode = PhreadEncoding::get_phread_encoding(self.min_char)?;
         let offset = encode.offset as u32;
@@ -128,6 +159,22 @@ impl<'a> QCModule for PerBaseQualityScores<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<PerBaseQualityScores>()
+            .unwrap();
+        self.min_char = cmp::min(self.min_char, other.min_char);
+        self.max_char = cmp::max(self.max_char, other.max_char);
+        self.qualities.merge(&other.qualities);
+    }
+    /// Without quality scores, as in FASTA input, there is nothing to show.
+    fn ignore_in_report(&self) -> bool {
+        return self.min_char > self.max_char;
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         let len = seq.quality.len();
 
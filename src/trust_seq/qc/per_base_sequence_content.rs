@@ -1,26 +1,30 @@
 use crate::trust_seq::group::BaseGroup;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
 use std::f64;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 
-pub struct PerBaseSequenceContent<'a> {
-    config: &'a TrustSeqConfig,
+pub struct PerBaseSequenceContent {
+    config: Arc<TrustSeqConfig>,
     counts: [Vec<u64>; 4],
     report: Option<PerBaseSequenceReport>,
 }
 #[derive(Serialize)]
-struct PerBaseSequenceReport {
-    status: QCResult,
-    group: Vec<BaseGroup>,
-    percents: Vec<[f64; 4]>,
+pub struct PerBaseSequenceReport {
+    pub status: QCResult,
+    pub group: Vec<BaseGroup>,
+    pub percents: Vec<[f64; 4]>,
 }
-impl<'a> PerBaseSequenceContent<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> PerBaseSequenceContent<'a> {
+impl PerBaseSequenceContent {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PerBaseSequenceContent {
         return PerBaseSequenceContent {
             config: config,
             counts: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
@@ -29,37 +33,50 @@ impl<'a> PerBaseSequenceContent<'a> {
     }
 }
 impl QCReport for PerBaseSequenceReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Per base sequence content";
     }
     fn get_status(&self) -> QCResult {
         return self.status;
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        // FastQC plots the bases in T, C, A, G order.
+        let order = [2, 3, 1, 0];
+        let graph = LineGraph {
+            title: "Sequence content across all bases".to_string(),
+            x_title: "Position in read (bp)",
+            x_labels: self.group.iter().map(|g| g.to_string()).collect(),
+            series_names: vec![
+                "%T".to_string(),
+                "%C".to_string(),
+                "%A".to_string(),
+                "%G".to_string(),
+            ],
+            series: order
+                .iter()
+                .map(|b| self.percents.iter().map(|p| p[*b]).collect())
+                .collect(),
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
-        write!(writer, "#Base\tG\tA\tT\tC\n")?;
+        writeln!(writer, "#Base\tG\tA\tT\tC")?;
         for (idx, group) in self.group.iter().enumerate() {
-            if group.lower_count == group.upper_count {
-                write!(
-                    writer,
-                    "{}\t{}\t{}\t{}\t{}\n",
-                    group.lower_count,
-                    self.percents[idx][0],
-                    self.percents[idx][1],
-                    self.percents[idx][2],
-                    self.percents[idx][3]
-                )?;
-            } else {
-                write!(
-                    writer,
-                    "{}-{}\t{}\t{}\t{}\t{}\n",
-                    group.lower_count,
-                    group.upper_count,
-                    self.percents[idx][0],
-                    self.percents[idx][1],
-                    self.percents[idx][2],
-                    self.percents[idx][3]
-                )?;
-            }
+            writeln!(
+                writer,
+                "{}\t{}\t{}\t{}\t{}",
+                group,
+                format_double(self.percents[idx][0]),
+                format_double(self.percents[idx][1]),
+                format_double(self.percents[idx][2]),
+                format_double(self.percents[idx][3])
+            )?;
         }
         return Ok(());
     }
@@ -68,7 +85,7 @@ impl QCReport for PerBaseSequenceReport {
         return Ok(());
     }
 }
-impl<'a> QCModule for PerBaseSequenceContent<'a> {
+impl QCModule for PerBaseSequenceContent {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         let seq_len = self.counts[0].len();
         let groups = BaseGroup::make_base_groups(&self.config.group_type, seq_len);
@@ -110,6 +127,23 @@ impl<'a> QCModule for PerBaseSequenceContent<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<PerBaseSequenceContent>()
+            .unwrap();
+        for (counts, other_counts) in self.counts.iter_mut().zip(other.counts.iter()) {
+            if counts.len() < other_counts.len() {
+                counts.resize(other_counts.len(), 0);
+            }
+            for (c, o) in counts.iter_mut().zip(other_counts.iter()) {
+                *c += *o;
+            }
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         if self.counts[0].len() < seq.sequence.len() {
             for _i in self.counts[0].len()..seq.sequence.len() {
@@ -1,36 +1,50 @@
 use crate::trust_seq::gc_model::GCModel;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
-use std::collections::hash_map::HashMap;
+use std::collections::BTreeMap;
 use std::f64;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 use std::slice::Iter;
 
-pub struct PerSequenceGCContents<'a> {
-    config: &'a TrustSeqConfig,
-    gc_distribution: [f64; 101],
-    gc_models: HashMap<usize, Box<GCModel>>,
+pub struct PerSequenceGCContents {
+    config: Arc<TrustSeqConfig>,
+    // Read counts per GC count, keyed by read length. Keeping integer
+    // counts makes the distribution independent of the processing order.
+    gc_counts: BTreeMap<usize, Vec<u64>>,
 }
 #[derive(Serialize)]
-struct PerSequenceGCReport {
-    status: QCResult,
-    gc_distribution: Vec<f64>,
-    theoretical_distribution: Vec<f64>,
+pub struct PerSequenceGCReport {
+    pub status: QCResult,
+    pub gc_distribution: Vec<f64>,
+    pub theoretical_distribution: Vec<f64>,
 }
-impl<'a> PerSequenceGCContents<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> PerSequenceGCContents {
-        let mut gc_distribution = Vec::new();
-        gc_distribution.resize(101, 0.0);
+impl PerSequenceGCContents {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PerSequenceGCContents {
         return PerSequenceGCContents {
             config: config,
-            gc_distribution: [0f64; 101],
-            gc_models: HashMap::new(),
+            gc_counts: BTreeMap::new(),
         };
     }
+    fn get_gc_distribution(&self) -> [f64; 101] {
+        let mut gc_distribution = [0f64; 101];
+        for (seq_len, counts) in &self.gc_counts {
+            let model = GCModel::new(*seq_len);
+            for (gc_count, count) in counts.iter().enumerate() {
+                if *count > 0 {
+                    model.add_value(gc_count, *count, &mut gc_distribution);
+                }
+            }
+        }
+        return gc_distribution;
+    }
 }
 fn truncate_sequence(sequence: &[u8]) -> &[u8] {
     if sequence.len() > 1000 {
@@ -59,6 +73,9 @@ fn calc_stddev_total(values: Iter<f64>, mode: f64) -> (f64, f64) {
     (stddev.sqrt(), total_count)
 }
 impl QCReport for PerSequenceGCReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Per sequence GC content";
     }
@@ -69,18 +86,42 @@ impl QCReport for PerSequenceGCReport {
         map.insert(self.get_name().to_string(), value::to_value(self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let y_max = self
+            .gc_distribution
+            .iter()
+            .chain(self.theoretical_distribution.iter())
+            .fold(0.0f64, |acc, v| acc.max(*v));
+        let graph = LineGraph {
+            title: "GC distribution over all sequences".to_string(),
+            x_title: "Mean GC content (%)",
+            x_labels: (0..101).map(|i: usize| i.to_string()).collect(),
+            series_names: vec![
+                "GC count per read".to_string(),
+                "Theoretical Distribution".to_string(),
+            ],
+            series: vec![
+                self.gc_distribution.clone(),
+                self.theoretical_distribution.clone(),
+            ],
+            y_min: 0.0,
+            y_max: y_max,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
         writeln!(writer, "#GC Content\tCount")?;
         for idx in 0..101 {
-            writeln!(writer, "{}\t{}", idx, self.gc_distribution[idx])?;
+            writeln!(writer, "{}\t{}", idx, format_double(self.gc_distribution[idx]))?;
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for PerSequenceGCContents<'a> {
+impl QCModule for PerSequenceGCContents {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
-        let mode = self
-            .gc_distribution
+        let gc_distribution = self.get_gc_distribution();
+        let mode = gc_distribution
             .iter()
             .enumerate()
             .max_by(|a, b| (a.1).partial_cmp(b.1).unwrap())
@@ -90,8 +131,8 @@ impl<'a> QCModule for PerSequenceGCContents<'a> {
         let mut mode_count: u32 = 0;
         let mut fell_off_top = true;
         let mut fell_off_bottom = true;
-        for idx in (mode.0)..self.gc_distribution.len() {
-            if self.gc_distribution[idx] > mode_th {
+        for idx in (mode.0)..gc_distribution.len() {
+            if gc_distribution[idx] > mode_th {
                 mode_total += idx;
                 mode_count += 1;
             } else {
@@ -100,7 +141,7 @@ impl<'a> QCModule for PerSequenceGCContents<'a> {
             }
         }
         for idx in (0..mode.0).rev() {
-            if self.gc_distribution[idx] > mode_th {
+            if gc_distribution[idx] > mode_th {
                 mode_total += idx;
                 mode_count += 1;
             } else {
@@ -113,12 +154,12 @@ impl<'a> QCModule for PerSequenceGCContents<'a> {
         } else {
             mode_total as f64 / mode_count as f64
         };
-        let (stddev, total_count) = calc_stddev_total(self.gc_distribution.iter(), mode2);
+        let (stddev, total_count) = calc_stddev_total(gc_distribution.iter(), mode2);
         let mut theoretical_distribution = [0.0 as f64; 101];
         let mut deviation_percent = 0.0;
         for (i, v) in theoretical_distribution.iter_mut().enumerate() {
             *v = calc_zscore_for_value(mode2, stddev, i as f64) * total_count;
-            deviation_percent += (*v - self.gc_distribution[i]).abs();
+            deviation_percent += (*v - gc_distribution[i]).abs();
         }
         deviation_percent = deviation_percent * 100.0 / total_count;
         let error_th = self.config.module_config.get("gc_sequence:error");
@@ -133,11 +174,29 @@ impl<'a> QCModule for PerSequenceGCContents<'a> {
         };
         reports.push(Box::new(PerSequenceGCReport {
             status: status,
-            gc_distribution: self.gc_distribution.to_vec(),
+            gc_distribution: gc_distribution.to_vec(),
             theoretical_distribution: theoretical_distribution.to_vec(),
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<PerSequenceGCContents>()
+            .unwrap();
+        for (seq_len, other_counts) in &other.gc_counts {
+            let counts = self
+                .gc_counts
+                .entry(*seq_len)
+                .or_insert_with(|| vec![0; seq_len + 1]);
+            for (c, o) in counts.iter_mut().zip(other_counts.iter()) {
+                *c += *o;
+            }
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         let mut gc_count: usize = 0;
         let sequence = truncate_sequence(seq.sequence);
@@ -155,13 +214,10 @@ impl<'a> QCModule for PerSequenceGCContents<'a> {
             }
         }
         let seq_len = seq.sequence.len();
-        if !self.gc_models.contains_key(&seq_len) {
-            self.gc_models
-                .insert(seq_len, Box::new(GCModel::new(seq_len)));
-        }
-        match self.gc_models.get(&seq_len) {
-            Some(model) => model.add_value(gc_count, &mut self.gc_distribution),
-            None => (),
-        }
+        let counts = self
+            .gc_counts
+            .entry(seq_len)
+            .or_insert_with(|| vec![0; seq_len + 1]);
+        counts[gc_count] += 1;
     }
 }
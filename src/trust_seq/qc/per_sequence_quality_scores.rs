@@ -1,24 +1,28 @@
 use crate::trust_seq::qc::PhreadEncoding;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 
-pub struct PerSequenceQualityScores<'a> {
+pub struct PerSequenceQualityScores {
     score_counts: [u64; 128],
     lowest_char: u8,
-    config: &'a TrustSeqConfig,
+    config: Arc<TrustSeqConfig>,
 }
 #[derive(Serialize)]
-struct PerSequenceQualityReport {
-    status: QCResult,
-    qualities: Vec<(u32, u64)>,
+pub struct PerSequenceQualityReport {
+    pub status: QCResult,
+    pub qualities: Vec<(u32, u64)>,
 }
-impl<'a> PerSequenceQualityScores<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> PerSequenceQualityScores {
+impl PerSequenceQualityScores {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PerSequenceQualityScores {
         return PerSequenceQualityScores {
             score_counts: [0; 128],
             lowest_char: 255,
@@ -27,6 +31,9 @@ impl<'a> PerSequenceQualityScores<'a> {
     }
 }
 impl QCReport for PerSequenceQualityReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Per sequence quality scores";
     }
@@ -37,14 +44,33 @@ impl QCReport for PerSequenceQualityReport {
     fn get_status(&self) -> QCResult {
         return self.status;
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let counts: Vec<f64> = self.qualities.iter().map(|q| q.1 as f64).collect();
+        let graph = LineGraph {
+            title: "Quality score distribution over all sequences".to_string(),
+            x_title: "Mean Sequence Quality (Phred Score)",
+            x_labels: self.qualities.iter().map(|q| q.0.to_string()).collect(),
+            series_names: vec!["Average Quality per read".to_string()],
+            y_min: 0.0,
+            y_max: counts.iter().fold(0.0f64, |acc, c| acc.max(*c)),
+            series: vec![counts],
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Quality\tCount")?;
         for val in &self.qualities {
-            write!(writer, "{}\t{}\n", val.0, val.1)?;
+            writeln!(writer, "{}\t{}", val.0, format_double(val.1 as f64))?;
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for PerSequenceQualityScores<'a> {
+impl QCModule for PerSequenceQualityScores {
+    /// Without quality scores, as in FASTA input, there is nothing to show.
+    fn ignore_in_report(&self) -> bool {
+        return self.lowest_char == 255;
+    }
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
         let mut min_score = 128;
         let mut max_score = 0;
@@ -67,14 +93,14 @@ impl<'a> QCModule for PerSequenceQualityScores<'a> {
             ));
             if max_count < self.score_counts[score] {
                 max_count = self.score_counts[score];
-                most_frequence_score = score as f64;
+                most_frequence_score = (score - encoding.offset as usize) as f64;
             }
         }
         let error_th = self.config.module_config.get("quality_sequence:error");
         let warn_th = self.config.module_config.get("quality_sequence:warn");
-        let status = if most_frequence_score > error_th {
+        let status = if most_frequence_score <= error_th {
             QCResult::Fail
-        } else if most_frequence_score > warn_th {
+        } else if most_frequence_score <= warn_th {
             QCResult::Warn
         } else {
             QCResult::Pass
@@ -85,6 +111,21 @@ impl<'a> QCModule for PerSequenceQualityScores<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<PerSequenceQualityScores>()
+            .unwrap();
+        for (count, other_count) in self.score_counts.iter_mut().zip(other.score_counts.iter()) {
+            *count += *other_count;
+        }
+        if other.lowest_char < self.lowest_char {
+            self.lowest_char = other.lowest_char;
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         let mut average_quality: usize = 0;
         for ch in seq.quality {
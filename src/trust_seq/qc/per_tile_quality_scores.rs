@@ -4,7 +4,9 @@ use crate::trust_seq::group::BaseGroup;
 use crate::trust_seq::qc::quality_counts::QualityCounts;
 use crate::trust_seq::qc::PhreadEncoding;
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::HeatMap;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
@@ -12,11 +14,13 @@ use serde_json::value::Value;
 use std::collections::HashMap;
 use std::f64;
 use std::i32;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 use std::str;
 use std::str::FromStr;
 
-pub struct PerTileQualityScores<'a> {
+pub struct PerTileQualityScores {
     ignore_in_report: bool,
     total_count: u64,
     id_position: i32,
@@ -24,18 +28,18 @@ pub struct PerTileQualityScores<'a> {
     min_char: u8,
     max_char: u8,
     quality_counts: HashMap<u32, QualityCounts>,
-    config: &'a TrustSeqConfig,
+    config: Arc<TrustSeqConfig>,
 }
 
 #[derive(Serialize)]
-struct PerTileQualityReport {
-    status: QCResult,
-    tiles: Vec<u32>,
-    groups: Vec<BaseGroup>,
-    qualities: Vec<Vec<f64>>,
+pub struct PerTileQualityReport {
+    pub status: QCResult,
+    pub tiles: Vec<u32>,
+    pub groups: Vec<BaseGroup>,
+    pub qualities: Vec<Vec<f64>>,
 }
-impl<'a> PerTileQualityScores<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> PerTileQualityScores {
+impl PerTileQualityScores {
+    pub fn new(config: Arc<TrustSeqConfig>) -> PerTileQualityScores {
         return PerTileQualityScores {
             total_count: 0,
             ignore_in_report: false,
@@ -49,6 +53,9 @@ impl<'a> PerTileQualityScores<'a> {
     }
 }
 impl QCReport for PerTileQualityReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Per tile sequence quality";
     }
@@ -59,35 +66,90 @@ impl QCReport for PerTileQualityReport {
         map.insert(self.get_name().to_string(), value::to_value(&self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        // Tiles losing quality against the average are drawn towards red.
+        let map = HeatMap {
+            title: "Quality per tile".to_string(),
+            x_title: "Position in read (bp)",
+            x_labels: self.groups.iter().map(|g| g.to_string()).collect(),
+            y_labels: self.tiles.iter().map(|t| t.to_string()).collect(),
+            values: self
+                .qualities
+                .iter()
+                .map(|row| row.iter().map(|v| -v).collect())
+                .collect(),
+            min_value: 0.0,
+            max_value: 10.0,
+        };
+        map.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
-        write!(writer, "Tile\tBase\tMean\n")?;
+        writeln!(writer, "#Tile\tBase\tMean")?;
         for (idx, tile) in self.tiles.iter().enumerate() {
             for (group_idx, group) in self.groups.iter().enumerate() {
-                if group.lower_count == group.upper_count {
-                    writeln!(
-                        writer,
-                        "{}\t{}\t{}",
-                        tile, group.lower_count, self.qualities[idx][group_idx]
-                    )?;
-                } else {
-                    writeln!(
-                        writer,
-                        "{}\t{}-{}\t{}",
-                        tile, group.lower_count, group.upper_count, self.qualities[idx][group_idx]
-                    )?;
-                }
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}",
+                    tile,
+                    group,
+                    format_double(self.qualities[idx][group_idx])
+                )?;
             }
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for PerTileQualityScores<'a> {
+impl QCModule for PerTileQualityScores {
+    fn ignore_filtered_sequences(&self) -> bool {
+        return false;
+    }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<PerTileQualityScores>()
+            .unwrap();
+        self.total_count += other.total_count;
+        if self.ignore_in_report {
+            return;
+        }
+        // Both parts must have found the tile at the same field of the id.
+        if other.ignore_in_report
+            || (0 <= self.id_position
+                && 0 <= other.id_position
+                && self.id_position != other.id_position)
+        {
+            self.ignore_in_report = true;
+            return;
+        }
+        if self.id_position < 0 {
+            self.id_position = other.id_position;
+        }
+        self.current_length = cmp::max(self.current_length, other.current_length);
+        self.min_char = cmp::min(self.min_char, other.min_char);
+        self.max_char = cmp::max(self.max_char, other.max_char);
+        for (tile, counts) in &other.quality_counts {
+            if !self.quality_counts.contains_key(tile) {
+                if self.quality_counts.len() > 500 {
+                    self.ignore_in_report = true;
+                    return;
+                }
+                self.quality_counts.insert(*tile, QualityCounts::new());
+            }
+            self.quality_counts.get_mut(tile).unwrap().merge(counts);
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         if self.ignore_in_report {
             return;
         }
+        // Filtered reads are counted, so every 10th read of the file is
+        // sampled however it is split between threads.
         self.total_count += 1;
-        if self.total_count % 10 != 0 {
+        if seq.filtered || seq.excluded || self.total_count % 10 != 0 {
             return;
         }
         let id_str = unsafe { str::from_utf8_unchecked(seq.id) };
@@ -129,11 +191,12 @@ impl<'a> QCModule for PerTileQualityScores<'a> {
             q.add_value(idx, *ch);
         }
     }
+    /// Also without quality scores, as in FASTA input.
     fn ignore_in_report(&self) -> bool {
-        return self.ignore_in_report;
+        return self.ignore_in_report || self.min_char > self.max_char;
     }
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
-        if self.ignore_in_report {
+        if self.ignore_in_report() || self.quality_counts.is_empty() {
             return Ok(());
         }
         let encode = PhreadEncoding::get_phread_encoding(self.min_char).unwrap();
@@ -3,29 +3,69 @@ use std::cmp;
 use std::f64;
 
 pub struct QualityCounts {
+    /// By position, or by logarithmic group for long reads.
     pub counts: Vec<QualityCount>,
+    grouped: bool,
+    len: usize,
 }
 impl QualityCounts {
     pub fn new() -> QualityCounts {
-        return QualityCounts { counts: Vec::new() };
+        return QualityCounts {
+            counts: Vec::new(),
+            grouped: false,
+            len: 0,
+        };
+    }
+    /// Counts that take as much memory for reads of 100kb as of 100 bases:
+    /// the qualities are kept by logarithmic group, and only these groups
+    /// can be asked for.
+    pub fn new_grouped() -> QualityCounts {
+        return QualityCounts {
+            counts: Vec::new(),
+            grouped: true,
+            len: 0,
+        };
+    }
+    fn get_index(&self, position: usize) -> usize {
+        if self.grouped {
+            return BaseGroup::get_logarithmic_index(position);
+        }
+        return position;
     }
     pub fn ensure_size(&mut self, len: usize) {
-        if self.counts.len() < len {
-            self.counts.resize(len, QualityCount::new());
+        if self.len < len {
+            self.len = len;
+            let size = self.get_index(len - 1) + 1;
+            if self.counts.len() < size {
+                self.counts.resize(size, QualityCount::new());
+            }
         }
     }
     pub fn add_value(&mut self, idx: usize, ch: u8) {
         self.ensure_size(idx + 1);
+        let idx = self.get_index(idx);
         self.counts[idx].counts[ch as usize] += 1;
         self.counts[idx].total_count += 1;
     }
+    pub fn merge(&mut self, other: &QualityCounts) {
+        self.ensure_size(other.len());
+        for (count, other_count) in self.counts.iter_mut().zip(other.counts.iter()) {
+            count.merge(other_count);
+        }
+    }
+    /// The length of the longest read.
     pub fn len(&self) -> usize {
-        return self.counts.len();
+        return self.len;
     }
     pub fn get_mean(&self, group: &BaseGroup, offset: u32) -> f64 {
+        if self.grouped {
+            return self.counts[self.get_index(group.lower_count - 1)].get_mean(offset);
+        }
         let mut count: u32 = 0;
         let mut total: f64 = 0.0;
-        for i in (group.lower_count - 1)..group.upper_count {
+        // Shorter reads may not reach the whole group.
+        let upper = cmp::min(group.upper_count, self.counts.len());
+        for i in (group.lower_count - 1)..upper {
             count += 1;
             total += self.counts[i].get_mean(offset);
         }
@@ -36,6 +76,13 @@ impl QualityCounts {
         }
     }
     pub fn get_percentile(&self, group: &BaseGroup, offset: u32, percentile: u32) -> f64 {
+        if self.grouped {
+            let count = &self.counts[self.get_index(group.lower_count - 1)];
+            if count.total_count > 100 {
+                return count.get_percentile(offset, percentile) as f64;
+            }
+            return f64::NAN;
+        }
         let mut count: u32 = 0;
         let mut total: f64 = 0.0;
         for i in (group.lower_count - 1)..group.upper_count {
@@ -82,6 +129,12 @@ impl QualityCount {
         self.counts[ch as usize] += 1;
         self.total_count += 1;
     }
+    pub fn merge(&mut self, other: &QualityCount) -> () {
+        for (c, o) in self.counts.iter_mut().zip(other.counts.iter()) {
+            *c += *o;
+        }
+        self.total_count += other.total_count;
+    }
     pub fn get_mean(&self, offset: u32) -> f64 {
         let mut total: f64 = 0.0;
         let mut count: f64 = 0.0;
@@ -108,3 +161,29 @@ impl QualityCount {
         return 0;
     }
 }
+#[cfg(test)]
+mod tests {
+    use super::QualityCounts;
+    use crate::trust_seq::group::{BaseGroup, GroupType};
+
+    #[test]
+    fn test_grouped_counts() {
+        let mut counts = QualityCounts::new_grouped();
+        for position in 0..19_999 {
+            let quality = if position < 14_999 { 10 } else { 30 };
+            counts.add_value(position, 33 + quality);
+        }
+        assert_eq!(19_999, counts.len());
+        assert_eq!(9 + 18 * 3 + 2, counts.counts.len());
+        let groups = BaseGroup::make_base_groups(&GroupType::Logarithmic, counts.len());
+        assert_eq!(counts.counts.len(), groups.len());
+        assert_eq!("15000-19999", groups[groups.len() - 1].to_string());
+        assert_eq!(30.0, counts.get_mean(&groups[groups.len() - 1], 33));
+        assert_eq!(
+            30.0,
+            counts.get_percentile(&groups[groups.len() - 1], 33, 50)
+        );
+        // Too few qualities for a percentile.
+        assert!(counts.get_percentile(&groups[0], 33, 50).is_nan());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::trust_seq::group::{BaseGroup, GroupType};
+use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
+use crate::trust_seq::trust_seq::TrustSeqErr;
+use crate::trust_seq::utils::format_double;
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Read length summary for long reads: N50 and read and base counts on
+/// logarithmic length bins.
+pub struct ReadLengthStats {
+    length_counts: BTreeMap<usize, u64>,
+}
+#[derive(Serialize)]
+pub struct ReadLengthStatsReport {
+    pub status: QCResult,
+    pub read_count: u64,
+    pub base_count: u64,
+    pub mean_length: f64,
+    pub median_length: usize,
+    pub longest_read: usize,
+    pub n50: usize,
+    pub histogram: Vec<LengthBin>,
+}
+#[derive(Serialize)]
+pub struct LengthBin {
+    pub group: BaseGroup,
+    pub reads: u64,
+    pub bases: u64,
+}
+impl ReadLengthStats {
+    pub fn new() -> ReadLengthStats {
+        return ReadLengthStats {
+            length_counts: BTreeMap::new(),
+        };
+    }
+}
+/// The length of the shortest read among the longest reads that together
+/// hold half of all bases.
+fn get_n50(length_counts: &BTreeMap<usize, u64>, base_count: u64) -> usize {
+    let mut bases = 0u64;
+    for (length, count) in length_counts.iter().rev() {
+        bases += *length as u64 * *count;
+        if bases * 2 >= base_count {
+            return *length;
+        }
+    }
+    return 0;
+}
+fn get_median(length_counts: &BTreeMap<usize, u64>, read_count: u64) -> usize {
+    let mut reads = 0u64;
+    for (length, count) in length_counts {
+        reads += *count;
+        if reads * 2 >= read_count {
+            return *length;
+        }
+    }
+    return 0;
+}
+impl QCReport for ReadLengthStatsReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Read length statistics";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(
+            writer,
+            "<p>{} reads, {} bases, N50 {}bp, longest read {}bp.</p>",
+            self.read_count, self.base_count, self.n50, self.longest_read
+        )?;
+        let to_percent = |v: u64, total: u64| v as f64 * 100.0 / total.max(1) as f64;
+        let graph = LineGraph {
+            title: "Distribution of read lengths".to_string(),
+            x_title: "Read Length (bp)",
+            x_labels: self.histogram.iter().map(|b| b.group.to_string()).collect(),
+            series_names: vec!["% Reads".to_string(), "% Bases".to_string()],
+            series: vec![
+                self.histogram
+                    .iter()
+                    .map(|b| to_percent(b.reads, self.read_count))
+                    .collect(),
+                self.histogram
+                    .iter()
+                    .map(|b| to_percent(b.bases, self.base_count))
+                    .collect(),
+            ],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Measure\tValue")?;
+        writeln!(writer, "Reads\t{}", self.read_count)?;
+        writeln!(writer, "Bases\t{}", self.base_count)?;
+        writeln!(writer, "Mean length\t{}", format_double(self.mean_length))?;
+        writeln!(writer, "Median length\t{}", self.median_length)?;
+        writeln!(writer, "Longest read\t{}", self.longest_read)?;
+        writeln!(writer, "N50\t{}", self.n50)?;
+        writeln!(writer, "#Length\tReads\tBases")?;
+        for bin in &self.histogram {
+            writeln!(writer, "{}\t{}\t{}", bin.group, bin.reads, bin.bases)?;
+        }
+        return Ok(());
+    }
+}
+impl QCModule for ReadLengthStats {
+    fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        let read_count: u64 = self.length_counts.values().sum();
+        let base_count: u64 = self
+            .length_counts
+            .iter()
+            .map(|(length, count)| *length as u64 * *count)
+            .sum();
+        let shortest_read = self.length_counts.keys().next().map_or(0, |l| *l);
+        let longest_read = self.length_counts.keys().next_back().map_or(0, |l| *l);
+        let mut histogram = Vec::new();
+        // Zero length reads fall outside the groups, which start at 1.
+        for group in BaseGroup::make_base_groups(&GroupType::Logarithmic, longest_read) {
+            if group.upper_count < shortest_read {
+                continue;
+            }
+            let mut reads = 0;
+            let mut bases = 0;
+            for (length, count) in self
+                .length_counts
+                .range(group.lower_count..(group.upper_count + 1))
+            {
+                reads += *count;
+                bases += *length as u64 * *count;
+            }
+            histogram.push(LengthBin {
+                group: group,
+                reads: reads,
+                bases: bases,
+            });
+        }
+        reports.push(Box::new(ReadLengthStatsReport {
+            status: QCResult::Pass,
+            read_count: read_count,
+            base_count: base_count,
+            mean_length: base_count as f64 / read_count.max(1) as f64,
+            median_length: get_median(&self.length_counts, read_count),
+            longest_read: longest_read,
+            n50: get_n50(&self.length_counts, base_count),
+            histogram: histogram,
+        }));
+        return Ok(());
+    }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<ReadLengthStats>().unwrap();
+        for (length, count) in &other.length_counts {
+            *self.length_counts.entry(*length).or_insert(0) += *count;
+        }
+    }
+    fn process_sequence(&mut self, seq: &Sequence) -> () {
+        *self.length_counts.entry(seq.sequence.len()).or_insert(0) += 1;
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::{get_median, get_n50};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_get_n50() {
+        // 2, 3, 4, 5, 6, 7, 8 and 10kb reads: 45kb in total.
+        let mut counts = BTreeMap::new();
+        for length in &[2000, 3000, 4000, 5000, 6000, 7000, 8000, 10000] {
+            counts.insert(*length, 1);
+        }
+        // 10 + 8 + 7 = 25kb reach half of the bases.
+        assert_eq!(7000, get_n50(&counts, 45000));
+        assert_eq!(5000, get_median(&counts, 8));
+        assert_eq!(0, get_n50(&BTreeMap::new(), 0));
+    }
+}
@@ -0,0 +1,146 @@
+use crate::trust_seq::qc::PhreadEncoding;
+use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
+use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::cmp;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The distribution of the mean quality of each read, averaged over error
+/// probabilities rather than Phred scores as long read tools do.
+pub struct ReadMeanQuality {
+    config: Arc<TrustSeqConfig>,
+    // The error probability of each quality character, before the offset.
+    error_probabilities: Vec<f64>,
+    // Reads by their mean quality, before the offset is subtracted.
+    quality_counts: [u64; 128],
+    lowest_char: u8,
+}
+#[derive(Serialize)]
+pub struct ReadMeanQualityReport {
+    pub status: QCResult,
+    pub median_quality: i32,
+    pub qualities: Vec<(i32, u64)>,
+}
+impl ReadMeanQuality {
+    pub fn new(config: Arc<TrustSeqConfig>) -> ReadMeanQuality {
+        return ReadMeanQuality {
+            config: config,
+            error_probabilities: (0..128).map(|c| 10f64.powf(-(c as f64) / 10.0)).collect(),
+            quality_counts: [0; 128],
+            lowest_char: 255,
+        };
+    }
+}
+impl QCReport for ReadMeanQualityReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Per read mean quality";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let counts: Vec<f64> = self.qualities.iter().map(|q| q.1 as f64).collect();
+        let graph = LineGraph {
+            title: format!("Mean read quality (median Q{})", self.median_quality),
+            x_title: "Mean Read Quality (Phred Score)",
+            x_labels: self.qualities.iter().map(|q| q.0.to_string()).collect(),
+            series_names: vec!["Reads".to_string()],
+            y_min: 0.0,
+            y_max: counts.iter().fold(0.0f64, |acc, c| acc.max(*c)),
+            series: vec![counts],
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(writer, "#Median Quality\t{}", self.median_quality)?;
+        writeln!(writer, "#Mean Quality\tCount")?;
+        for quality in &self.qualities {
+            writeln!(writer, "{}\t{}", quality.0, format_double(quality.1 as f64))?;
+        }
+        return Ok(());
+    }
+}
+impl QCModule for ReadMeanQuality {
+    fn ignore_in_report(&self) -> bool {
+        return self.lowest_char == 255;
+    }
+    fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        let offset = PhreadEncoding::get_phread_encoding(self.lowest_char)?.offset as i32;
+        let mut qualities = Vec::new();
+        let min = self.quality_counts.iter().position(|c| *c > 0);
+        let max = self.quality_counts.iter().rposition(|c| *c > 0);
+        if let (Some(min), Some(max)) = (min, max) {
+            for q in min..(max + 1) {
+                qualities.push((q as i32 - offset, self.quality_counts[q]));
+            }
+        }
+        let read_count: u64 = self.quality_counts.iter().sum();
+        let mut reads = 0;
+        let mut median_quality = 0;
+        for quality in &qualities {
+            reads += quality.1;
+            if reads * 2 >= read_count {
+                median_quality = quality.0;
+                break;
+            }
+        }
+        let median = median_quality as f64;
+        let status = if median < self.config.module_config.get("mean_quality:error") {
+            QCResult::Fail
+        } else if median < self.config.module_config.get("mean_quality:warn") {
+            QCResult::Warn
+        } else {
+            QCResult::Pass
+        };
+        reports.push(Box::new(ReadMeanQualityReport {
+            status: status,
+            median_quality: median_quality,
+            qualities: qualities,
+        }));
+        return Ok(());
+    }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<ReadMeanQuality>().unwrap();
+        for (count, other_count) in self
+            .quality_counts
+            .iter_mut()
+            .zip(other.quality_counts.iter())
+        {
+            *count += *other_count;
+        }
+        self.lowest_char = cmp::min(self.lowest_char, other.lowest_char);
+    }
+    fn process_sequence(&mut self, seq: &Sequence) -> () {
+        if seq.quality.is_empty() {
+            return;
+        }
+        let mut total = 0.0;
+        for ch in seq.quality {
+            self.lowest_char = cmp::min(self.lowest_char, *ch);
+            total += self.error_probabilities[(*ch & 127) as usize];
+        }
+        // The offset is an integer, so flooring before subtracting it
+        // gives the same bin as flooring after. The small margin keeps
+        // reads of a single quality out of the bin below.
+        let mean_quality = -10.0 * (total / seq.quality.len() as f64).log10();
+        self.quality_counts[cmp::min((mean_quality + 1e-9).floor() as usize, 127)] += 1;
+    }
+}
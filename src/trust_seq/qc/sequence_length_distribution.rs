@@ -1,23 +1,27 @@
 use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
 use crate::trust_seq::trust_seq::{TrustSeqConfig, TrustSeqErr};
+use crate::trust_seq::utils::format_double;
 use crate::trust_seq::utils::Sequence;
 use serde_json::map::Map;
 use serde_json::value;
 use serde_json::value::Value;
 use std::cmp;
+use std::any::Any;
 use std::io::Write;
+use std::sync::Arc;
 
-pub struct SequenceLengthDistribution<'a> {
-    config: &'a TrustSeqConfig,
+pub struct SequenceLengthDistribution {
+    config: Arc<TrustSeqConfig>,
     length_counts: Vec<u64>,
 }
 #[derive(Serialize)]
-struct SequenceLengthReport {
-    status: QCResult,
-    length_counts: Vec<GroupValue<u64>>,
+pub struct SequenceLengthReport {
+    pub status: QCResult,
+    pub length_counts: Vec<GroupValue<u64>>,
 }
-impl<'a> SequenceLengthDistribution<'a> {
-    pub fn new(config: &'a TrustSeqConfig) -> SequenceLengthDistribution<'a> {
+impl SequenceLengthDistribution {
+    pub fn new(config: Arc<TrustSeqConfig>) -> SequenceLengthDistribution {
         return SequenceLengthDistribution {
             config: config,
             length_counts: Vec::new(),
@@ -38,10 +42,10 @@ fn get_min_max_idx(vec: &Vec<u64>) -> (usize, usize) {
     return (min as usize, max);
 }
 #[derive(Serialize)]
-struct GroupValue<V> {
-    lower_count: usize,
-    upper_count: usize,
-    value: V,
+pub struct GroupValue<V> {
+    pub lower_count: usize,
+    pub upper_count: usize,
+    pub value: V,
 }
 fn calc_interval(width: usize) -> usize {
     let mut base: usize = 1;
@@ -62,6 +66,9 @@ fn get_size_distribution(min: usize, max: usize) -> (usize, usize) {
     return (base_div * interval, interval);
 }
 impl QCReport for SequenceLengthReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
     fn get_name(&self) -> &'static str {
         return "Sequence Length Distribution";
     }
@@ -72,28 +79,62 @@ impl QCReport for SequenceLengthReport {
         map.insert(self.get_name().to_string(), value::to_value(&self)?);
         return Ok(());
     }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let counts: Vec<f64> = self.length_counts.iter().map(|v| v.value as f64).collect();
+        let graph = LineGraph {
+            title: "Distribution of sequence lengths over all sequences".to_string(),
+            x_title: "Sequence Length (bp)",
+            x_labels: self
+                .length_counts
+                .iter()
+                .map(|v| {
+                    if v.lower_count == v.upper_count {
+                        v.lower_count.to_string()
+                    } else {
+                        format!("{}-{}", v.lower_count, v.upper_count)
+                    }
+                })
+                .collect(),
+            series_names: vec!["Sequence Length".to_string()],
+            y_min: 0.0,
+            y_max: counts.iter().fold(0.0f64, |acc, c| acc.max(*c)),
+            series: vec![counts],
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
     fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
-        writeln!(writer, "#Length Count")?;
+        writeln!(writer, "#Length\tCount")?;
         for value in &self.length_counts {
+            let count = format_double(value.value as f64);
             if value.lower_count == value.upper_count {
-                writeln!(writer, "{}\t{}", value.lower_count, value.value)?;
+                writeln!(writer, "{}\t{}", value.lower_count, count)?;
             } else {
                 writeln!(
                     writer,
                     "{}-{}\t{}",
-                    value.lower_count, value.upper_count, value.value
+                    value.lower_count, value.upper_count, count
                 )?;
             }
         }
         return Ok(());
     }
 }
-impl<'a> QCModule for SequenceLengthDistribution<'a> {
+impl QCModule for SequenceLengthDistribution {
     fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        if self.length_counts.iter().all(|c| *c == 0) {
+            reports.push(Box::new(SequenceLengthReport {
+                status: QCResult::Pass,
+                length_counts: Vec::new(),
+            }));
+            return Ok(());
+        }
         let (mut min_len, mut max_len) = get_min_max_idx(&self.length_counts);
         let is_same_length = max_len == min_len;
         //  We put one extra category either side of the actual size
-        min_len -= 1;
+        if min_len > 0 {
+            min_len -= 1;
+        }
         max_len += 1;
         let (start, interval) = get_size_distribution(min_len, max_len);
         let mut current_pos = start;
@@ -117,7 +158,7 @@ impl<'a> QCModule for SequenceLengthDistribution<'a> {
         let warn_th = self.config.module_config.get("sequence_length:warn");
         let status = if error_th != 0.0 && self.length_counts[0] > 0 {
             QCResult::Fail
-        } else if warn_th != 0.0 && is_same_length {
+        } else if warn_th != 0.0 && !is_same_length {
             QCResult::Warn
         } else {
             QCResult::Pass
@@ -128,6 +169,21 @@ impl<'a> QCModule for SequenceLengthDistribution<'a> {
         }));
         return Ok(());
     }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other
+            .as_any()
+            .downcast_ref::<SequenceLengthDistribution>()
+            .unwrap();
+        if self.length_counts.len() < other.length_counts.len() {
+            self.length_counts.resize(other.length_counts.len(), 0);
+        }
+        for (idx, count) in other.length_counts.iter().enumerate() {
+            self.length_counts[idx] += *count;
+        }
+    }
     fn process_sequence(&mut self, seq: &Sequence) -> () {
         let len = seq.sequence.len();
         if self.length_counts.len() < len + 2 {
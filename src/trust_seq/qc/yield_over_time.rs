@@ -0,0 +1,263 @@
+use crate::trust_seq::qc::{QCModule, QCReport, QCResult};
+use crate::trust_seq::svg::LineGraph;
+use crate::trust_seq::trust_seq::TrustSeqErr;
+use crate::trust_seq::utils::Sequence;
+use serde_json::map::Map;
+use serde_json::value;
+use serde_json::value::Value;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str;
+
+// Bin widths in minutes, the first one giving at most MAX_BINS bins is used.
+const INTERVALS: [i64; 10] = [1, 5, 10, 15, 30, 60, 120, 360, 720, 1440];
+const MAX_BINS: i64 = 60;
+
+/// Reads and bases sequenced over the run, from the `start_time=` field
+/// nanopore basecallers write into read headers.
+pub struct YieldOverTime {
+    // Reads and bases by the minute (since 1970) their sequencing started.
+    minute_counts: BTreeMap<i64, (u64, u64)>,
+    missing_count: u64,
+}
+#[derive(Serialize)]
+pub struct YieldOverTimeReport {
+    pub status: QCResult,
+    pub reads_without_time: u64,
+    pub interval_minutes: i64,
+    pub bins: Vec<YieldBin>,
+}
+#[derive(Serialize)]
+pub struct YieldBin {
+    pub start_minute: i64,
+    pub reads: u64,
+    pub bases: u64,
+    pub cumulative_bases: u64,
+}
+impl YieldOverTime {
+    pub fn new() -> YieldOverTime {
+        return YieldOverTime {
+            minute_counts: BTreeMap::new(),
+            missing_count: 0,
+        };
+    }
+}
+fn parse_number(text: &[u8]) -> Option<i64> {
+    if text.is_empty() || !text.iter().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    return str::from_utf8(text).ok()?.parse::<i64>().ok();
+}
+/// Days from 1970-01-01 to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    return era * 146097 + day_of_era - 719468;
+}
+/// Parses an ISO 8601 time such as `2019-06-01T12:34:56Z` or
+/// `2019-06-01T12:34:56.789+02:00` into seconds since 1970 in UTC.
+fn parse_time(text: &[u8]) -> Option<i64> {
+    if text.len() < 19 || text[4] != b'-' || text[7] != b'-' || text[10] != b'T' {
+        return None;
+    }
+    if text[13] != b':' || text[16] != b':' {
+        return None;
+    }
+    let days = days_from_civil(
+        parse_number(&text[0..4])?,
+        parse_number(&text[5..7])?,
+        parse_number(&text[8..10])?,
+    );
+    let seconds = parse_number(&text[11..13])? * 3600
+        + parse_number(&text[14..16])? * 60
+        + parse_number(&text[17..19])?;
+    let mut rest = &text[19..];
+    if rest.first() == Some(&b'.') {
+        let digits = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        rest = &rest[(1 + digits)..];
+    }
+    let offset = match rest.first() {
+        None | Some(b'Z') => 0,
+        Some(sign) if (*sign == b'+' || *sign == b'-') && rest.len() == 6 && rest[3] == b':' => {
+            let offset = parse_number(&rest[1..3])? * 3600 + parse_number(&rest[4..6])? * 60;
+            if *sign == b'+' {
+                offset
+            } else {
+                -offset
+            }
+        }
+        _ => return None,
+    };
+    return Some(days * 86400 + seconds - offset);
+}
+/// Returns the start time of a read in seconds since 1970, if its header
+/// has a `start_time=` field.
+pub fn get_start_time(id: &[u8]) -> Option<i64> {
+    for field in id.split(|c| *c == b' ' || *c == b'\t') {
+        if field.starts_with(b"start_time=") {
+            return parse_time(&field[11..]);
+        }
+    }
+    return None;
+}
+impl QCReport for YieldOverTimeReport {
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn get_name(&self) -> &'static str {
+        return "Yield over time";
+    }
+    fn get_status(&self) -> QCResult {
+        return self.status;
+    }
+    fn add_json(&self, map: &mut Map<String, Value>) -> Result<(), TrustSeqErr> {
+        map.insert(self.get_name().to_string(), value::to_value(self)?);
+        return Ok(());
+    }
+    fn print_html_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        let total = self.bins.last().map_or(0, |b| b.cumulative_bases);
+        let graph = LineGraph {
+            title: format!("Cumulative yield, {} bases", total),
+            x_title: "Run Time (minutes)",
+            x_labels: self
+                .bins
+                .iter()
+                .map(|b| b.start_minute.to_string())
+                .collect(),
+            series_names: vec!["% of bases".to_string()],
+            series: vec![self
+                .bins
+                .iter()
+                .map(|b| b.cumulative_bases as f64 * 100.0 / total.max(1) as f64)
+                .collect()],
+            y_min: 0.0,
+            y_max: 100.0,
+        };
+        graph.write_svg(writer)?;
+        return Ok(());
+    }
+    fn print_text_report(&self, writer: &mut Write) -> Result<(), TrustSeqErr> {
+        writeln!(
+            writer,
+            "#Reads without start_time\t{}",
+            self.reads_without_time
+        )?;
+        writeln!(writer, "#Minute\tReads\tBases\tCumulative Bases")?;
+        for bin in &self.bins {
+            if self.interval_minutes > 1 {
+                write!(
+                    writer,
+                    "{}-{}",
+                    bin.start_minute,
+                    bin.start_minute + self.interval_minutes - 1
+                )?;
+            } else {
+                write!(writer, "{}", bin.start_minute)?;
+            }
+            writeln!(
+                writer,
+                "\t{}\t{}\t{}",
+                bin.reads, bin.bases, bin.cumulative_bases
+            )?;
+        }
+        return Ok(());
+    }
+}
+impl QCModule for YieldOverTime {
+    fn ignore_in_report(&self) -> bool {
+        return self.minute_counts.is_empty();
+    }
+    fn calculate(&self, reports: &mut Vec<Box<QCReport>>) -> Result<(), TrustSeqErr> {
+        if self.minute_counts.is_empty() {
+            return Ok(());
+        }
+        let first = self.minute_counts.keys().next().map_or(0, |m| *m);
+        let last = self.minute_counts.keys().next_back().map_or(0, |m| *m);
+        let span = last - first + 1;
+        let mut interval = INTERVALS[INTERVALS.len() - 1];
+        for i in INTERVALS.iter() {
+            if (span + i - 1) / i <= MAX_BINS {
+                interval = *i;
+                break;
+            }
+        }
+        let mut bins: Vec<YieldBin> = Vec::new();
+        let mut cumulative_bases = 0;
+        for (minute, counts) in &self.minute_counts {
+            let start_minute = (minute - first) / interval * interval;
+            while bins.last().map_or(true, |b| b.start_minute < start_minute) {
+                let next = bins.last().map_or(0, |b| b.start_minute + interval);
+                bins.push(YieldBin {
+                    start_minute: next,
+                    reads: 0,
+                    bases: 0,
+                    cumulative_bases: cumulative_bases,
+                });
+            }
+            cumulative_bases += counts.1;
+            let bin = bins.last_mut().unwrap();
+            bin.reads += counts.0;
+            bin.bases += counts.1;
+            bin.cumulative_bases = cumulative_bases;
+        }
+        reports.push(Box::new(YieldOverTimeReport {
+            status: QCResult::Pass,
+            reads_without_time: self.missing_count,
+            interval_minutes: interval,
+            bins: bins,
+        }));
+        return Ok(());
+    }
+    fn as_any(&self) -> &Any {
+        return self;
+    }
+    fn merge(&mut self, other: &QCModule) -> () {
+        let other = other.as_any().downcast_ref::<YieldOverTime>().unwrap();
+        for (minute, counts) in &other.minute_counts {
+            let c = self.minute_counts.entry(*minute).or_insert((0, 0));
+            c.0 += counts.0;
+            c.1 += counts.1;
+        }
+        self.missing_count += other.missing_count;
+    }
+    fn process_sequence(&mut self, seq: &Sequence) -> () {
+        match get_start_time(seq.id) {
+            Some(time) => {
+                let c = self
+                    .minute_counts
+                    .entry(time.div_euclid(60))
+                    .or_insert((0, 0));
+                c.0 += 1;
+                c.1 += seq.sequence.len() as u64;
+            }
+            None => self.missing_count += 1,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::get_start_time;
+
+    #[test]
+    fn test_get_start_time() {
+        let id = b"@0a1b2c3d runid=9f8e7d read=12 ch=301 start_time=2019-06-01T12:34:56Z";
+        assert_eq!(Some(1559392496), get_start_time(id));
+        assert_eq!(
+            Some(1559392496),
+            get_start_time(b"@r start_time=2019-06-01T14:34:56.250+02:00 flow_cell_id=FAL1")
+        );
+        assert_eq!(
+            Some(0),
+            get_start_time(b"@r start_time=1970-01-01T00:00:00")
+        );
+        assert_eq!(None, get_start_time(b"@r start_time=2019-06-01 12:34:56"));
+        assert_eq!(
+            None,
+            get_start_time(b"@SIM:1:FCX:1:1101:1000:2000 1:N:0:ATCACG")
+        );
+    }
+}
@@ -0,0 +1,445 @@
+use super::range_corder::{RangeDecoder, RangeEncoder, SimpleModel};
+use std::collections::HashMap;
+
+// Bit probabilities are coded in 1/4096ths.
+const BIT_TOTAL: u32 = 1 << 12;
+// How fast a `BitModel` follows the bits, as a shift: 4 moves the
+// probability 1/16 of the way to the last bit.
+const BIT_RATE: u32 = 4;
+// The logistic function at -2047..2047 in steps of 128, in 1/4096ths.
+const SQUASH: [i32; 33] = [
+    1, 2, 3, 6, 10, 16, 27, 45, 73, 120, 194, 310, 488, 747, 1101, 1546, 2047, 2549, 2994, 3348,
+    3607, 3785, 3901, 3975, 4022, 4050, 4068, 4079, 4085, 4089, 4092, 4093, 4094,
+];
+
+/// Codes a bit whose probability of being 1 is `p1` 4096ths, 1 to 4095.
+pub fn encode_bit(rc: &mut RangeEncoder, bit: bool, p1: u32) {
+    if bit {
+        rc.encode(0, p1, BIT_TOTAL);
+    } else {
+        rc.encode(p1, BIT_TOTAL - p1, BIT_TOTAL);
+    }
+}
+/// Decodes a bit coded by `encode_bit` with the same `p1`.
+pub fn decode_bit(rd: &mut RangeDecoder, p1: u32) -> bool {
+    let bit = rd.get_freq(BIT_TOTAL) < p1;
+    if bit {
+        rd.decode(0, p1, BIT_TOTAL);
+    } else {
+        rd.decode(p1, BIT_TOTAL - p1, BIT_TOTAL);
+    }
+    return bit;
+}
+/// The logistic function: a probability in 4096ths from its log odds,
+/// -2047 to 2047 in 256ths.
+pub fn squash(d: i32) -> u32 {
+    if d > 2047 {
+        return 4095;
+    } else if d < -2047 {
+        return 1;
+    }
+    let w = d & 127;
+    let idx = ((d >> 7) + 16) as usize;
+    return ((SQUASH[idx] * (128 - w) + SQUASH[idx + 1] * w + 64) >> 7).max(1) as u32;
+}
+
+/// An adaptive probability of a bit being 1.
+#[derive(Debug, Clone, Copy)]
+pub struct BitModel {
+    // The probability in 65536ths.
+    p: u16,
+}
+impl BitModel {
+    pub fn new() -> BitModel {
+        return BitModel { p: 1 << 15 };
+    }
+    /// The probability of a 1 in 4096ths, never 0 or 4096.
+    pub fn get_p(&self) -> u32 {
+        return ((self.p >> 4) as u32).max(1).min(BIT_TOTAL - 1);
+    }
+    pub fn update(&mut self, bit: bool) {
+        if bit {
+            self.p += (u16::max_value() - self.p) >> BIT_RATE;
+        } else {
+            self.p -= self.p >> BIT_RATE;
+        }
+    }
+    pub fn encode(&mut self, rc: &mut RangeEncoder, bit: bool) {
+        encode_bit(rc, bit, self.get_p());
+        self.update(bit);
+    }
+    pub fn decode(&mut self, rd: &mut RangeDecoder) -> bool {
+        let bit = decode_bit(rd, self.get_p());
+        self.update(bit);
+        return bit;
+    }
+}
+
+/// Codes a `u32` a byte at a time, most significant first, each byte
+/// with a model of its own.
+#[derive(Debug, Clone)]
+pub struct IntModel {
+    models: Vec<SimpleModel>,
+}
+impl IntModel {
+    pub fn new() -> IntModel {
+        return IntModel {
+            models: vec![SimpleModel::new(256); 4],
+        };
+    }
+    pub fn encode(&mut self, rc: &mut RangeEncoder, value: u32) {
+        for (idx, model) in self.models.iter_mut().enumerate() {
+            model.encode(rc, ((value >> (24 - idx * 8)) & 0xff) as u16);
+        }
+    }
+    pub fn decode(&mut self, rd: &mut RangeDecoder) -> u32 {
+        let mut value = 0;
+        for model in &mut self.models {
+            value = value << 8 | model.decode(rd) as u32;
+        }
+        return value;
+    }
+}
+
+/// An order-k model: the symbols `0..num_of_symbol` are coded with a
+/// `SimpleModel` chosen by the `order` symbols before them. The models
+/// are made as their contexts turn up, so order 2 over bytes is cheap
+/// on data using few of the 65536 contexts.
+#[derive(Debug)]
+pub struct ContextModel {
+    num_of_symbol: usize,
+    // The number of contexts, num_of_symbol to the power of order.
+    context_count: u64,
+    context: u64,
+    models: HashMap<u64, SimpleModel>,
+}
+impl ContextModel {
+    pub fn new(num_of_symbol: usize, order: u32) -> ContextModel {
+        return ContextModel {
+            num_of_symbol: num_of_symbol,
+            context_count: (num_of_symbol as u64).pow(order),
+            context: 0,
+            models: HashMap::new(),
+        };
+    }
+    /// Forgets the symbols before, as at the start of a new read. The
+    /// statistics are kept.
+    pub fn reset_context(&mut self) {
+        self.context = 0;
+    }
+    fn update(&mut self, sym: u16) {
+        self.context = (self.context * self.num_of_symbol as u64 + sym as u64) % self.context_count;
+    }
+    pub fn encode(&mut self, rc: &mut RangeEncoder, sym: u16) {
+        let num_of_symbol = self.num_of_symbol;
+        self.models
+            .entry(self.context)
+            .or_insert_with(|| SimpleModel::new(num_of_symbol))
+            .encode(rc, sym);
+        self.update(sym);
+    }
+    pub fn decode(&mut self, rd: &mut RangeDecoder) -> u16 {
+        let num_of_symbol = self.num_of_symbol;
+        let sym = self
+            .models
+            .entry(self.context)
+            .or_insert_with(|| SimpleModel::new(num_of_symbol))
+            .decode(rd);
+        self.update(sym);
+        return sym;
+    }
+}
+
+/// Predicts the bases A, C, G and T, as 2 bit codes, from the `order`
+/// bases before them. Each base is two binary decisions: the high bit,
+/// then the low bit given the high one.
+#[derive(Debug)]
+pub struct NucleotideModel {
+    order: usize,
+    // The probabilities of three `BitModel`s per context: the high bit,
+    // then the low bit after a 0 and after a 1. They are kept xor 1/2, so
+    // a new model is memory the allocator hands out zeroed, and only the
+    // pages of contexts that turn up are ever touched.
+    models: Vec<u16>,
+}
+impl NucleotideModel {
+    /// A model of 3 * 4^order bit models, so 24M bytes at order 11.
+    pub fn new(order: usize) -> NucleotideModel {
+        return NucleotideModel {
+            order: order,
+            models: vec![0; 3 << (2 * order)],
+        };
+    }
+    fn get_model(&self, idx: usize) -> BitModel {
+        return BitModel {
+            p: self.models[idx] ^ BitModel::new().p,
+        };
+    }
+    /// The index of the bit model for `node`, 0 for the high bit or 1 + the
+    /// high bit for the low one, in `history`: the bases before, the last
+    /// one in the lowest 2 bits.
+    fn get_index(&self, history: u64, node: usize) -> usize {
+        let mask = (1u64 << (2 * self.order)) - 1;
+        return (history & mask) as usize * 3 + node;
+    }
+    pub fn get_p(&self, history: u64, node: usize) -> u32 {
+        return self.get_model(self.get_index(history, node)).get_p();
+    }
+    pub fn update(&mut self, history: u64, node: usize, bit: bool) {
+        let idx = self.get_index(history, node);
+        let mut model = self.get_model(idx);
+        model.update(bit);
+        self.models[idx] = model.p ^ BitModel::new().p;
+    }
+}
+
+/// Combines the predictions of several models of a bit into one, by
+/// weights in the logistic domain that are learnt as the bits are coded.
+/// Weight sets can be kept apart by a small context.
+#[derive(Debug)]
+pub struct Mixer {
+    input_count: usize,
+    // Weights in 65536ths, a set of input_count per context.
+    weights: Vec<i32>,
+    // The log odds of every probability in 4096ths.
+    stretch: Vec<i16>,
+    inputs: Vec<i32>,
+    set: usize,
+    p: u32,
+}
+impl Mixer {
+    pub fn new(input_count: usize, context_count: usize) -> Mixer {
+        // stretch is the inverse of squash.
+        let mut stretch = vec![2047i16; BIT_TOTAL as usize];
+        let mut next = 0;
+        for d in -2047..2048 {
+            let p = squash(d) as usize;
+            for s in &mut stretch[next..(p + 1).max(next)] {
+                *s = d as i16;
+            }
+            next = next.max(p + 1);
+        }
+        return Mixer {
+            input_count: input_count,
+            weights: vec![(1 << 16) / input_count as i32; input_count * context_count],
+            stretch: stretch,
+            inputs: Vec::with_capacity(input_count),
+            set: 0,
+            p: BIT_TOTAL / 2,
+        };
+    }
+    /// Returns the mixed probability in 4096ths of the probabilities
+    /// `ps`, one per input, with the weight set of `context`.
+    pub fn mix(&mut self, ps: &[u32], context: usize) -> u32 {
+        self.inputs.clear();
+        for p in ps {
+            self.inputs.push(self.stretch[*p as usize] as i32);
+        }
+        self.set = context * self.input_count;
+        let weights = &self.weights[self.set..(self.set + self.input_count)];
+        let mut dot: i64 = 0;
+        for (w, s) in weights.iter().zip(self.inputs.iter()) {
+            dot += *w as i64 * *s as i64;
+        }
+        self.p = squash((dot >> 16) as i32).min(BIT_TOTAL - 1);
+        return self.p;
+    }
+    /// Moves the weights of the last `mix` towards the inputs that
+    /// predicted `bit` best.
+    pub fn update(&mut self, bit: bool) {
+        let err = ((bit as i32) << 12) - self.p as i32;
+        let weights = &mut self.weights[self.set..(self.set + self.input_count)];
+        for (w, s) in weights.iter_mut().zip(self.inputs.iter()) {
+            *w = (*w + ((*s * err) >> 10)).max(-(8 << 16)).min(8 << 16);
+        }
+    }
+}
+
+/// Codes bases with `NucleotideModel`s of several orders mixed, so short
+/// contexts carry the coding until the long ones have seen enough bases.
+#[derive(Debug)]
+pub struct MixedNucleotideModel {
+    models: Vec<NucleotideModel>,
+    mixer: Mixer,
+    history: u64,
+    ps: Vec<u32>,
+}
+impl MixedNucleotideModel {
+    /// Orders below 32, the bases the history holds.
+    pub fn new(orders: &[usize]) -> MixedNucleotideModel {
+        return MixedNucleotideModel {
+            models: orders.iter().map(|o| NucleotideModel::new(*o)).collect(),
+            // A weight set per node of the 2 bit tree.
+            mixer: Mixer::new(orders.len(), 3),
+            history: 0,
+            ps: Vec::with_capacity(orders.len()),
+        };
+    }
+    fn predict(&mut self, node: usize) -> u32 {
+        self.ps.clear();
+        for model in &self.models {
+            self.ps.push(model.get_p(self.history, node));
+        }
+        return self.mixer.mix(&self.ps, node);
+    }
+    fn update(&mut self, node: usize, bit: bool) {
+        self.mixer.update(bit);
+        for model in &mut self.models {
+            model.update(self.history, node, bit);
+        }
+    }
+    /// Codes `code`, 0 to 3 for A, C, G and T.
+    pub fn encode(&mut self, rc: &mut RangeEncoder, code: u16) {
+        let high = code & 2 != 0;
+        let p = self.predict(0);
+        encode_bit(rc, high, p);
+        self.update(0, high);
+        let node = 1 + high as usize;
+        let low = code & 1 != 0;
+        let p = self.predict(node);
+        encode_bit(rc, low, p);
+        self.update(node, low);
+        self.history = self.history << 2 | code as u64;
+    }
+    pub fn decode(&mut self, rd: &mut RangeDecoder) -> u16 {
+        let p = self.predict(0);
+        let high = decode_bit(rd, p);
+        self.update(0, high);
+        let node = 1 + high as usize;
+        let p = self.predict(node);
+        let low = decode_bit(rd, p);
+        self.update(node, low);
+        let code = (high as u16) << 1 | low as u16;
+        self.history = self.history << 2 | code as u64;
+        return code;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::range_corder::{RangeDecoder, RangeEncoder};
+    use super::{squash, BitModel, ContextModel, MixedNucleotideModel, Mixer};
+
+    // A small deterministic generator, so a failing case can be rerun.
+    struct Random(u64);
+    impl Random {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            return (self.0 >> 33) % n;
+        }
+    }
+    // Symbols that follow a pattern part of the time, so the contexts
+    // have something to learn.
+    fn random_symbols(random: &mut Random, num_of_symbol: u64) -> Vec<u16> {
+        let len = random.next(5000) as usize;
+        let skew = random.next(100);
+        let mut symbols: Vec<u16> = Vec::new();
+        for i in 0..len {
+            let sym = if random.next(100) < skew && i > 1 {
+                (symbols[i - 1] + symbols[i - 2]) % num_of_symbol as u16
+            } else {
+                random.next(num_of_symbol) as u16
+            };
+            symbols.push(sym);
+        }
+        return symbols;
+    }
+
+    #[test]
+    fn test_squash() {
+        assert_eq!(2047, squash(0));
+        assert_eq!(1, squash(-5000));
+        assert_eq!(4095, squash(5000));
+        let mixer = Mixer::new(1, 1);
+        for d in &[-1500, -300, 0, 700, 2000] {
+            let p = squash(*d);
+            assert_eq!(p, squash(mixer.stretch[p as usize] as i32));
+        }
+    }
+    #[test]
+    fn test_bit_model_round_trip() {
+        let mut random = Random(1);
+        for _ in 0..50 {
+            let one_percent = random.next(101);
+            let bits: Vec<bool> = (0..random.next(20000))
+                .map(|_| random.next(100) < one_percent)
+                .collect();
+            let mut rc = RangeEncoder::new(1024);
+            let mut model = BitModel::new();
+            for bit in &bits {
+                model.encode(&mut rc, *bit);
+            }
+            rc.finish_encode();
+            let mut rd = RangeDecoder::new(rc.get_buf());
+            let mut model = BitModel::new();
+            for bit in &bits {
+                assert_eq!(*bit, model.decode(&mut rd));
+            }
+            // Constant bits cost next to nothing.
+            if one_percent == 0 || one_percent == 100 {
+                assert!(rc.get_buf().len() < 100 + bits.len() / 100);
+            }
+        }
+    }
+    #[test]
+    fn test_context_model_round_trip() {
+        let mut random = Random(2);
+        for case in 0..60 {
+            let num_of_symbol = [2, 4, 37, 256][case % 4];
+            let order = (case / 4 % 3) as u32;
+            let symbols = random_symbols(&mut random, num_of_symbol);
+            let mut rc = RangeEncoder::new(1024);
+            let mut model = ContextModel::new(num_of_symbol as usize, order);
+            for (idx, sym) in symbols.iter().enumerate() {
+                if idx % 1000 == 0 {
+                    model.reset_context();
+                }
+                model.encode(&mut rc, *sym);
+            }
+            rc.finish_encode();
+            let mut rd = RangeDecoder::new(rc.get_buf());
+            let mut model = ContextModel::new(num_of_symbol as usize, order);
+            for (idx, sym) in symbols.iter().enumerate() {
+                if idx % 1000 == 0 {
+                    model.reset_context();
+                }
+                assert_eq!(*sym, model.decode(&mut rd), "case {} symbol {}", case, idx);
+            }
+        }
+    }
+    #[test]
+    fn test_mixed_nucleotide_model_round_trip() {
+        let mut random = Random(3);
+        // Reads from a small genome, so the long contexts repeat.
+        let genome: Vec<u16> = (0..20000).map(|_| random.next(4) as u16).collect();
+        for case in 0..10 {
+            let mut bases: Vec<u16> = Vec::new();
+            if case < 5 {
+                bases.extend(random_symbols(&mut random, 4));
+            } else {
+                for _ in 0..2000 {
+                    let start = random.next(genome.len() as u64 - 100) as usize;
+                    bases.extend_from_slice(&genome[start..(start + 100)]);
+                }
+            }
+            let mut rc = RangeEncoder::new(1024);
+            let mut model = MixedNucleotideModel::new(&[2, 11]);
+            for base in &bases {
+                model.encode(&mut rc, *base);
+            }
+            rc.finish_encode();
+            let mut rd = RangeDecoder::new(rc.get_buf());
+            let mut model = MixedNucleotideModel::new(&[2, 11]);
+            for (idx, base) in bases.iter().enumerate() {
+                assert_eq!(*base, model.decode(&mut rd), "case {} base {}", case, idx);
+            }
+            // 200000 bases of a 20000 base genome take well under 2 bits.
+            if case >= 5 {
+                assert!(rc.get_buf().len() * 4 * 2 < bases.len());
+            }
+        }
+    }
+}
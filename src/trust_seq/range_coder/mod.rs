@@ -1 +1,3 @@
+pub mod context_model;
+pub mod name_tokenizer;
 pub mod range_corder;
@@ -0,0 +1,340 @@
+use super::context_model::{ContextModel, IntModel};
+use super::range_corder::{RangeDecoder, RangeEncoder, SimpleModel};
+use std::cmp::Ordering;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result;
+
+/// The most tokens a name is split into, the last one taking the rest of
+/// the name.
+pub const MAX_TOKENS: usize = 32;
+// Integers have at most 9 digits, so they fit a u32.
+const MAX_DIGITS: usize = 9;
+// The largest difference to the integer of the previous name coded as a
+// delta.
+const MAX_DELTA: u32 = 255;
+// Names longer than this are taken for corrupt input by `decode_names`.
+const MAX_NAME_LENGTH: u64 = 1 << 20;
+
+/// A field of a read name: a run of digits without a leading zero, or
+/// the text between two of those.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    String(Vec<u8>),
+    Integer(u32),
+}
+/// How a token is coded, against the token in the same column of the
+/// previous name.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TokenType {
+    /// The same as the previous token.
+    Match,
+    /// An integer up to `MAX_DELTA` more than the previous integer.
+    Delta,
+    Integer,
+    String,
+    /// No more tokens.
+    End,
+}
+impl TokenType {
+    fn from_symbol(sym: u16) -> TokenType {
+        return match sym {
+            0 => TokenType::Match,
+            1 => TokenType::Delta,
+            2 => TokenType::Integer,
+            3 => TokenType::String,
+            _ => TokenType::End,
+        };
+    }
+}
+
+fn is_integer(digits: &[u8]) -> bool {
+    return digits.len() <= MAX_DIGITS && (digits[0] != b'0' || digits.len() == 1);
+}
+/// Splits a read name into tokens. The bytes of the tokens joined are the
+/// name: `A00123:45:HXXX:1:1101:1234:5678` is the strings `A` and `00123`
+/// (zero padded), then `:`, 45, `:HXXX:`, 1, `:`, 1101 and so on.
+pub fn tokenize(name: &[u8]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    while start < name.len() {
+        if tokens.len() == MAX_TOKENS - 1 {
+            tokens.push(Token::String(name[start..].to_vec()));
+            break;
+        }
+        let is_digit = name[start].is_ascii_digit();
+        let len = name[start..]
+            .iter()
+            .take_while(|c| c.is_ascii_digit() == is_digit)
+            .count();
+        let text = &name[start..(start + len)];
+        if is_digit && is_integer(text) {
+            let value = text.iter().fold(0, |v, c| v * 10 + (c - b'0') as u32);
+            tokens.push(Token::Integer(value));
+        } else {
+            tokens.push(Token::String(text.to_vec()));
+        }
+        start += len;
+    }
+    return tokens;
+}
+/// Orders read names field by field, integers by their value and the text
+/// between them byte by byte, so `r9` comes before `r10`. Integers come
+/// before text.
+pub fn compare_names(a: &[u8], b: &[u8]) -> Ordering {
+    let (a, b) = (tokenize(a), tokenize(b));
+    for (x, y) in a.iter().zip(b.iter()) {
+        let order = match (x, y) {
+            (&Token::Integer(x), &Token::Integer(y)) => x.cmp(&y),
+            (&Token::String(ref x), &Token::String(ref y)) => x.cmp(y),
+            (&Token::Integer(_), &Token::String(_)) => Ordering::Less,
+            (&Token::String(_), &Token::Integer(_)) => Ordering::Greater,
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    return a.len().cmp(&b.len());
+}
+fn push_token(name: &mut Vec<u8>, token: &Token) {
+    match *token {
+        Token::String(ref text) => name.extend_from_slice(text),
+        Token::Integer(value) => name.extend_from_slice(value.to_string().as_bytes()),
+    }
+}
+
+/// The models of one token column.
+struct ColumnModels {
+    types: SimpleModel,
+    deltas: SimpleModel,
+    integers: IntModel,
+    // Order 1 over the bytes of the string, 256 ending it.
+    strings: ContextModel,
+}
+impl ColumnModels {
+    fn new() -> ColumnModels {
+        return ColumnModels {
+            types: SimpleModel::new(5),
+            deltas: SimpleModel::new(MAX_DELTA as usize + 1),
+            integers: IntModel::new(),
+            strings: ContextModel::new(257, 1),
+        };
+    }
+}
+
+/// Codes read names as tokens, each column of tokens with models of its
+/// own. Consecutive names mostly differ in a few small deltas, such as
+/// the tile and x/y coordinates of Illumina names, so most tokens cost
+/// next to nothing. The same `NameModel` codes or decodes a series of
+/// names; each name is coded against the one before.
+pub struct NameModel {
+    columns: Vec<ColumnModels>,
+    previous: Vec<Token>,
+}
+impl NameModel {
+    pub fn new() -> NameModel {
+        return NameModel {
+            columns: (0..MAX_TOKENS).map(|_| ColumnModels::new()).collect(),
+            previous: Vec::new(),
+        };
+    }
+    fn get_type(&self, column: usize, token: &Token) -> TokenType {
+        let previous = self.previous.get(column);
+        if previous == Some(token) {
+            return TokenType::Match;
+        }
+        return match (token, previous) {
+            (&Token::Integer(value), Some(&Token::Integer(p)))
+                if p < value && value - p <= MAX_DELTA =>
+            {
+                TokenType::Delta
+            }
+            (&Token::Integer(_), _) => TokenType::Integer,
+            (&Token::String(_), _) => TokenType::String,
+        };
+    }
+    pub fn encode(&mut self, rc: &mut RangeEncoder, name: &[u8]) {
+        let tokens = tokenize(name);
+        for (column, token) in tokens.iter().enumerate() {
+            let token_type = self.get_type(column, token);
+            let models = &mut self.columns[column];
+            models.types.encode(rc, token_type as u16);
+            match (token_type, token) {
+                (TokenType::Delta, &Token::Integer(value)) => {
+                    let previous = match self.previous[column] {
+                        Token::Integer(p) => p,
+                        _ => 0,
+                    };
+                    models.deltas.encode(rc, (value - previous) as u16);
+                }
+                (TokenType::Integer, &Token::Integer(value)) => models.integers.encode(rc, value),
+                (TokenType::String, &Token::String(ref text)) => {
+                    models.strings.reset_context();
+                    for c in text {
+                        models.strings.encode(rc, *c as u16);
+                    }
+                    models.strings.encode(rc, 256);
+                }
+                _ => {}
+            }
+        }
+        if tokens.len() < MAX_TOKENS {
+            self.columns[tokens.len()]
+                .types
+                .encode(rc, TokenType::End as u16);
+        }
+        self.previous = tokens;
+    }
+    /// Decodes a name into `name`, failing on corrupt input or on a name
+    /// longer than `limit`.
+    pub fn decode(&mut self, rd: &mut RangeDecoder, name: &mut Vec<u8>, limit: u64) -> Result<()> {
+        let corrupt = || Error::new(ErrorKind::InvalidData, "corrupt read name");
+        let mut tokens = Vec::new();
+        name.clear();
+        for column in 0..MAX_TOKENS {
+            let models = &mut self.columns[column];
+            let token = match TokenType::from_symbol(models.types.decode(rd)) {
+                TokenType::End => break,
+                TokenType::Match => match self.previous.get(column) {
+                    Some(t) => t.clone(),
+                    None => return Err(corrupt()),
+                },
+                TokenType::Delta => match self.previous.get(column) {
+                    Some(&Token::Integer(p)) => {
+                        let delta = models.deltas.decode(rd) as u32;
+                        Token::Integer(p.checked_add(delta).ok_or_else(corrupt)?)
+                    }
+                    _ => return Err(corrupt()),
+                },
+                TokenType::Integer => Token::Integer(models.integers.decode(rd)),
+                TokenType::String => {
+                    let mut text = Vec::new();
+                    models.strings.reset_context();
+                    loop {
+                        let c = models.strings.decode(rd);
+                        if c == 256 {
+                            break;
+                        }
+                        if (name.len() + text.len()) as u64 >= limit {
+                            return Err(corrupt());
+                        }
+                        text.push(c as u8);
+                    }
+                    Token::String(text)
+                }
+            };
+            push_token(name, &token);
+            if name.len() as u64 > limit {
+                return Err(corrupt());
+            }
+            tokens.push(token);
+        }
+        self.previous = tokens;
+        return Ok(());
+    }
+}
+
+/// Codes a series of read names on their own, for `decode_names`.
+pub fn encode_names<'a, I: IntoIterator<Item = &'a [u8]>>(names: I) -> Vec<u8> {
+    let mut rc = RangeEncoder::new(1 << 16);
+    let mut model = NameModel::new();
+    for name in names {
+        model.encode(&mut rc, name);
+    }
+    rc.finish_encode();
+    return rc.get_buf().to_vec();
+}
+/// Decodes `count` names coded by `encode_names`.
+pub fn decode_names(buf: &[u8], count: usize) -> Result<Vec<Vec<u8>>> {
+    let mut rd = RangeDecoder::new(buf);
+    let mut model = NameModel::new();
+    let mut names = Vec::new();
+    for _ in 0..count {
+        let mut name = Vec::new();
+        model.decode(&mut rd, &mut name, MAX_NAME_LENGTH)?;
+        names.push(name);
+    }
+    return Ok(names);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compare_names, decode_names, encode_names, tokenize, Token, MAX_TOKENS};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn test_tokenize() {
+        let s = |t: &str| Token::String(t.as_bytes().to_vec());
+        assert_eq!(
+            vec![
+                s("A"),
+                s("00123"),
+                s(":"),
+                Token::Integer(45),
+                s(":HXXX:"),
+                Token::Integer(1),
+                s(":"),
+                Token::Integer(1101),
+                s(":"),
+                Token::Integer(1234),
+                s(":"),
+                Token::Integer(5678),
+                s(" "),
+                Token::Integer(1),
+                s(":N:"),
+                Token::Integer(0),
+                s(":ACGT"),
+            ],
+            tokenize(b"A00123:45:HXXX:1:1101:1234:5678 1:N:0:ACGT")
+        );
+        assert_eq!(
+            vec![s("read"), s("12345678901"), s("/"), Token::Integer(1)],
+            tokenize(b"read12345678901/1")
+        );
+        assert!(tokenize(b"").is_empty());
+        let many = "1.".repeat(40);
+        let tokens = tokenize(many.as_bytes());
+        assert_eq!(MAX_TOKENS, tokens.len());
+        assert_eq!(s(&many[31..]), tokens[MAX_TOKENS - 1]);
+    }
+    #[test]
+    fn test_compare_names() {
+        assert_eq!(Ordering::Less, compare_names(b"r9", b"r10"));
+        assert_eq!(
+            Ordering::Greater,
+            compare_names(b"SIM:1:FCX:1:1102:1000:2000", b"SIM:1:FCX:1:1101:9999:9999")
+        );
+        assert_eq!(Ordering::Equal, compare_names(b"r1/1", b"r1/1"));
+        assert_eq!(Ordering::Less, compare_names(b"r1", b"r1/1"));
+        assert_eq!(Ordering::Less, compare_names(b"", b"r1"));
+        // A leading zero makes text of the digits.
+        assert_eq!(Ordering::Less, compare_names(b"r10", b"r01"));
+    }
+    #[test]
+    fn test_encode_decode_names() {
+        let mut names: Vec<Vec<u8>> = Vec::new();
+        for i in 0..5000u32 {
+            names.push(
+                format!(
+                    "A00123:45:HXXX:1:{}:{}:{} 1:N:0:ACGT",
+                    1101 + i / 1000,
+                    1000 + i * 7 % 30000,
+                    2000 + i * 3
+                )
+                .into_bytes(),
+            );
+        }
+        // Names that change shape, and ones past MAX_TOKENS.
+        names.push(b"".to_vec());
+        names.push(b"SRR001666.1 071112_SLXA-EAS1_s_7:5:1:817:345 length=36".to_vec());
+        names.push(b"SRR001666.2 071112_SLXA-EAS1_s_7:5:1:801:338 length=36".to_vec());
+        names.push(b"0000:4294967295:999999999:1000000000".to_vec());
+        names.push("1.".repeat(40).into_bytes());
+        names.push("1.".repeat(41).into_bytes());
+        names.push(vec![0, 255, b'7', 10]);
+        let buf = encode_names(names.iter().map(|n| &n[..]));
+        assert_eq!(names, decode_names(&buf, names.len()).unwrap());
+        // Well under 2 bytes a name, against some 40 bytes of text.
+        assert!(buf.len() < 2 * names.len());
+    }
+}
@@ -5,46 +5,54 @@ struct SymFreqs {
 }
 const MAX_FREQ: u32 = ((1u32 << 16) - 32) as u32;
 const TOP: u32 = (1 << 24);
+/// A carryless range coder writing the coded bytes to a buffer.
 #[derive(Debug)]
-struct RangeEncoder {
+pub struct RangeEncoder {
     low: u64,
     range: u32,
     code: u32,
     buf: Vec<u8>,
 }
 impl RangeEncoder {
-    fn new(buff_size: usize) -> RangeEncoder {
+    pub fn new(buff_size: usize) -> RangeEncoder {
         return RangeEncoder {
             low: 0,
             range: std::u32::MAX,
             code: 0,
-            buf: Vec::new(),
+            buf: Vec::with_capacity(buff_size),
         };
     }
-    fn encode(&mut self, cum_freq: u32, freq: u32, total_freq: u32) {
+    pub fn encode(&mut self, cum_freq: u32, freq: u32, total_freq: u32) {
         self.range /= total_freq;
         self.low += cum_freq as u64 * self.range as u64;
         self.range *= freq;
         assert!(cum_freq + freq <= total_freq);
         while self.range < TOP {
-            let m = ((self.low ^ (self.low + self.range as u64)) >> 54) as u32;
-            if m != 0 {
-                self.range = self.low as u32 | (TOP - 1) - (self.low as u32)
+            // The same adjustment as the decoder, so that no carry can
+            // reach the bytes already written.
+            if ((self.low ^ (self.low + self.range as u64)) >> 56) != 0 {
+                self.range = (self.low as u32 | (TOP - 1)) - (self.low as u32);
             }
             self.buf.push((self.low >> 56) as u8);
             self.range <<= 8;
             self.low <<= 8;
         }
     }
-    fn finish_encode(&mut self) -> () {
+    pub fn finish_encode(&mut self) -> () {
         for _ in 0..8 {
             self.buf.push((self.low >> 56) as u8);
             self.low <<= 8;
         }
     }
+    /// The coded bytes, complete once `finish_encode` has been called.
+    pub fn get_buf(&self) -> &[u8] {
+        return &self.buf;
+    }
 }
+/// Decodes the bytes of a `RangeEncoder`. Past the end of the buffer it
+/// reads zeros, so that corrupt input gives wrong symbols but no panic.
 #[derive(Debug)]
-struct RangeDecoder<'a> {
+pub struct RangeDecoder<'a> {
     low: u64,
     range: u32,
     code: u32,
@@ -52,11 +60,11 @@ struct RangeDecoder<'a> {
     buf: &'a [u8],
 }
 impl<'a> RangeDecoder<'a> {
-    fn new(buf: &'a [u8]) -> RangeDecoder<'a> {
+    pub fn new(buf: &'a [u8]) -> RangeDecoder<'a> {
         let mut code: u32 = 0;
         let mut idx: usize = 0;
         for _ in 0..8 {
-            code = code << 8 | buf[idx] as u32;
+            code = code << 8 | buf.get(idx).map_or(0, |b| *b as u32);
             idx += 1;
         }
         return RangeDecoder {
@@ -67,37 +75,44 @@ impl<'a> RangeDecoder<'a> {
             buf: buf,
         };
     }
-    fn decode(&mut self, cum_freq: u32, freq: u32, total_freq: u32) {
+    /// Consumes the symbol `get_freq` pointed at, with the same frequencies
+    /// the encoder was given.
+    pub fn decode(&mut self, cum_freq: u32, freq: u32, total_freq: u32) {
+        debug_assert!(cum_freq + freq <= total_freq);
         let temp: u32 = cum_freq * self.range;
         self.low += temp as u64;
-        self.code -= temp;
+        self.code = self.code.wrapping_sub(temp);
         self.range *= freq;
         while self.range < TOP {
             let tmp = self.low ^ (self.low + self.range as u64);
             if (tmp >> 56) != 0 {
-                let old_range = self.range;
                 self.range = (self.low as u32 | (TOP - 1)) - (self.low as u32);
             }
-            self.code = (self.code << 8) | (self.buf[self.idx] as u32);
+            let byte = self.buf.get(self.idx).map_or(0, |b| *b as u32);
+            self.code = (self.code << 8) | byte;
             self.idx += 1;
             self.range <<= 8;
             self.low <<= 8;
         }
     }
-    fn get_freq(&mut self, total_freq: u32) -> u32 {
+    /// Returns the cumulative frequency of the next symbol, which may be
+    /// `total_freq` or more in corrupt input.
+    pub fn get_freq(&mut self, total_freq: u32) -> u32 {
         self.range /= total_freq;
         return self.code / self.range;
     }
 }
-#[derive(Debug)]
-struct SimpleModel {
+/// An adaptive order-0 model of the symbols `0..num_of_symbol`, kept
+/// roughly sorted from the most to the least frequent.
+#[derive(Debug, Clone)]
+pub struct SimpleModel {
     total_freq: u32,
     bub_count: u32,
     freqs: Vec<SymFreqs>,
 }
 
 impl SimpleModel {
-    fn new(num_of_symbol: usize) -> SimpleModel {
+    pub fn new(num_of_symbol: usize) -> SimpleModel {
         let mut c = SimpleModel {
             total_freq: num_of_symbol as u32,
             bub_count: 0,
@@ -121,7 +136,7 @@ impl SimpleModel {
             self.total_freq += freq.freq as u32;
         }
     }
-    fn encode(&mut self, rc: &mut RangeEncoder, sym: u16) {
+    pub fn encode(&mut self, rc: &mut RangeEncoder, sym: u16) {
         let mut acc_freq: u32 = 0;
         let mut idx: usize = 1;
         while self.freqs[idx].symbol != sym {
@@ -141,13 +156,13 @@ impl SimpleModel {
             self.freqs[idx - 1] = t;
         }
     }
-    fn decode(&mut self, rd: &mut RangeDecoder) -> u16 {
+    pub fn decode(&mut self, rd: &mut RangeDecoder) -> u16 {
         let mut acc_freq: u32 = 0;
         let mut idx: usize = 1;
         let freq = rd.get_freq(self.total_freq);
         loop {
             acc_freq += self.freqs[idx].freq as u32;
-            if acc_freq > freq {
+            if acc_freq > freq || idx + 1 == self.freqs.len() {
                 break;
             }
             idx += 1;
@@ -192,4 +207,38 @@ mod tests {
         }
         assert_eq!(test_text, String::from_utf8(buf).unwrap());
     }
+    #[test]
+    fn test_encode_decode_long() {
+        // Enough symbols for the carry adjustment and the renormalization
+        // of the frequencies to happen many times.
+        let mut symbols: Vec<u16> = Vec::new();
+        let mut x: u32 = 12345;
+        for i in 0..200000 {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345);
+            let sym = if i % 3 == 0 {
+                (x >> 16) % 200
+            } else {
+                (x >> 16) % 4
+            };
+            symbols.push(sym as u16);
+        }
+        let mut rc = RangeEncoder::new(4096);
+        let mut model = SimpleModel::new(200);
+        for sym in &symbols {
+            model.encode(&mut rc, *sym);
+        }
+        rc.finish_encode();
+        let mut model = SimpleModel::new(200);
+        let mut rd = RangeDecoder::new(rc.get_buf());
+        for sym in &symbols {
+            assert_eq!(*sym, model.decode(&mut rd));
+        }
+
+        // Truncated input decodes to wrong symbols, not a panic.
+        let mut model = SimpleModel::new(200);
+        let mut rd = RangeDecoder::new(&rc.get_buf()[..10]);
+        for _ in 0..1000 {
+            assert!(model.decode(&mut rd) < 200);
+        }
+    }
 }
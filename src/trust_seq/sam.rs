@@ -0,0 +1,425 @@
+use super::utils::{trim_line_end, LineReader, Mapping, Sequence, SequenceReader};
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result;
+use std::str;
+
+const BAM_MAGIC: [u8; 4] = ['B' as u8, 'A' as u8, 'M' as u8, 1];
+// The 4 bit base codes of BAM records.
+const BAM_BASES: &'static [u8; 16] = b"=ACMGRSVTWYHKDBN";
+const FLAG_SECONDARY: u16 = 0x100;
+const FLAG_SUPPLEMENTARY: u16 = 0x800;
+// Size of the fixed part of a BAM record, up to the read name.
+const BAM_FIXED_LENGTH: usize = 32;
+
+fn invalid_data(msg: String) -> Error {
+    return Error::new(ErrorKind::InvalidData, msg);
+}
+
+fn complement(base: u8) -> u8 {
+    return match base {
+        b'A' => b'T',
+        b'C' => b'G',
+        b'G' => b'C',
+        b'T' => b'A',
+        b'a' => b't',
+        b'c' => b'g',
+        b'g' => b'c',
+        b't' => b'a',
+        b'R' => b'Y',
+        b'Y' => b'R',
+        b'K' => b'M',
+        b'M' => b'K',
+        b'B' => b'V',
+        b'V' => b'B',
+        b'D' => b'H',
+        b'H' => b'D',
+        b => b,
+    };
+}
+
+/// The read of the last record, owned by the reader the `Sequence` it
+/// hands out borrows from.
+struct AlignedRead {
+    id: Vec<u8>,
+    sequence: Vec<u8>,
+    quality: Vec<u8>,
+    mapping: Mapping,
+}
+impl AlignedRead {
+    fn new() -> AlignedRead {
+        return AlignedRead {
+            id: Vec::new(),
+            sequence: Vec::new(),
+            quality: Vec::new(),
+            mapping: Mapping { flag: 0, mapq: 0 },
+        };
+    }
+    /// Secondary and supplementary records repeat a read that has its
+    /// primary record elsewhere in the file.
+    fn is_primary(&self) -> bool {
+        return self.mapping.flag & (FLAG_SECONDARY | FLAG_SUPPLEMENTARY) == 0;
+    }
+    /// Aligners store reads on the reverse strand reverse complemented:
+    /// turn them back to the orientation they were sequenced in.
+    fn restore_orientation(&mut self) {
+        if self.mapping.is_reverse() {
+            self.sequence.reverse();
+            for base in self.sequence.iter_mut() {
+                *base = complement(*base);
+            }
+            self.quality.reverse();
+        }
+    }
+    fn as_sequence(&self, mapped_only: bool) -> Sequence {
+        return Sequence {
+            id: &self.id,
+            sequence: &self.sequence,
+            quality: &self.quality,
+            filtered: false,
+            excluded: mapped_only && self.mapping.is_unmapped(),
+            mapping: Some(self.mapping),
+        };
+    }
+}
+
+/// Reads the primary records of a SAM file.
+pub struct SamReader<T: Read> {
+    reader: LineReader<T>,
+    read: AlignedRead,
+    mapped_only: bool,
+    line_count: u64,
+}
+impl<T: Read> SamReader<T> {
+    pub fn new(read: T) -> SamReader<T> {
+        return SamReader {
+            reader: LineReader::new(read, 4096),
+            read: AlignedRead::new(),
+            mapped_only: false,
+            line_count: 0,
+        };
+    }
+    /// Marks the unmapped reads as excluded.
+    pub fn set_mapped_only(&mut self, mapped_only: bool) {
+        self.mapped_only = mapped_only;
+    }
+    fn parse_line(read: &mut AlignedRead, line: &[u8]) -> Option<()> {
+        let mut fields = line.split(|c| *c == '\t' as u8);
+        let id = fields.next()?;
+        let flag = str::from_utf8(fields.next()?).ok()?.parse::<u16>().ok()?;
+        let mapq = str::from_utf8(fields.nth(2)?).ok()?.parse::<u8>().ok()?;
+        let sequence = fields.nth(4)?;
+        let quality = fields.next()?;
+        read.id.clear();
+        read.id.extend_from_slice(id);
+        read.mapping = Mapping {
+            flag: flag,
+            mapq: mapq,
+        };
+        read.sequence.clear();
+        if sequence != b"*" {
+            read.sequence
+                .extend(sequence.iter().map(|c| c.to_ascii_uppercase()));
+        }
+        read.quality.clear();
+        if quality != b"*" {
+            if quality.len() != read.sequence.len() {
+                return None;
+            }
+            read.quality.extend_from_slice(quality);
+        }
+        return Some(());
+    }
+    pub fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        loop {
+            let line = match self.reader.read_line()? {
+                Some(l) => trim_line_end(l),
+                None => return Ok(None),
+            };
+            self.line_count += 1;
+            if line.is_empty() || line[0] == '@' as u8 {
+                continue;
+            }
+            if SamReader::<T>::parse_line(&mut self.read, line).is_none() {
+                return Err(invalid_data(format!(
+                    "line {} is not a valid SAM record",
+                    self.line_count
+                )));
+            }
+            if self.read.is_primary() {
+                break;
+            }
+        }
+        self.read.restore_orientation();
+        return Ok(Some(self.read.as_sequence(self.mapped_only)));
+    }
+}
+impl<T: Read> SequenceReader for SamReader<T> {
+    fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        return SamReader::next_seq(self);
+    }
+}
+
+fn read_i32<T: Read>(read: &mut T) -> Result<i32> {
+    let mut buf = [0u8; 4];
+    read.read_exact(&mut buf)?;
+    return Ok(i32::from_le_bytes(buf));
+}
+fn get_u16(buf: &[u8], pos: usize) -> u16 {
+    return u16::from_le_bytes([buf[pos], buf[pos + 1]]);
+}
+fn get_i32(buf: &[u8], pos: usize) -> i32 {
+    return i32::from_le_bytes([buf[pos], buf[pos + 1], buf[pos + 2], buf[pos + 3]]);
+}
+
+/// Reads the primary records of a BAM file. `read` must be the
+/// decompressed stream, see `compression::open_decompressed`.
+pub struct BamReader<T: Read> {
+    read: T,
+    record: Vec<u8>,
+    aligned_read: AlignedRead,
+    mapped_only: bool,
+    record_count: u64,
+}
+impl<T: Read> BamReader<T> {
+    /// Checks the magic and skips the header and the reference list.
+    pub fn new(mut read: T) -> Result<BamReader<T>> {
+        let mut magic = [0u8; 4];
+        read.read_exact(&mut magic)?;
+        if magic != BAM_MAGIC {
+            return Err(invalid_data(format!("not a BAM file")));
+        }
+        let text_length = read_i32(&mut read)?;
+        BamReader::skip(&mut read, text_length)?;
+        let ref_count = read_i32(&mut read)?;
+        for _ in 0..ref_count {
+            let name_length = read_i32(&mut read)?;
+            BamReader::skip(&mut read, name_length)?;
+            read_i32(&mut read)?;
+        }
+        return Ok(BamReader {
+            read: read,
+            record: Vec::new(),
+            aligned_read: AlignedRead::new(),
+            mapped_only: false,
+            record_count: 0,
+        });
+    }
+    /// Marks the unmapped reads as excluded.
+    pub fn set_mapped_only(&mut self, mapped_only: bool) {
+        self.mapped_only = mapped_only;
+    }
+    fn skip(read: &mut T, length: i32) -> Result<()> {
+        if length < 0 {
+            return Err(invalid_data(format!("negative length in the BAM header")));
+        }
+        let skipped = std::io::copy(&mut read.take(length as u64), &mut std::io::sink())?;
+        if skipped != length as u64 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "truncated BAM header"));
+        }
+        return Ok(());
+    }
+    /// Reads the next record into `self.record`, false at the end of file.
+    fn read_record(&mut self) -> Result<bool> {
+        let mut buf = [0u8; 4];
+        let mut len = 0;
+        while len < buf.len() {
+            let n = self.read.read(&mut buf[len..])?;
+            if n == 0 {
+                if len == 0 {
+                    return Ok(false);
+                }
+                return Err(Error::new(ErrorKind::UnexpectedEof, "truncated BAM record"));
+            }
+            len += n;
+        }
+        let block_size = i32::from_le_bytes(buf);
+        if block_size < BAM_FIXED_LENGTH as i32 {
+            return Err(invalid_data(format!(
+                "BAM record {} is too short",
+                self.record_count + 1
+            )));
+        }
+        self.record.resize(block_size as usize, 0);
+        self.read.read_exact(&mut self.record)?;
+        self.record_count += 1;
+        return Ok(true);
+    }
+    fn parse_record(record: &[u8], read: &mut AlignedRead) -> Option<()> {
+        let name_length = record[8] as usize;
+        let cigar_length = get_u16(record, 12) as usize * 4;
+        let seq_length = get_i32(record, 16);
+        if seq_length < 0 {
+            return None;
+        }
+        let seq_length = seq_length as usize;
+        let seq_start = BAM_FIXED_LENGTH + name_length + cigar_length;
+        let qual_start = seq_start + (seq_length + 1) / 2;
+        if record.len() < qual_start + seq_length || name_length == 0 {
+            return None;
+        }
+        read.mapping = Mapping {
+            flag: get_u16(record, 14),
+            mapq: record[9],
+        };
+        // The name is NUL terminated.
+        read.id.clear();
+        read.id
+            .extend_from_slice(&record[BAM_FIXED_LENGTH..(BAM_FIXED_LENGTH + name_length - 1)]);
+        read.sequence.clear();
+        for i in 0..seq_length {
+            let code = record[seq_start + i / 2] >> (4 * (1 - i % 2));
+            read.sequence.push(BAM_BASES[(code & 0xf) as usize]);
+        }
+        read.quality.clear();
+        let quality = &record[qual_start..(qual_start + seq_length)];
+        // 0xff is a missing quality string.
+        if quality.first() != Some(&0xff) {
+            read.quality
+                .extend(quality.iter().map(|q| q.saturating_add(33)));
+        }
+        return Some(());
+    }
+    pub fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        loop {
+            if !self.read_record()? {
+                return Ok(None);
+            }
+            if BamReader::<T>::parse_record(&self.record, &mut self.aligned_read).is_none() {
+                return Err(invalid_data(format!(
+                    "BAM record {} is malformed",
+                    self.record_count
+                )));
+            }
+            if self.aligned_read.is_primary() {
+                break;
+            }
+        }
+        self.aligned_read.restore_orientation();
+        return Ok(Some(self.aligned_read.as_sequence(self.mapped_only)));
+    }
+}
+impl<T: Read> SequenceReader for BamReader<T> {
+    fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        return BamReader::next_seq(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BamReader, SamReader};
+    use crate::trust_seq::utils::Mapping;
+
+    const SAM: &'static [u8] = b"@HD\tVN:1.6\tSO:unsorted\n\
+@SQ\tSN:chr1\tLN:1000\n\
+r1\t0\tchr1\t100\t60\t4M\t*\t0\t0\tACGT\tABCD\n\
+r2\t16\tchr1\t200\t30\t5M\t*\t0\t0\tAACGN\tABCDE\n\
+r2\t256\tchr1\t300\t0\t5M\t*\t0\t0\t*\t*\n\
+r3\t4\t*\t0\t0\t*\t*\t0\t0\tGGTA\t*\n";
+
+    fn bam_record(name: &str, flag: u16, mapq: u8, seq: &[u8], qual: &[u8]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.push(name.len() as u8 + 1);
+        data.push(mapq);
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&flag.to_le_bytes());
+        data.extend_from_slice(&(seq.len() as i32).to_le_bytes());
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.extend_from_slice(&(-1i32).to_le_bytes());
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+        data.push(0);
+        data.extend_from_slice(&((seq.len() as u32) << 4).to_le_bytes());
+        for pair in seq.chunks(2) {
+            let code = |c: u8| b"=ACMGRSVTWYHKDBN".iter().position(|b| *b == c).unwrap() as u8;
+            let low = if pair.len() > 1 { code(pair[1]) } else { 0 };
+            data.push(code(pair[0]) << 4 | low);
+        }
+        data.extend_from_slice(qual);
+        data.extend_from_slice(b"NMC\x00");
+        let mut record = (data.len() as i32).to_le_bytes().to_vec();
+        record.extend(data);
+        return record;
+    }
+
+    #[test]
+    fn test_sam_reader() {
+        let mut reader = SamReader::new(SAM);
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(
+            (&b"r1"[..], &b"ACGT"[..], &b"ABCD"[..]),
+            (seq.id, seq.sequence, seq.quality)
+        );
+        assert_eq!(Some(Mapping { flag: 0, mapq: 60 }), seq.mapping);
+        // Reverse strand: back to the orientation it was sequenced in.
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!((&b"NCGTT"[..], &b"EDCBA"[..]), (seq.sequence, seq.quality));
+        // The secondary alignment of r2 is skipped.
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(
+            (&b"r3"[..], &b"GGTA"[..], &b""[..]),
+            (seq.id, seq.sequence, seq.quality)
+        );
+        assert!(!seq.filtered);
+        assert!(reader.next_seq().unwrap().is_none());
+
+        let mut reader = SamReader::new(SAM);
+        reader.set_mapped_only(true);
+        let excluded: Vec<(bool, bool)> = (0..3)
+            .map(|_| {
+                let seq = reader.next_seq().unwrap().unwrap();
+                return (seq.excluded, seq.filtered);
+            })
+            .collect();
+        assert_eq!(
+            vec![(false, false), (false, false), (true, false)],
+            excluded
+        );
+
+        let mut reader = SamReader::new(&b"r1\t0\tchr1\n"[..]);
+        assert!(reader.next_seq().is_err());
+    }
+    #[test]
+    fn test_bam_reader() {
+        let mut bam = b"BAM\x01".to_vec();
+        let header = b"@HD\tVN:1.6\n";
+        bam.extend_from_slice(&(header.len() as i32).to_le_bytes());
+        bam.extend_from_slice(header);
+        bam.extend_from_slice(&1i32.to_le_bytes());
+        bam.extend_from_slice(&5i32.to_le_bytes());
+        bam.extend_from_slice(b"chr1\x00");
+        bam.extend_from_slice(&1000i32.to_le_bytes());
+        bam.extend(bam_record("r1", 0, 60, b"ACGTN", &[30, 31, 32, 33, 34]));
+        bam.extend(bam_record("r2", 16 | 0x800, 0, b"ACGT", &[30, 31, 32, 33]));
+        bam.extend(bam_record("r3", 16, 12, b"AACG", &[0xff; 4]));
+        bam.extend(bam_record("r4", 0, 0, b"=ACMGRSVTWYHKDBN", &[0xff; 16]));
+
+        let mut reader = BamReader::new(&bam[..]).unwrap();
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(
+            (&b"r1"[..], &b"ACGTN"[..], &b"?@ABC"[..]),
+            (seq.id, seq.sequence, seq.quality)
+        );
+        assert_eq!(Some(Mapping { flag: 0, mapq: 60 }), seq.mapping);
+        // r2 is a supplementary alignment.
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(
+            (&b"r3"[..], &b"CGTT"[..], &b""[..]),
+            (seq.id, seq.sequence, seq.quality)
+        );
+        let seq = reader.next_seq().unwrap().unwrap();
+        assert_eq!(&b"=ACMGRSVTWYHKDBN"[..], seq.sequence);
+        assert!(reader.next_seq().unwrap().is_none());
+
+        assert!(BamReader::new(&b"BAM\x02"[..]).is_err());
+        let truncated = &bam[..(bam.len() - 3)];
+        let mut reader = BamReader::new(truncated).unwrap();
+        reader.next_seq().unwrap();
+        reader.next_seq().unwrap();
+        assert!(reader.next_seq().is_err());
+    }
+}
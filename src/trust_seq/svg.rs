@@ -0,0 +1,497 @@
+use std::f64;
+use std::io::{Result, Write};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 600.0;
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 50.0;
+const BOTTOM: f64 = 70.0;
+const COLORS: [&'static str; 8] = [
+    "#dc0000", "#0000dc", "#00a000", "#000000", "#a000c8", "#ff8c00", "#00aaaa", "#808080",
+];
+
+pub fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    return escaped;
+}
+pub fn get_color(idx: usize) -> &'static str {
+    return COLORS[idx % COLORS.len()];
+}
+/// Picks a 1/2/5 x 10^n step that splits `range` into at most ~10 ticks.
+fn calc_tick_interval(range: f64) -> f64 {
+    if !(range > 0.0) || !range.is_finite() {
+        return 1.0;
+    }
+    let mut base = 10f64.powf((range / 10.0).log10().floor());
+    loop {
+        for division in [1.0, 2.0, 5.0].iter() {
+            let interval = base * division;
+            if range / interval <= 10.0 {
+                return interval;
+            }
+        }
+        base *= 10.0;
+    }
+}
+fn format_tick(value: f64) -> String {
+    if value == value.round() {
+        return format!("{}", value as i64);
+    }
+    let text = format!("{:.3}", value);
+    return text.trim_end_matches('0').to_string();
+}
+
+/// The plotting area shared by every chart: `count` categories along the
+/// x axis and a linear y axis from `y_min` to `y_max`.
+struct Frame {
+    count: usize,
+    y_min: f64,
+    y_max: f64,
+}
+impl Frame {
+    fn new(count: usize, y_min: f64, y_max: f64) -> Frame {
+        let y_max = if y_max > y_min { y_max } else { y_min + 1.0 };
+        return Frame {
+            count: count,
+            y_min: y_min,
+            y_max: y_max,
+        };
+    }
+    fn slot_width(&self) -> f64 {
+        return (WIDTH - LEFT - RIGHT) / (self.count.max(1) as f64);
+    }
+    fn x(&self, idx: usize) -> f64 {
+        return LEFT + self.slot_width() * (idx as f64 + 0.5);
+    }
+    fn y(&self, value: f64) -> f64 {
+        let v = value.max(self.y_min).min(self.y_max);
+        return HEIGHT - BOTTOM - (v - self.y_min) / (self.y_max - self.y_min) * (HEIGHT - TOP - BOTTOM);
+    }
+    fn write_header(&self, w: &mut Write, title: &str) -> Result<()> {
+        writeln!(
+            w,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"Arial, sans-serif\" font-size=\"12\">",
+            WIDTH, HEIGHT, WIDTH, HEIGHT
+        )?;
+        writeln!(
+            w,
+            "<rect x=\"0\" y=\"0\" width=\"{}\" height=\"{}\" fill=\"#ffffff\"/>",
+            WIDTH, HEIGHT
+        )?;
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"25\" text-anchor=\"middle\" font-size=\"16\">{}</text>",
+            WIDTH / 2.0,
+            escape_xml(title)
+        )?;
+        return Ok(());
+    }
+    fn write_axes(&self, w: &mut Write, x_labels: &[String], x_title: &str) -> Result<()> {
+        let bottom = HEIGHT - BOTTOM;
+        let interval = calc_tick_interval(self.y_max - self.y_min);
+        let mut tick = (self.y_min / interval).ceil() * interval;
+        while tick <= self.y_max + interval * 1e-9 {
+            let y = self.y(tick);
+            writeln!(
+                w,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#dddddd\"/>",
+                LEFT,
+                y,
+                WIDTH - RIGHT,
+                y
+            )?;
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                LEFT - 5.0,
+                y + 4.0,
+                format_tick(tick)
+            )?;
+            tick += interval;
+        }
+        // Only label as many categories as fit side by side.
+        let step = ((x_labels.len() as f64 * 45.0) / (WIDTH - LEFT - RIGHT)).ceil().max(1.0) as usize;
+        for (idx, label) in x_labels.iter().enumerate() {
+            if idx % step != 0 {
+                continue;
+            }
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                self.x(idx),
+                bottom + 18.0,
+                escape_xml(label)
+            )?;
+        }
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            (LEFT + WIDTH - RIGHT) / 2.0,
+            HEIGHT - 20.0,
+            escape_xml(x_title)
+        )?;
+        writeln!(
+            w,
+            "<polyline points=\"{:.1},{:.1} {:.1},{:.1} {:.1},{:.1}\" fill=\"none\" stroke=\"#000000\"/>",
+            LEFT,
+            TOP,
+            LEFT,
+            bottom,
+            WIDTH - RIGHT,
+            bottom
+        )?;
+        return Ok(());
+    }
+    fn write_legend(&self, w: &mut Write, names: &[String]) -> Result<()> {
+        let longest = names.iter().fold(0, |acc, n| acc.max(n.chars().count()));
+        let width = longest as f64 * 7.0 + 35.0;
+        let x = WIDTH - RIGHT - width - 5.0;
+        writeln!(
+            w,
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#ffffff\" fill-opacity=\"0.8\" stroke=\"#999999\"/>",
+            x,
+            TOP + 5.0,
+            width,
+            names.len() as f64 * 18.0 + 6.0
+        )?;
+        for (idx, name) in names.iter().enumerate() {
+            let y = TOP + 20.0 + idx as f64 * 18.0;
+            writeln!(
+                w,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"2\"/>",
+                x + 5.0,
+                y - 4.0,
+                x + 25.0,
+                y - 4.0,
+                get_color(idx)
+            )?;
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                x + 30.0,
+                y,
+                escape_xml(name)
+            )?;
+        }
+        return Ok(());
+    }
+}
+
+/// One or more series drawn as lines over the same categories.
+pub struct LineGraph<'a> {
+    pub title: String,
+    pub x_title: &'a str,
+    pub x_labels: Vec<String>,
+    pub series_names: Vec<String>,
+    pub series: Vec<Vec<f64>>,
+    pub y_min: f64,
+    pub y_max: f64,
+}
+impl<'a> LineGraph<'a> {
+    pub fn write_svg(&self, w: &mut Write) -> Result<()> {
+        let frame = Frame::new(self.x_labels.len(), self.y_min, self.y_max);
+        frame.write_header(w, &self.title)?;
+        frame.write_axes(w, &self.x_labels, self.x_title)?;
+        for (idx, values) in self.series.iter().enumerate() {
+            let mut points = String::new();
+            for (pos, value) in values.iter().enumerate() {
+                if value.is_finite() {
+                    points.push_str(&format!("{:.1},{:.1} ", frame.x(pos), frame.y(*value)));
+                }
+            }
+            writeln!(
+                w,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+                points.trim_end(),
+                get_color(idx)
+            )?;
+        }
+        if !self.series_names.is_empty() {
+            frame.write_legend(w, &self.series_names)?;
+        }
+        writeln!(w, "</svg>")?;
+        return Ok(());
+    }
+}
+
+/// The FastQC style per base quality plot: a box per position spanning the
+/// quartiles, whiskers at the 10th/90th percentiles, a median tick and a
+/// line through the means, over green/orange/red quality bands.
+pub struct QualityBoxPlot {
+    pub title: String,
+    pub x_labels: Vec<String>,
+    pub means: Vec<f64>,
+    pub medians: Vec<f64>,
+    pub lower_quartiles: Vec<f64>,
+    pub upper_quartiles: Vec<f64>,
+    pub lowest: Vec<f64>,
+    pub highest: Vec<f64>,
+    pub y_max: f64,
+}
+impl QualityBoxPlot {
+    pub fn write_svg(&self, w: &mut Write) -> Result<()> {
+        let frame = Frame::new(self.x_labels.len(), 0.0, self.y_max);
+        frame.write_header(w, &self.title)?;
+        let bands = [
+            (0.0, 20.0, "#e6afaf"),
+            (20.0, 28.0, "#e6d7af"),
+            (28.0, self.y_max, "#afe6af"),
+        ];
+        for &(low, high, color) in bands.iter() {
+            if low >= self.y_max {
+                continue;
+            }
+            let top = frame.y(high);
+            writeln!(
+                w,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+                LEFT,
+                top,
+                WIDTH - LEFT - RIGHT,
+                frame.y(low) - top,
+                color
+            )?;
+        }
+        frame.write_axes(w, &self.x_labels, "Position in read (bp)")?;
+        let half = (frame.slot_width() * 0.35).max(1.0);
+        for idx in 0..self.x_labels.len() {
+            if !self.medians[idx].is_finite() {
+                continue;
+            }
+            let x = frame.x(idx);
+            writeln!(
+                w,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#000000\"/>",
+                x,
+                frame.y(self.lowest[idx]),
+                x,
+                frame.y(self.highest[idx])
+            )?;
+            let top = frame.y(self.upper_quartiles[idx]);
+            writeln!(
+                w,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"#ffff00\" stroke=\"#000000\"/>",
+                x - half,
+                top,
+                half * 2.0,
+                frame.y(self.lower_quartiles[idx]) - top
+            )?;
+            writeln!(
+                w,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#dc0000\" stroke-width=\"2\"/>",
+                x - half,
+                frame.y(self.medians[idx]),
+                x + half,
+                frame.y(self.medians[idx])
+            )?;
+        }
+        let mut points = String::new();
+        for (idx, mean) in self.means.iter().enumerate() {
+            if mean.is_finite() {
+                points.push_str(&format!("{:.1},{:.1} ", frame.x(idx), frame.y(*mean)));
+            }
+        }
+        writeln!(
+            w,
+            "<polyline points=\"{}\" fill=\"none\" stroke=\"#0000dc\" stroke-width=\"2\"/>",
+            points.trim_end()
+        )?;
+        writeln!(w, "</svg>")?;
+        return Ok(());
+    }
+}
+
+/// A grid of cells coloured from blue (`min_value` and above) to red
+/// (`max_value` and above), one row per `y_labels` entry.
+pub struct HeatMap<'a> {
+    pub title: String,
+    pub x_title: &'a str,
+    pub x_labels: Vec<String>,
+    pub y_labels: Vec<String>,
+    pub values: Vec<Vec<f64>>,
+    pub min_value: f64,
+    pub max_value: f64,
+}
+fn get_heat_color(ratio: f64) -> String {
+    let r = ratio.max(0.0).min(1.0);
+    let (red, green, blue) = if r < 0.5 {
+        (0.0, r * 2.0, 1.0 - r * 2.0)
+    } else {
+        ((r - 0.5) * 2.0, 1.0 - (r - 0.5) * 2.0, 0.0)
+    };
+    return format!(
+        "#{:02x}{:02x}{:02x}",
+        (red * 255.0) as u8,
+        (green * 255.0) as u8,
+        (blue * 255.0) as u8
+    );
+}
+impl<'a> HeatMap<'a> {
+    pub fn write_svg(&self, w: &mut Write) -> Result<()> {
+        let frame = Frame::new(self.x_labels.len(), 0.0, self.y_labels.len() as f64);
+        frame.write_header(w, &self.title)?;
+        let cell_height = (HEIGHT - TOP - BOTTOM) / (self.y_labels.len().max(1) as f64);
+        let range = self.max_value - self.min_value;
+        for (row, values) in self.values.iter().enumerate() {
+            let y = TOP + cell_height * row as f64;
+            for (col, value) in values.iter().enumerate() {
+                let ratio = if range > 0.0 {
+                    (value - self.min_value) / range
+                } else {
+                    0.0
+                };
+                writeln!(
+                    w,
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>",
+                    frame.x(col) - frame.slot_width() / 2.0,
+                    y,
+                    frame.slot_width(),
+                    cell_height,
+                    get_heat_color(ratio)
+                )?;
+            }
+        }
+        let step = ((self.y_labels.len() as f64 * 14.0) / (HEIGHT - TOP - BOTTOM)).ceil().max(1.0) as usize;
+        for (row, label) in self.y_labels.iter().enumerate() {
+            if row % step != 0 {
+                continue;
+            }
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+                LEFT - 5.0,
+                TOP + cell_height * (row as f64 + 0.5) + 4.0,
+                escape_xml(label)
+            )?;
+        }
+        let frame = Frame::new(self.x_labels.len(), 0.0, 0.0);
+        let bottom = HEIGHT - BOTTOM;
+        let step = ((self.x_labels.len() as f64 * 45.0) / (WIDTH - LEFT - RIGHT)).ceil().max(1.0) as usize;
+        for (idx, label) in self.x_labels.iter().enumerate() {
+            if idx % step == 0 {
+                writeln!(
+                    w,
+                    "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+                    frame.x(idx),
+                    bottom + 18.0,
+                    escape_xml(label)
+                )?;
+            }
+        }
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            (LEFT + WIDTH - RIGHT) / 2.0,
+            HEIGHT - 20.0,
+            escape_xml(self.x_title)
+        )?;
+        writeln!(w, "</svg>")?;
+        return Ok(());
+    }
+}
+
+/// Horizontal bars, one per label, scaled against the largest value.
+pub struct BarGraph {
+    pub title: String,
+    pub value_title: String,
+    pub labels: Vec<String>,
+    pub values: Vec<f64>,
+}
+impl BarGraph {
+    pub fn write_svg(&self, w: &mut Write) -> Result<()> {
+        let frame = Frame::new(0, 0.0, 0.0);
+        frame.write_header(w, &self.title)?;
+        let max_value = self.values.iter().fold(0.0f64, |acc, v| acc.max(*v));
+        let left = LEFT + 60.0;
+        let bar_width = WIDTH - left - RIGHT - 60.0;
+        let bar_height = ((HEIGHT - TOP - BOTTOM) / (self.labels.len().max(1) as f64)).min(30.0);
+        for (idx, label) in self.labels.iter().enumerate() {
+            let y = TOP + bar_height * idx as f64;
+            let len = if max_value > 0.0 {
+                self.values[idx] / max_value * bar_width
+            } else {
+                0.0
+            };
+            writeln!(
+                w,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"/>",
+                left,
+                y + 2.0,
+                len,
+                bar_height - 4.0,
+                get_color(idx)
+            )?;
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" font-family=\"monospace\">{}</text>",
+                left - 5.0,
+                y + bar_height / 2.0 + 4.0,
+                escape_xml(label)
+            )?;
+            writeln!(
+                w,
+                "<text x=\"{:.1}\" y=\"{:.1}\">{}</text>",
+                left + len + 5.0,
+                y + bar_height / 2.0 + 4.0,
+                format_tick(self.values[idx])
+            )?;
+        }
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            left + bar_width / 2.0,
+            HEIGHT - 20.0,
+            escape_xml(&self.value_title)
+        )?;
+        writeln!(w, "</svg>")?;
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calc_tick_interval() {
+        assert_eq!(5.0, calc_tick_interval(40.0));
+        assert_eq!(10.0, calc_tick_interval(100.0));
+        assert_eq!(0.2, calc_tick_interval(1.5));
+        assert_eq!(1.0, calc_tick_interval(0.0));
+    }
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!("Illumina Small RNA 3&#39; Adapter", escape_xml("Illumina Small RNA 3' Adapter"));
+        assert_eq!("&lt;a &amp; b&gt;", escape_xml("<a & b>"));
+    }
+    #[test]
+    fn test_line_graph() {
+        let graph = LineGraph {
+            title: "Test".to_string(),
+            x_title: "Position",
+            x_labels: vec!["1".to_string(), "2".to_string(), "3-4".to_string()],
+            series_names: vec!["A".to_string()],
+            series: vec![vec![1.0, f64::NAN, 3.0]],
+            y_min: 0.0,
+            y_max: 4.0,
+        };
+        let mut buf: Vec<u8> = Vec::new();
+        graph.write_svg(&mut buf).unwrap();
+        let svg = String::from_utf8(buf).unwrap();
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert!(svg.contains("3-4"));
+        assert!(!svg.contains("NaN"));
+    }
+}
@@ -1,12 +1,27 @@
+use super::adapter::Adapter;
+use super::adapter_list::ADAPTER_LIST;
+use super::archive::BLOCK_SIZE;
+use super::binning::QualityBins;
+use super::compression::Compression;
+use super::contaminant::Contaminant;
+use super::contaminant_list::CONTAMINANT_LIST;
 use super::group::GroupType;
 use super::module_config::ModuleConfig;
+use super::qc::{
+    Correction, CustomModules, QCModule, MAX_KMER_SIZE, MIN_KMER_SIZE, MODULE_NAMES,
+    OBSERVATION_CUTOFF,
+};
+use super::utils::{is_stdin, FileFormat};
 use getopts::{Fail, Options};
 use serde_json;
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
 use std::io::ErrorKind;
+use std::ops::Range;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::vec::Vec;
 #[derive(Debug)]
 pub enum TrustSeqErr {
@@ -30,19 +45,64 @@ impl From<Fail> for TrustSeqErr {
     }
 }
 
+/// What is done with the files: the QC, or a subcommand given before the
+/// options.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Qc,
+    /// `compress IN [OUT]` writes a trust_seq archive of a FASTQ file.
+    Compress,
+    /// `decompress IN [OUT]` writes the FASTQ file back.
+    Decompress,
+    /// `bin IN [OUT]` writes a FASTQ file with binned quality scores.
+    Bin,
+    /// `view IN [OUT]` writes some or all records of an archive.
+    View,
+}
+
 #[derive(Clone, Debug)]
 pub struct TrustSeqConfig {
+    pub command: Command,
     pub group_type: GroupType,
     pub quiet: bool,
     pub show_version: bool,
     pub kmer_size: Option<u32>,
+    pub kmer_exhaustive: bool,
     pub output_dir: String,
+    pub exit_status: bool,
+    pub threads: usize,
+    pub paired: bool,
+    pub interleaved: bool,
+    pub mismatched_pairs: bool,
+    pub exit_modules: Option<Vec<String>>,
     pub casava: bool,
     pub nano: bool,
     pub nofilter: bool,
+    pub mapped_only: bool,
+    pub validate: bool,
+    /// The declared format and compression of the inputs, in place of
+    /// their file extensions and leading bytes.
+    pub format: Option<FileFormat>,
+    pub compression: Option<Compression>,
+    /// The bins quality scores are put in by `compress` and `bin`.
+    pub quality_bins: Option<QualityBins>,
+    /// The records of an archive block.
+    pub block_size: u64,
+    /// The records `view` writes, by their numbers from 0, or the reads
+    /// it looks up by name.
+    pub region: Option<Range<u64>>,
+    pub read_names: Vec<String>,
     pub contaminant_file: Option<String>,
     pub adapter_file: Option<String>,
+    pub merge_builtin: bool,
+    pub contaminants: Vec<Contaminant>,
+    pub adapters: Vec<Adapter>,
     pub module_config: ModuleConfig,
+    /// The distinct sequences the duplication and overrepresented sequence
+    /// modules track.
+    pub observation_cutoff: usize,
+    /// Modules added by library users, run after the built-in ones.
+    pub custom_modules: CustomModules,
     pub files: Vec<String>,
 }
 #[test]
@@ -55,6 +115,169 @@ fn test_get_fastqc_config() {
     let result = TrustSeqConfig::get_fastqc_config(&m);
     println!("{:?}", result);
 }
+#[test]
+fn test_exit_modules() {
+    let m = vec![
+        "trust_seq".to_string(),
+        "--exit_modules".to_string(),
+        "Basic Statistics, Adapter Content".to_string(),
+        "test.fastq".to_string(),
+    ];
+    let config = TrustSeqConfig::get_fastqc_config(&m).unwrap();
+    assert!(config.exit_status);
+    assert_eq!(
+        Some(vec![
+            "Basic Statistics".to_string(),
+            "Adapter Content".to_string()
+        ]),
+        config.exit_modules
+    );
+    let m = vec![
+        "trust_seq".to_string(),
+        "--exit_modules".to_string(),
+        "basic statistics,No such module".to_string(),
+        "test.fastq".to_string(),
+    ];
+    match TrustSeqConfig::get_fastqc_config(&m) {
+        Err(TrustSeqErr::Io(e)) => assert_eq!(
+            "unknown module in --exit_modules: No such module",
+            e.to_string()
+        ),
+        _ => panic!("expected an unknown module error"),
+    }
+}
+#[test]
+fn test_paired_threads() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let config =
+        TrustSeqConfig::get_fastqc_config(&args(&["trust_seq", "--paired", "r1.fq", "r2.fq"]))
+            .unwrap();
+    assert!(config.paired);
+    assert!(TrustSeqConfig::get_fastqc_config(&args(&[
+        "trust_seq",
+        "--paired",
+        "-t",
+        "4",
+        "r1.fq",
+        "r2.fq"
+    ]))
+    .is_err());
+}
+#[test]
+fn test_command() {
+    let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let config = TrustSeqConfig::get_fastqc_config(&args(&[
+        "trust_seq",
+        "compress",
+        "-f",
+        "fastq.gz",
+        "-",
+        "out.tsq",
+    ]))
+    .unwrap();
+    assert_eq!(Command::Compress, config.command);
+    assert_eq!(Some(Compression::Gzip), config.compression);
+    assert_eq!(vec!["-".to_string(), "out.tsq".to_string()], config.files);
+    let config =
+        TrustSeqConfig::get_fastqc_config(&args(&["trust_seq", "decompress", "in.tsq"])).unwrap();
+    assert_eq!(Command::Decompress, config.command);
+    assert!(
+        TrustSeqConfig::get_fastqc_config(&args(&["trust_seq", "compress", "a", "b", "c"]))
+            .is_err()
+    );
+    let config = TrustSeqConfig::get_fastqc_config(&args(&["trust_seq", "a", "b", "c"])).unwrap();
+    assert_eq!(Command::Qc, config.command);
+    let config = TrustSeqConfig::get_fastqc_config(&args(&["trust_seq", "bin", "in.fq"])).unwrap();
+    assert_eq!(Command::Bin, config.command);
+    assert!(config.quality_bins.is_some());
+    let config = TrustSeqConfig::get_fastqc_config(&args(&[
+        "trust_seq",
+        "compress",
+        "--bin_qualities",
+        "0-19:10,20-:30",
+        "in.fq",
+    ]))
+    .unwrap();
+    assert!(config.quality_bins.is_some());
+    assert!(TrustSeqConfig::get_fastqc_config(&args(&[
+        "trust_seq",
+        "--bin_qualities",
+        "illumina",
+        "in.fq"
+    ]))
+    .is_err());
+    let view = |a: &[&str]| {
+        let mut all = vec!["trust_seq", "view", "in.tsq"];
+        all.extend_from_slice(a);
+        return TrustSeqConfig::get_fastqc_config(&args(&all));
+    };
+    assert_eq!(
+        Some(9999999..11000000),
+        view(&["--region", "10000000-11000000"]).unwrap().region
+    );
+    assert_eq!(Some(4..5), view(&["--region", "5"]).unwrap().region);
+    assert_eq!(
+        Some(4..u64::max_value()),
+        view(&["--region", "5-"]).unwrap().region
+    );
+    for region in &["0-5", "5-4", "a-b", "-5"] {
+        assert!(view(&["--region", region]).is_err());
+    }
+    let config = view(&["--name", "r1", "--name", "r2"]).unwrap();
+    assert_eq!(vec!["r1".to_string(), "r2".to_string()], config.read_names);
+    assert!(view(&["--name", "r1", "--region", "1-2"]).is_err());
+}
+#[test]
+fn test_kmer_size() {
+    let args = |k: &str| {
+        vec![
+            "trust_seq".to_string(),
+            "-k".to_string(),
+            k.to_string(),
+            "test.fastq".to_string(),
+        ]
+    };
+    assert_eq!(
+        Some(5),
+        TrustSeqConfig::get_fastqc_config(&args("5"))
+            .unwrap()
+            .kmer_size
+    );
+    assert!(TrustSeqConfig::get_fastqc_config(&args("1")).is_err());
+    assert!(TrustSeqConfig::get_fastqc_config(&args("11")).is_err());
+}
+/// Loads a contaminant or adapter file, naming the file in any error.
+fn load_list<T, F>(path: &str, load: F) -> Result<Vec<T>, TrustSeqErr>
+where
+    F: Fn(BufReader<File>) -> io::Result<Vec<T>>,
+{
+    let f = File::open(path)?;
+    return match load(BufReader::new(f)) {
+        Ok(list) => Ok(list),
+        Err(e) => Err(TrustSeqErr::Io(io::Error::new(
+            e.kind(),
+            format!("{}: {}", path, e),
+        ))),
+    };
+}
+/// Parses a `--region` of records counted from 1, `START-END`, `START-`
+/// or a single record, into the range of their numbers from 0.
+fn parse_region(region: &str) -> Option<Range<u64>> {
+    let (start, end) = match region.find('-') {
+        Some(i) => (&region[..i], &region[(i + 1)..]),
+        None => (region, region),
+    };
+    let start = u64::from_str(start).ok()?;
+    let end = if end.is_empty() {
+        u64::max_value()
+    } else {
+        u64::from_str(end).ok()?
+    };
+    if start == 0 || end < start {
+        return None;
+    }
+    return Some((start - 1)..end);
+}
 impl TrustSeqConfig {
     pub fn get_fastqc_config(args: &Vec<String>) -> Result<TrustSeqConfig, TrustSeqErr> {
         let mut opts = Options::new();
@@ -67,8 +290,125 @@ impl TrustSeqConfig {
         );
         opts.optopt("a", "adapter_file", "adapter file path", "ADAPTER_FILE");
         opts.optopt("l", "limit_file", "limit file path", "LIMIT_FILE");
+        opts.optflag(
+            "",
+            "merge_builtin",
+            "use the -c/-a lists in addition to the built-in contaminants and adapters",
+        );
+        opts.optopt(
+            "o",
+            "outdir",
+            "directory the <sample>_fastqc report folders are created in",
+            "OUTPUT_DIR",
+        );
+        opts.optopt(
+            "t",
+            "threads",
+            "number of worker threads the reads are processed on (only 1 with --paired)",
+            "THREADS",
+        );
+        opts.optopt(
+            "k",
+            "kmers",
+            "length of the k-mers counted by the Kmer Content module, 2 to 10 (default 7)",
+            "KMER_SIZE",
+        );
+        opts.optopt(
+            "f",
+            "format",
+            "format of the input files, fastq, fasta, sam or bam, with .gz if gzipped (e.g. fastq.gz), for pipes and unusual file names",
+            "FORMAT",
+        );
+        opts.optflag(
+            "",
+            "kmer_exhaustive",
+            "count the k-mers of every read instead of 1 read in 50",
+        );
+        opts.optflag(
+            "",
+            "casava",
+            "the files come from raw CASAVA output: leave out the reads flagged as filtered",
+        );
+        opts.optflag(
+            "",
+            "nofilter",
+            "with --casava, keep the reads flagged as filtered in the analysis",
+        );
+        opts.optflag(
+            "",
+            "mapped_only",
+            "for SAM and BAM files, leave out the unmapped reads (the mapping module still counts them)",
+        );
+        opts.optflag(
+            "",
+            "nano",
+            "long read mode: read length, mean read quality and yield over time modules, logarithmic position groups, no per tile or duplication modules",
+        );
+        opts.optflag(
+            "",
+            "paired",
+            "treat the files as R1/R2 pairs (R1_FILE R2_FILE ...) and add pair level modules",
+        );
+        opts.optflag(
+            "",
+            "interleaved",
+            "like --paired, but each file holds both mates of every pair one after the other",
+        );
+        opts.optflag(
+            "",
+            "mismatched_pairs",
+            "with --paired, carry on when the read names of the mates differ (the pair name module counts them)",
+        );
+        opts.optflag(
+            "",
+            "validate",
+            "only check that the files are well formed, reporting every problem found, and exit with 1 if any",
+        );
+        opts.optflag(
+            "",
+            "exit_status",
+            "exit with 2 on WARN and 3 on FAIL for the worst module of any file",
+        );
+        opts.optopt(
+            "",
+            "exit_modules",
+            "comma separated module names the exit status is taken from (implies --exit_status)",
+            "MODULES",
+        );
+        opts.optopt(
+            "",
+            "bin_qualities",
+            "with compress or bin, put the quality scores in bins: illumina (the default of bin) or Phred ranges and the score of each, such as 0-9:6,10-29:20,30-:37",
+            "BINS",
+        );
+        opts.optopt(
+            "",
+            "block_size",
+            "with compress, the records of each archive block, which are decoded together",
+            "RECORDS",
+        );
+        opts.optopt(
+            "",
+            "region",
+            "with view, write records START to END, counted from 1, or from START to the end for START-",
+            "START-END",
+        );
+        opts.optmulti(
+            "",
+            "name",
+            "with view, write the reads with this read ID or whole name",
+            "NAME",
+        );
         let mut config: TrustSeqConfig = TrustSeqConfig::new();
-        let matches = opts.parse(&args[1..])?;
+        let (command, args) = match args.get(1).map(|a| &a[..]) {
+            Some("compress") => (Command::Compress, &args[2..]),
+            Some("decompress") => (Command::Decompress, &args[2..]),
+            Some("bin") => (Command::Bin, &args[2..]),
+            Some("view") => (Command::View, &args[2..]),
+            _ => (Command::Qc, &args[1..]),
+        };
+        config.command = command;
+        let matches = opts.parse(args)?;
         if let Some(c_path) = matches.opt_str("c") {
             if !Path::new(&c_path).is_file() {
                 return Err(TrustSeqErr::Io(io::Error::new(
@@ -87,32 +427,241 @@ impl TrustSeqConfig {
             }
             config.adapter_file = Some(a_path);
         }
+        if let Some(o_path) = matches.opt_str("o") {
+            if !Path::new(&o_path).is_dir() {
+                return Err(TrustSeqErr::Io(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("{} is not a directory!", o_path),
+                )));
+            }
+            config.output_dir = o_path;
+        }
+        if let Some(threads) = matches.opt_str("t") {
+            config.threads = match usize::from_str(&threads) {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("{} is not a valid number of threads!", threads),
+                    )));
+                }
+            };
+        }
+        if let Some(kmers) = matches.opt_str("k") {
+            config.kmer_size = match u32::from_str(&kmers) {
+                Ok(k) if MIN_KMER_SIZE as u32 <= k && k <= MAX_KMER_SIZE as u32 => Some(k),
+                _ => {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{} is not a valid k-mer size! It must be {} to {}.",
+                            kmers, MIN_KMER_SIZE, MAX_KMER_SIZE
+                        ),
+                    )));
+                }
+            };
+        }
+        if let Some(format) = matches.opt_str("f") {
+            let (name, gzipped) = if format.ends_with(".gz") {
+                (&format[..(format.len() - 3)], true)
+            } else {
+                (&format[..], false)
+            };
+            config.format = match FileFormat::from_name(name) {
+                Some(f) => Some(f),
+                None => {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{} is not a known format! Use fastq, fasta, sam or bam.",
+                            format
+                        ),
+                    )));
+                }
+            };
+            config.compression = Some(if config.format == Some(FileFormat::Bam) {
+                Compression::Bgzf
+            } else if gzipped {
+                Compression::Gzip
+            } else {
+                Compression::None
+            });
+        }
+        config.kmer_exhaustive = matches.opt_present("kmer_exhaustive");
+        config.nano = matches.opt_present("nano");
+        if config.nano {
+            config.group_type = GroupType::Logarithmic;
+        }
+        config.casava = matches.opt_present("casava");
+        config.nofilter = matches.opt_present("nofilter");
+        config.mapped_only = matches.opt_present("mapped_only");
+        if let Some(spec) = matches.opt_str("bin_qualities") {
+            if command != Command::Compress && command != Command::Bin {
+                return Err(TrustSeqErr::Io(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("--bin_qualities is only used by compress and bin"),
+                )));
+            }
+            config.quality_bins = Some(QualityBins::from_spec(&spec)?);
+        } else if command == Command::Bin {
+            config.quality_bins = Some(QualityBins::illumina());
+        }
+        if let Some(block_size) = matches.opt_str("block_size") {
+            config.block_size = match u64::from_str(&block_size) {
+                Ok(n) if n > 0 && command == Command::Compress => n,
+                _ => {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("--block_size takes a number of records, with compress"),
+                    )));
+                }
+            };
+        }
+        if let Some(region) = matches.opt_str("region") {
+            config.region = match parse_region(&region) {
+                Some(r) if command == Command::View => Some(r),
+                _ => {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("--region takes records START-END, with view"),
+                    )));
+                }
+            };
+        }
+        config.read_names = matches.opt_strs("name");
+        if !config.read_names.is_empty() && (command != Command::View || config.region.is_some()) {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("--name is used by view, in place of --region"),
+            )));
+        }
+        config.validate = matches.opt_present("validate");
+        config.exit_status = matches.opt_present("exit_status");
+        if let Some(modules) = matches.opt_str("exit_modules") {
+            let modules: Vec<String> = modules
+                .split(',')
+                .map(|m| m.trim().to_string())
+                .filter(|m| !m.is_empty())
+                .collect();
+            for name in &modules {
+                if !MODULE_NAMES.iter().any(|m| m.eq_ignore_ascii_case(name)) {
+                    return Err(TrustSeqErr::Io(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("unknown module in --exit_modules: {}", name),
+                    )));
+                }
+            }
+            config.exit_status = true;
+            config.exit_modules = Some(modules);
+        }
+        config.merge_builtin = matches.opt_present("merge_builtin");
+        if let Some(ref c_path) = config.contaminant_file {
+            let contaminants = load_list(c_path, Contaminant::load_contaminants)?;
+            if !config.merge_builtin {
+                config.contaminants.clear();
+            }
+            config.contaminants.extend(contaminants);
+        }
+        if let Some(ref a_path) = config.adapter_file {
+            let adapters = load_list(a_path, Adapter::load_adapters)?;
+            if !config.merge_builtin {
+                config.adapters.clear();
+            }
+            config.adapters.extend(adapters);
+        }
         if let Some(l_path) = matches.opt_str("l") {
             let f = File::open(l_path)?;
             config.module_config.load(BufReader::new(f))?;
         }
+        Correction::from_config(&config.module_config)?;
         if matches.free.len() <= 0 {
             return Err(TrustSeqErr::Io(io::Error::new(
                 ErrorKind::NotFound,
                 format!("missing orepand"),
             )));
         }
+        config.interleaved = matches.opt_present("interleaved");
+        config.paired = config.interleaved || matches.opt_present("paired");
+        if config.paired && !config.interleaved && matches.free.len() % 2 != 0 {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("--paired needs an R2 file for every R1 file"),
+            )));
+        }
+        if config.paired && config.threads > 1 {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("--paired runs on one thread, --threads can not be used with it"),
+            )));
+        }
+        config.mismatched_pairs = matches.opt_present("mismatched_pairs");
+        if matches.free.iter().filter(|f| is_stdin(f)).count() > 1 {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("the standard input can only be read once"),
+            )));
+        }
+        if command != Command::Qc && matches.free.len() > 2 {
+            return Err(TrustSeqErr::Io(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "compress, decompress, bin and view take an input and an optional output file"
+                ),
+            )));
+        }
         config.files = matches.free;
         return Ok(config);
     }
+    /// Whether the reads CASAVA flagged as poor quality are left out.
+    pub fn filter_casava(&self) -> bool {
+        return self.casava && !self.nofilter;
+    }
+    /// Adds a module to the ones `create_qcmodules` creates, so its
+    /// reports are written with the others. `limits` holds the default
+    /// thresholds of the module in the limits file format, and values
+    /// already loaded from a limits file are kept.
+    pub fn register_module<F>(&mut self, factory: F, limits: &str) -> Result<(), TrustSeqErr>
+    where
+        F: Fn(&Arc<TrustSeqConfig>) -> Box<QCModule> + Send + Sync + 'static,
+    {
+        self.module_config.load_defaults(limits.as_bytes())?;
+        self.custom_modules.factories.push(Arc::new(factory));
+        return Ok(());
+    }
     pub fn new() -> TrustSeqConfig {
         return TrustSeqConfig {
+            command: Command::Qc,
             group_type: GroupType::Linear,
             quiet: false,
             show_version: false,
             kmer_size: None,
+            kmer_exhaustive: false,
             output_dir: ".".to_string(),
+            exit_status: false,
+            threads: 1,
+            paired: false,
+            interleaved: false,
+            mismatched_pairs: false,
+            exit_modules: None,
             casava: false,
             nano: false,
             nofilter: false,
+            mapped_only: false,
+            validate: false,
+            format: None,
+            compression: None,
+            quality_bins: None,
+            block_size: BLOCK_SIZE,
+            region: None,
+            read_names: Vec::new(),
             contaminant_file: None,
             adapter_file: None,
+            merge_builtin: false,
+            contaminants: Contaminant::load_contaminants(CONTAMINANT_LIST.as_bytes()).unwrap(),
+            adapters: Adapter::load_adapters(ADAPTER_LIST.as_bytes()).unwrap(),
             module_config: ModuleConfig::new(),
+            observation_cutoff: OBSERVATION_CUTOFF,
+            custom_modules: CustomModules::default(),
             files: Vec::new(),
         };
     }
@@ -1,6 +1,52 @@
+use crate::trust_seq::qc::get_pair_name;
+use std::error;
+use std::fmt;
+use std::io::BufRead;
+use std::io::Error;
+use std::io::ErrorKind;
 use std::io::Read;
 use std::io::Result;
 use std::option::Option;
+use std::path::Path;
+
+const SAMPLE_NAME_SUFFIXES: [&'static str; 8] = [
+    ".gz", ".bz2", ".txt", ".fastq", ".fq", ".csfastq", ".sam", ".bam",
+];
+const FASTA_SUFFIXES: [&'static str; 4] = [".fasta", ".fa", ".fna", ".fas"];
+
+/// The read file formats, see `FileFormat::from_path`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FileFormat {
+    Fastq,
+    Fasta,
+    Sam,
+    Bam,
+}
+impl FileFormat {
+    pub fn from_name(name: &str) -> Option<FileFormat> {
+        return match name {
+            "fastq" | "fq" => Some(FileFormat::Fastq),
+            "fasta" | "fa" => Some(FileFormat::Fasta),
+            "sam" => Some(FileFormat::Sam),
+            "bam" => Some(FileFormat::Bam),
+            _ => None,
+        };
+    }
+    /// Tells the format from the file extension, FASTQ unless it is .sam,
+    /// .bam or a FASTA one.
+    pub fn from_path(path: &str) -> FileFormat {
+        let name = path.to_lowercase();
+        let name = name.trim_end_matches(".gz");
+        if name.ends_with(".bam") {
+            return FileFormat::Bam;
+        } else if name.ends_with(".sam") {
+            return FileFormat::Sam;
+        } else if FASTA_SUFFIXES.iter().any(|s| name.ends_with(s)) {
+            return FileFormat::Fasta;
+        }
+        return FileFormat::Fastq;
+    }
+}
 
 pub fn split_by_space(line: &str) -> Vec<&str> {
     let mut vals: Vec<&str> = Vec::new();
@@ -23,6 +69,107 @@ pub fn split_by_space(line: &str) -> Vec<&str> {
     return vals;
 }
 
+/// Whether `path` stands for the standard input.
+pub fn is_stdin(path: &str) -> bool {
+    return path == "-" || path == "stdin";
+}
+
+/// Derives the report name of an input file the way FastQC does: the
+/// directory and the usual sequence file extensions are stripped.
+pub fn get_sample_name(path: &str) -> String {
+    if is_stdin(path) {
+        return "stdin".to_string();
+    }
+    let mut name = match Path::new(path).file_name() {
+        Some(n) => n.to_string_lossy().to_string(),
+        None => path.to_string(),
+    };
+    for suffix in SAMPLE_NAME_SUFFIXES.iter() {
+        if name.ends_with(suffix) && name.len() > suffix.len() {
+            let len = name.len() - suffix.len();
+            name.truncate(len);
+        }
+    }
+    return name;
+}
+
+/// Reads a FastQC style list of `name[tab]sequence` lines, the format of
+/// the contaminant and adapter files. Sequences are upper cased; malformed
+/// lines are reported with their line number.
+pub fn read_sequence_list<R: BufRead>(reader: R) -> Result<Vec<(String, String)>> {
+    let mut entries = Vec::new();
+    for (idx, rslt) in reader.lines().enumerate() {
+        let line = rslt?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |msg: String| Error::new(ErrorKind::InvalidData, format!("line {}: {}", idx + 1, msg));
+        let (name, sequence) = match (line.find('\t'), line.rfind('\t')) {
+            (Some(first), Some(last)) => (line[..first].trim(), line[(last + 1)..].trim()),
+            _ => {
+                return Err(invalid(
+                    "expected a name and a sequence separated by a tab".to_string(),
+                ));
+            }
+        };
+        if name.is_empty() {
+            return Err(invalid("the name is empty".to_string()));
+        }
+        if sequence.is_empty() {
+            return Err(invalid(format!("the sequence of '{}' is empty", name)));
+        }
+        let sequence = sequence.to_uppercase();
+        if let Some(c) = sequence.chars().find(|c| !"ACGTN".contains(*c)) {
+            return Err(invalid(format!("'{}' in the sequence of '{}' is not a base", c, name)));
+        }
+        entries.push((name.to_string(), sequence));
+    }
+    return Ok(entries);
+}
+fn format_java_number(abs: f64, plain: String, scientific: String) -> String {
+    if 1.0e-3 <= abs && abs < 1.0e7 {
+        if plain.contains('.') {
+            return plain;
+        }
+        return format!("{}.0", plain);
+    }
+    let (mantissa, exponent) = match scientific.find('e') {
+        Some(idx) => (&scientific[..idx], &scientific[(idx + 1)..]),
+        None => (&scientific[..], "0"),
+    };
+    if mantissa.contains('.') {
+        return format!("{}E{}", mantissa, exponent);
+    }
+    return format!("{}.0E{}", mantissa, exponent);
+}
+/// Formats a value like Java's `Double.toString`, which is what FastQC
+/// writes into fastqc_data.txt.
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    } else if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    } else if value == 0.0 {
+        return if value.is_sign_negative() { "-0.0" } else { "0.0" }.to_string();
+    }
+    return format_java_number(
+        value.abs(),
+        format!("{}", value),
+        format!("{:e}", value),
+    );
+}
+/// Formats a value like Java's `Float.toString`.
+pub fn format_float(value: f32) -> String {
+    if !value.is_finite() || value == 0.0 {
+        return format_double(value as f64);
+    }
+    return format_java_number(
+        value.abs() as f64,
+        format!("{}", value),
+        format!("{:e}", value),
+    );
+}
+
 pub fn revcomp(dna: &str) -> String {
     let mut rdna: String = String::with_capacity(dna.len());
     for c in dna.chars().rev() {
@@ -56,16 +203,21 @@ impl<R: Read> LineReader<R> {
         };
     }
     fn read(&mut self) -> Result<usize> {
-        for i in 0..self.len {
-            self.buff[i] = self.buff[self.pos + i];
-        }
+        self.buff.copy_within(self.pos..(self.pos + self.len), 0);
         self.pos = 0;
+        // The lines do not fit, as with long reads: make room for more.
+        if self.len == self.buff.len() {
+            let size = self.buff.len() * 2;
+            self.buff.resize(size, 0);
+        }
         let len = self.read.read(&mut self.buff[self.len..])?;
         self.len = self.len + len;
         return Ok(len);
     }
+    /// Returns the next line with its line end, if it has one: the last
+    /// line of a file need not end with a newline.
     pub fn read_line(&mut self) -> Result<Option<&[u8]>> {
-        let mut pos = 0;
+        let mut line_len = 0;
         for idx in 0.. {
             if self.len <= idx {
                 let rslt = self.read()?;
@@ -73,48 +225,74 @@ impl<R: Read> LineReader<R> {
                     if idx == 0 {
                         return Ok(None);
                     } else {
-                        pos = idx;
+                        line_len = idx;
                         break;
                     }
                 }
             }
             if self.buff[self.pos + idx] == '\n' as u8 {
-                pos = idx;
+                line_len = idx + 1;
                 break;
             }
         }
-        let s = &self.buff[self.pos..(self.pos + pos + 1)];
-        self.pos = self.pos + pos + 1;
-        self.len = self.len - pos - 1;
+        let s = &self.buff[self.pos..(self.pos + line_len)];
+        self.pos = self.pos + line_len;
+        self.len = self.len - line_len;
         return Ok(Some(s));
     }
+    /// Skips the blank lines at the reader position, as found between
+    /// records or at the end of a file, returning the length of each: 1
+    /// for "\n" and 2 for "\r\n".
+    pub fn skip_blank_lines(&mut self) -> Result<Vec<usize>> {
+        let mut blank_lines = Vec::new();
+        loop {
+            while self.len < 2 {
+                if self.read()? == 0 {
+                    break;
+                }
+            }
+            let line = &self.buff[self.pos..(self.pos + self.len)];
+            let line_len = if line.starts_with(b"\n") {
+                1
+            } else if line.starts_with(b"\r\n") {
+                2
+            } else {
+                return Ok(blank_lines);
+            };
+            self.pos += line_len;
+            self.len -= line_len;
+            blank_lines.push(line_len);
+        }
+    }
+    /// Returns up to `lsize` lines, fewer only at the end of the file.
     pub fn read_lines(&mut self, lsize: usize) -> Result<Vec<&[u8]>> {
-        let mut lines = Vec::with_capacity(lsize);
+        // The end of each line, past its newline.
+        let mut line_ends = Vec::with_capacity(lsize);
+        let mut line_start = 0;
         for idx in 0.. {
             if self.len <= idx {
                 let rslt = self.read()?;
                 if 0 == rslt {
-                    if idx == 0 {
-                        return Ok(Vec::new());
-                    } else {
-                        lines.push(idx);
-                        break;
+                    if line_start < idx {
+                        line_ends.push(idx);
                     }
+                    break;
                 }
             }
             if self.buff[self.pos + idx] == '\n' as u8 {
-                lines.push(idx);
-                if lsize <= lines.len() {
+                line_ends.push(idx + 1);
+                line_start = idx + 1;
+                if lsize <= line_ends.len() {
                     break;
                 }
             }
         }
         let mut lv = Vec::with_capacity(lsize);
         let mut pos = 0;
-        for l in &lines {
-            let s = &self.buff[(self.pos + pos)..(self.pos + l + 1)];
+        for end in &line_ends {
+            let s = &self.buff[(self.pos + pos)..(self.pos + end)];
             lv.push(s);
-            pos = l + 1;
+            pos = *end;
         }
         self.pos = self.pos + pos;
         self.len = self.len - pos;
@@ -125,46 +303,356 @@ pub struct Sequence<'a> {
     pub id: &'a [u8],
     pub sequence: &'a [u8],
     pub quality: &'a [u8],
+    /// Flagged as poor quality by CASAVA, so left out of most modules.
+    pub filtered: bool,
+    /// Unmapped with --mapped_only: left out of the modules like a filtered
+    /// read, but not counted as poor quality.
+    pub excluded: bool,
+    /// Set for reads from SAM and BAM files.
+    pub mapping: Option<Mapping>,
+}
+/// The alignment of a read from a SAM or BAM file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mapping {
+    pub flag: u16,
+    pub mapq: u8,
+}
+pub const FLAG_UNMAPPED: u16 = 0x4;
+pub const FLAG_REVERSE: u16 = 0x10;
+impl Mapping {
+    pub fn is_unmapped(&self) -> bool {
+        return self.flag & FLAG_UNMAPPED != 0;
+    }
+    pub fn is_reverse(&self) -> bool {
+        return self.flag & FLAG_REVERSE != 0;
+    }
+}
+/// An owned copy of a `Sequence`, used to hand reads to other threads.
+pub struct SequenceRecord {
+    pub id: Vec<u8>,
+    pub sequence: Vec<u8>,
+    pub quality: Vec<u8>,
+    pub filtered: bool,
+    pub excluded: bool,
+    pub mapping: Option<Mapping>,
+}
+/// A source of reads, whatever the file format.
+pub trait SequenceReader {
+    fn next_seq(&mut self) -> Result<Option<Sequence>>;
+}
+impl<R: SequenceReader + ?Sized> SequenceReader for Box<R> {
+    fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        return (**self).next_seq();
+    }
+}
+/// Returns whether a CASAVA 1.8 read id, such as
+/// `@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG`, has the
+/// is-filtered field set to Y.
+pub fn is_casava_filtered(id: &[u8]) -> bool {
+    let comment = match id.iter().position(|c| *c == ' ' as u8) {
+        Some(pos) => &id[(pos + 1)..],
+        None => return false,
+    };
+    return comment.split(|c| *c == ':' as u8).nth(1) == Some(&b"Y"[..]);
+}
+impl SequenceRecord {
+    pub fn from_sequence(seq: &Sequence) -> SequenceRecord {
+        return SequenceRecord {
+            id: seq.id.to_vec(),
+            sequence: seq.sequence.to_vec(),
+            quality: seq.quality.to_vec(),
+            filtered: seq.filtered,
+            excluded: seq.excluded,
+            mapping: seq.mapping,
+        };
+    }
+    pub fn as_sequence(&self) -> Sequence {
+        return Sequence {
+            id: &self.id,
+            sequence: &self.sequence,
+            quality: &self.quality,
+            filtered: self.filtered,
+            excluded: self.excluded,
+            mapping: self.mapping,
+        };
+    }
+}
+/// Strips the `\n` or `\r\n` line end, if any.
+pub fn trim_line_end(line: &[u8]) -> &[u8] {
+    let mut len = line.len();
+    if len > 0 && line[len - 1] == '\n' as u8 {
+        len -= 1;
+        if len > 0 && line[len - 1] == '\r' as u8 {
+            len -= 1;
+        }
+    }
+    return &line[..len];
 }
+#[derive(Debug, Clone, PartialEq)]
+pub enum FastQErrorKind {
+    /// The header line does not start with `@`.
+    MissingHeader,
+    /// The third line does not start with `+`.
+    MissingSeparator,
+    SequenceQualityMismatch { sequence: usize, quality: usize },
+    /// The file ends after the given number of lines of the record.
+    Truncated { lines: usize },
+}
+/// A malformed FASTQ record: its 1-based number and the byte offset of
+/// its first line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FastQError {
+    pub kind: FastQErrorKind,
+    pub record: u64,
+    pub offset: u64,
+}
+impl fmt::Display for FastQError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {} at byte {}: ", self.record, self.offset)?;
+        return match self.kind {
+            FastQErrorKind::MissingHeader => write!(f, "the header line does not start with '@'"),
+            FastQErrorKind::MissingSeparator => write!(f, "the third line does not start with '+'"),
+            FastQErrorKind::SequenceQualityMismatch { sequence, quality } => write!(
+                f,
+                "the sequence has {} bases but the quality has {} characters",
+                sequence, quality
+            ),
+            FastQErrorKind::Truncated { lines } => {
+                write!(f, "the file ends after {} of the 4 lines of the record", lines)
+            }
+        };
+    }
+}
+impl error::Error for FastQError {}
+impl From<FastQError> for Error {
+    fn from(err: FastQError) -> Error {
+        return Error::new(ErrorKind::InvalidData, err);
+    }
+}
+/// Checks the lines of a record, line ends included.
+pub fn check_fastq_record(lines: &[&[u8]]) -> Option<FastQErrorKind> {
+    if lines.len() < 4 {
+        return Some(FastQErrorKind::Truncated { lines: lines.len() });
+    }
+    if lines[0].first() != Some(&('@' as u8)) {
+        return Some(FastQErrorKind::MissingHeader);
+    }
+    if lines[2].first() != Some(&('+' as u8)) {
+        return Some(FastQErrorKind::MissingSeparator);
+    }
+    let sequence = trim_line_end(lines[1]).len();
+    let quality = trim_line_end(lines[3]).len();
+    if sequence != quality {
+        return Some(FastQErrorKind::SequenceQualityMismatch {
+            sequence: sequence,
+            quality: quality,
+        });
+    }
+    return None;
+}
+/// Reads FASTQ records, failing with a `FastQError` on the first
+/// malformed one. Blank lines between records are skipped.
 pub struct FastQReader<T: Read> {
     reader: LineReader<T>,
+    casava_filter: bool,
+    record_count: u64,
+    offset: u64,
 }
 impl<'a, T: Read> FastQReader<T> {
     pub fn new(read: T) -> FastQReader<T> {
         return FastQReader {
             reader: LineReader::new(read, 4096),
+            casava_filter: false,
+            record_count: 0,
+            offset: 0,
         };
     }
+    /// Marks the reads CASAVA flagged as poor quality as filtered.
+    pub fn set_casava_filter(&mut self, casava_filter: bool) {
+        self.casava_filter = casava_filter;
+    }
+    /// Returns the next record, skipping blank lines before it.
     pub fn next_seq(&mut self) -> Result<Option<Sequence>> {
-        let rslt = self.reader.read_lines(4);
-        match rslt {
-            Ok(lines) => {
-                if lines.is_empty() {
-                    return Ok(None);
+        self.offset += self.reader.skip_blank_lines()?.iter().sum::<usize>() as u64;
+        let lines = self.reader.read_lines(4)?;
+        if lines.is_empty() {
+            return Ok(None);
+        }
+        let offset = self.offset;
+        self.record_count += 1;
+        self.offset += lines.iter().map(|l| l.len() as u64).sum::<u64>();
+        if let Some(kind) = check_fastq_record(&lines) {
+            return Err(Error::from(FastQError {
+                kind: kind,
+                record: self.record_count,
+                offset: offset,
+            }));
+        }
+        let id = trim_line_end(lines[0]);
+        return Ok(Some(Sequence {
+            id: id,
+            sequence: trim_line_end(lines[1]),
+            quality: trim_line_end(lines[3]),
+            filtered: self.casava_filter && is_casava_filtered(id),
+            excluded: false,
+            mapping: None,
+        }));
+    }
+}
+impl<T: Read> SequenceReader for FastQReader<T> {
+    fn next_seq(&mut self) -> Result<Option<Sequence>> {
+        return FastQReader::next_seq(self);
+    }
+}
+/// Reads the two mates of each pair in lockstep, either from an R1 and an
+/// R2 file or from one interleaved file. Running out of reads in one place
+/// before the other is an error, and so are mates whose read names differ
+/// unless mismatches are allowed.
+pub struct PairReader<R: SequenceReader> {
+    r1: R,
+    r2: Option<R>,
+    names: (String, String),
+    mate: SequenceRecord,
+    pair_count: u64,
+    allow_mismatches: bool,
+}
+/// Fails unless both mates carry the same read name.
+fn check_pair_names(
+    names: &(String, String),
+    pair: u64,
+    r1: &Sequence,
+    r2: &Sequence,
+) -> Result<()> {
+    if get_pair_name(r1.id) == get_pair_name(r2.id) {
+        return Ok(());
+    }
+    return Err(Error::new(
+        ErrorKind::InvalidData,
+        format!(
+            "pair {} does not match: {} in {} but {} in {}",
+            pair,
+            String::from_utf8_lossy(r1.id),
+            names.0,
+            String::from_utf8_lossy(r2.id),
+            names.1
+        ),
+    ));
+}
+impl<R: SequenceReader> PairReader<R> {
+    pub fn new(r1: R, r1_name: &str, r2: R, r2_name: &str) -> PairReader<R> {
+        return PairReader {
+            r1: r1,
+            r2: Some(r2),
+            names: (r1_name.to_string(), r2_name.to_string()),
+            mate: SequenceRecord {
+                id: Vec::new(),
+                sequence: Vec::new(),
+                quality: Vec::new(),
+                filtered: false,
+                excluded: false,
+                mapping: None,
+            },
+            pair_count: 0,
+            allow_mismatches: false,
+        };
+    }
+    pub fn new_interleaved(reader: R, name: &str) -> PairReader<R> {
+        return PairReader {
+            r1: reader,
+            r2: None,
+            names: (name.to_string(), name.to_string()),
+            mate: SequenceRecord {
+                id: Vec::new(),
+                sequence: Vec::new(),
+                quality: Vec::new(),
+                filtered: false,
+                excluded: false,
+                mapping: None,
+            },
+            pair_count: 0,
+            allow_mismatches: false,
+        };
+    }
+    /// Lets mates with different read names through, for the pair name
+    /// module to count.
+    pub fn set_allow_mismatches(&mut self, allow_mismatches: bool) {
+        self.allow_mismatches = allow_mismatches;
+    }
+    pub fn next_pair(&mut self) -> Result<Option<(Sequence, Sequence)>> {
+        let pair_count = self.pair_count;
+        match self.r2 {
+            Some(ref mut r2) => {
+                let seq1 = self.r1.next_seq()?;
+                let seq2 = r2.next_seq()?;
+                match (seq1, seq2) {
+                    (Some(s1), Some(s2)) => {
+                        if !self.allow_mismatches {
+                            check_pair_names(&self.names, pair_count + 1, &s1, &s2)?;
+                        }
+                        self.pair_count += 1;
+                        return Ok(Some((s1, s2)));
+                    }
+                    (None, None) => return Ok(None),
+                    (Some(_), None) => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "{} ended after {} reads but {} has more reads",
+                                self.names.1, pair_count, self.names.0
+                            ),
+                        ));
+                    }
+                    (None, Some(_)) => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "{} ended after {} reads but {} has more reads",
+                                self.names.0, pair_count, self.names.1
+                            ),
+                        ));
+                    }
+                }
+            }
+            None => {
+                match self.r1.next_seq()? {
+                    Some(s1) => self.mate = SequenceRecord::from_sequence(&s1),
+                    None => return Ok(None),
+                }
+                match self.r1.next_seq()? {
+                    Some(s2) => {
+                        let s1 = self.mate.as_sequence();
+                        if !self.allow_mismatches {
+                            check_pair_names(&self.names, pair_count + 1, &s1, &s2)?;
+                        }
+                        self.pair_count += 1;
+                        return Ok(Some((s1, s2)));
+                    }
+                    None => {
+                        return Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!(
+                                "{} has an odd number of reads: read {} has no mate",
+                                self.names.0,
+                                pair_count * 2 + 1
+                            ),
+                        ));
+                    }
                 }
-                let id = lines[0];
-                let seq = lines[1];
-                let qual = lines[3];
-                let trim = if lines[0][id.len() - 2] == '\r' as u8 {
-                    2
-                } else {
-                    1
-                };
-                let len = seq.len() - trim;
-                return Ok(Some(Sequence {
-                    id: &id[0..id.len() - trim],
-                    sequence: &seq[0..len],
-                    quality: &qual[0..len],
-                }));
             }
-            Err(e) => return Err(e),
         }
     }
 }
 #[cfg(test)]
 mod tests {
+    use super::format_double;
+    use super::format_float;
+    use super::get_sample_name;
+    use super::is_casava_filtered;
     use super::split_by_space;
+    use super::FastQReader;
+    use super::FileFormat;
     use super::LineReader;
+    use super::PairReader;
     #[test]
     fn test_split_by_space() {
         assert_eq!(
@@ -174,6 +662,71 @@ mod tests {
         assert_eq!(vec!["test1", "test2"], split_by_space(" test1 test2  "));
     }
     #[test]
+    fn test_file_format() {
+        assert_eq!(FileFormat::Bam, FileFormat::from_path("dir/sample.bam"));
+        assert_eq!(FileFormat::Sam, FileFormat::from_path("sample.SAM.gz"));
+        assert_eq!(FileFormat::Fastq, FileFormat::from_path("sample.fq.gz"));
+        assert_eq!(FileFormat::Fasta, FileFormat::from_path("contigs.fa.gz"));
+        assert_eq!(FileFormat::Fastq, FileFormat::from_path("bam/sample.txt"));
+    }
+    #[test]
+    fn test_format_double() {
+        assert_eq!("29.0", format_double(29.0));
+        assert_eq!("27.928", format_double(27.928));
+        assert_eq!("-3.5", format_double(-3.5));
+        assert_eq!("0.0", format_double(0.0));
+        assert_eq!("0.001", format_double(0.001));
+        assert_eq!("1.0E-4", format_double(0.0001));
+        assert_eq!("2.5E-12", format_double(2.5e-12));
+        assert_eq!("1234567.0", format_double(1234567.0));
+        assert_eq!("1.0E7", format_double(1.0e7));
+        assert_eq!("NaN", format_double(std::f64::NAN));
+        assert_eq!("7.25", format_float(7.25));
+        assert_eq!("0.1", format_float(0.1));
+    }
+    #[test]
+    fn test_casava_filter() {
+        assert!(is_casava_filtered(
+            b"@EAS139:136:FC706VJ:2:2104:15343:197393 1:Y:18:ATCACG"
+        ));
+        assert!(!is_casava_filtered(
+            b"@EAS139:136:FC706VJ:2:2104:15343:197393 1:N:18:ATCACG"
+        ));
+        assert!(!is_casava_filtered(b"@read:Y:1"));
+        let fastq = b"@r1 1:Y:0:ATCACG\nACGT\n+\nIIII\n@r2 1:N:0:ATCACG\nACGT\n+\nIIII\n";
+        let mut reader = FastQReader::new(&fastq[..]);
+        assert!(!reader.next_seq().unwrap().unwrap().filtered);
+        let mut reader = FastQReader::new(&fastq[..]);
+        reader.set_casava_filter(true);
+        assert!(reader.next_seq().unwrap().unwrap().filtered);
+        assert!(!reader.next_seq().unwrap().unwrap().filtered);
+    }
+    #[test]
+    fn test_blank_lines() {
+        let fastq = b"@r1\nACGT\n+\nIIII\n\n";
+        let mut reader = FastQReader::new(&fastq[..]);
+        assert_eq!(b"@r1", reader.next_seq().unwrap().unwrap().id);
+        assert!(reader.next_seq().unwrap().is_none());
+
+        let fastq = b"\n@r1\nACGT\n+\nIIII\n\n\r\n@r2\nACGT\n+\nIII\n";
+        let mut reader = FastQReader::new(&fastq[..]);
+        assert_eq!(b"@r1", reader.next_seq().unwrap().unwrap().id);
+        let err = reader.next_seq().err().unwrap();
+        assert_eq!(
+            "record 2 at byte 20: the sequence has 4 bases but the quality has 3 characters",
+            err.get_ref().unwrap().to_string()
+        );
+    }
+    #[test]
+    fn test_get_sample_name() {
+        assert_eq!("test", get_sample_name("test.fastq"));
+        assert_eq!("lane1_R1", get_sample_name("/data/run/lane1_R1.fastq.gz"));
+        assert_eq!("sample.fq", get_sample_name("sample.fq.fq"));
+        assert_eq!("reads", get_sample_name("dir/reads.bam"));
+        assert_eq!("reads.fa", get_sample_name("reads.fa"));
+        assert_eq!("stdin", get_sample_name("-"));
+    }
+    #[test]
     fn test_split_by_newline() {
         let cur = std::io::Cursor::new(b"test1\ntest2\ntest3\n");
         let mut reader = LineReader::new(cur, 1024);
@@ -191,4 +744,53 @@ mod tests {
         );
         assert_eq!(1, 1);
     }
+    #[test]
+    fn test_lines_longer_than_buffer() {
+        let cur = std::io::Cursor::new(b"@r1\nACGTACGTACGTACGTACGT\n+\nIIIIIIIIIIIIIIIIIIII\n");
+        let mut reader = LineReader::new(cur, 8);
+        let lines = reader.read_lines(4).unwrap();
+        assert_eq!(4, lines.len());
+        assert_eq!(b"ACGTACGTACGTACGTACGT\n", lines[1]);
+        assert_eq!(b"IIIIIIIIIIIIIIIIIIII\n", lines[3]);
+    }
+    #[test]
+    fn test_pair_reader() {
+        let r1: &[u8] = b"@a/1\nACGT\n+\nIIII\n@b/1\nACGT\n+\nIIII\n";
+        let r2: &[u8] = b"@a/2\nTTTT\n+\nIIII\n";
+        let mut reader = PairReader::new(FastQReader::new(r1), "r1.fq", FastQReader::new(r2), "r2.fq");
+        {
+            let (s1, s2) = reader.next_pair().unwrap().unwrap();
+            assert_eq!(b"@a/1", s1.id);
+            assert_eq!(b"TTTT", s2.sequence);
+        }
+        assert!(reader.next_pair().is_err());
+
+        let r: &[u8] = b"@a/1\nACGT\n+\nIIII\n@a/2\nTTTT\n+\nIIII\n";
+        let mut reader = PairReader::new_interleaved(FastQReader::new(r), "r.fq");
+        {
+            let (s1, s2) = reader.next_pair().unwrap().unwrap();
+            assert_eq!(b"@a/1", s1.id);
+            assert_eq!(b"@a/2", s2.id);
+        }
+        assert!(reader.next_pair().unwrap().is_none());
+
+        let mut reader = PairReader::new_interleaved(FastQReader::new(r1), "r.fq");
+        assert!(reader.next_pair().is_err());
+    }
+    #[test]
+    fn test_pair_reader_mismatch() {
+        let r1: &[u8] = b"@a/1\nACGT\n+\nIIII\n@b/1\nACGT\n+\nIIII\n";
+        let r2: &[u8] = b"@a/2\nTTTT\n+\nIIII\n@c/2\nTTTT\n+\nIIII\n";
+        let mut reader = PairReader::new(FastQReader::new(r1), "r1.fq", FastQReader::new(r2), "r2.fq");
+        assert!(reader.next_pair().unwrap().is_some());
+        let err = reader.next_pair().err().unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+        assert_eq!("pair 2 does not match: @b/1 in r1.fq but @c/2 in r2.fq", err.to_string());
+
+        let mut reader = PairReader::new(FastQReader::new(r1), "r1.fq", FastQReader::new(r2), "r2.fq");
+        reader.set_allow_mismatches(true);
+        assert!(reader.next_pair().unwrap().is_some());
+        let (s1, s2) = reader.next_pair().unwrap().unwrap();
+        assert_eq!((&b"@b/1"[..], &b"@c/2"[..]), (s1.id, s2.id));
+    }
 }
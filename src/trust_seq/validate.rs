@@ -0,0 +1,130 @@
+use super::utils::{check_fastq_record, FastQError, FastQErrorKind, LineReader};
+use std::collections::VecDeque;
+use std::io::Read;
+use std::io::Result;
+
+/// Whether `lines` may be the start of a record: a header line and, if
+/// the file goes on that far, a separator line.
+fn is_record_start(lines: &VecDeque<(u64, Vec<u8>)>) -> bool {
+    return lines[0].1.first() == Some(&('@' as u8))
+        && (lines.len() < 3 || lines[2].1.first() == Some(&('+' as u8)));
+}
+
+/// Checks every record of a FASTQ file, returning the number of records
+/// and the problems found. A bad header or separator line puts the lines
+/// out of step with the records, so the check goes on from the next line
+/// that looks like the start of a record. Blank lines between records are
+/// skipped.
+pub fn validate_fastq<R: Read>(read: R) -> Result<(u64, Vec<FastQError>)> {
+    let mut reader = LineReader::new(read, 4096);
+    // The lines read ahead, with their byte offsets.
+    let mut lines: VecDeque<(u64, Vec<u8>)> = VecDeque::new();
+    let mut offset = 0u64;
+    let mut record_count = 0u64;
+    let mut errors = Vec::new();
+    loop {
+        if lines.is_empty() {
+            offset += reader.skip_blank_lines()?.iter().sum::<usize>() as u64;
+        }
+        while lines.len() < 4 {
+            match reader.read_line()? {
+                Some(line) => {
+                    lines.push_back((offset, line.to_vec()));
+                    offset += line.len() as u64;
+                }
+                None => break,
+            }
+        }
+        if lines.is_empty() {
+            break;
+        }
+        record_count += 1;
+        let record: Vec<&[u8]> = lines.iter().map(|l| &l.1[..]).collect();
+        let kind = check_fastq_record(&record);
+        let resync = match kind {
+            Some(FastQErrorKind::MissingHeader) | Some(FastQErrorKind::MissingSeparator) => true,
+            _ => false,
+        };
+        if let Some(kind) = kind {
+            errors.push(FastQError {
+                kind: kind,
+                record: record_count,
+                offset: lines[0].0,
+            });
+        }
+        if !resync {
+            lines.clear();
+            continue;
+        }
+        lines.pop_front();
+        loop {
+            while lines.len() < 4 {
+                match reader.read_line()? {
+                    Some(line) => {
+                        lines.push_back((offset, line.to_vec()));
+                        offset += line.len() as u64;
+                    }
+                    None => break,
+                }
+            }
+            if lines.is_empty() || is_record_start(&lines) {
+                break;
+            }
+            lines.pop_front();
+        }
+    }
+    return Ok((record_count, errors));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_fastq;
+    use crate::trust_seq::utils::{FastQErrorKind, FastQReader};
+
+    #[test]
+    fn test_validate_fastq() {
+        let fastq: &[u8] = b"@r1\nACGT\n+\nIIII\n\
+@r2\nACGT\n+\nIII\n\
+r3\nACGT\n+\nIIII\n\
+@r4\nACGT\n+\nIIII\n\
+@r5\nAC\nGT\n+\nIIII\n\
+@r6\r\nACGT\r\n+\r\nIIII\r\n\
+@r7\nACGT\n";
+        let (record_count, errors) = validate_fastq(fastq).unwrap();
+        let found: Vec<(FastQErrorKind, u64, u64)> = errors
+            .into_iter()
+            .map(|e| (e.kind, e.record, e.offset))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    FastQErrorKind::SequenceQualityMismatch {
+                        sequence: 4,
+                        quality: 3
+                    },
+                    2,
+                    16
+                ),
+                (FastQErrorKind::MissingHeader, 3, 31),
+                (FastQErrorKind::MissingSeparator, 5, 62),
+                (FastQErrorKind::Truncated { lines: 2 }, 7, 99),
+            ],
+            found
+        );
+        // The checks go on at r4 and r6, the next records after a bad one.
+        assert_eq!(7, record_count);
+
+        let mut reader = FastQReader::new(fastq);
+        assert!(reader.next_seq().is_ok());
+        let err = reader.next_seq().err().unwrap();
+        assert_eq!(
+            "record 2 at byte 16: the sequence has 4 bases but the quality has 3 characters",
+            err.get_ref().unwrap().to_string()
+        );
+
+        // Blank lines between records and at the end are skipped.
+        let fastq: &[u8] = b"@r1\nACGT\n+\nIIII\n\n\r\n@r2\nACGT\n+\nIIII\n\n";
+        let (record_count, errors) = validate_fastq(fastq).unwrap();
+        assert_eq!((2, 0), (record_count, errors.len()));
+    }
+}